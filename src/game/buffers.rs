@@ -32,7 +32,7 @@ impl<T: Default + Copy> CircularBuffer<T> {
         }
     }
 
-    pub fn get_both(&mut self, tick: u16) -> (&T, u16) {
+    pub fn get_both(&self, tick: u16) -> (&T, u16) {
         let i = tick as usize % BUFFER_LEN;
         (&self.0[i], self.1[i])
     }
@@ -4,10 +4,13 @@ use bevy::window::PrimaryWindow;
 use crate::AppState;
 use crate::movement;
 use crate::game::camp::setup_camps;
-use crate::game::components::{Camp, CampStatus, Grade, Health, Player};
-use crate::game::{player, player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_HP, MAX_PLAYERS}, PlayerId};
+use crate::game::components::{Camp, CampStatus, Enemy, Grade, Health, Player};
+use crate::game::{player, player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent}};
 use crate::game::buffers::EventBuffer;
-use crate::game::player::SpawnEvent;
+use crate::game::events::{self, Announcement};
+use crate::game::player::{Respawn, SpawnEvent, SpawnRules};
+use crate::game::team::Team;
+use crate::game::hill::{Hill, NO_HILL};
 use crate::map;
 use crate::net::{IsHost, TickNum};
 
@@ -30,6 +33,8 @@ const CAMP_MARKER_COLORS: [Color; 5] = [
 ];
 
 const ENEMY_PLAYER_COLOR: Color = Color::Rgba {red: 1., green: 0.2, blue: 0.2, alpha: 1.};
+const HILL_MARKER_COLOR: Color = Color::Rgba {red: 1., green: 1., blue: 1., alpha: 1.};
+const HILL_MARKER_SCALE: f32 = 2.;
 
 #[derive(Component)]
 pub struct GameCamera;
@@ -41,7 +46,7 @@ pub struct LocalPlayerMarker;
 pub struct EnemyPlayerMarker(pub u8);
 
 #[derive(Component)]
-pub struct CampMarker(pub u8);
+pub struct CampMarker(pub u16);
 
 #[derive(Component)]
 pub struct Minimap;
@@ -67,6 +72,7 @@ impl Plugin for CameraPlugin {
             .add_systems(Update, configure_map_on_event)
             .add_systems(Update, spawn_camp_markers.run_if(any_with_component::<Camp>()))
             .add_systems(Update, hide_cleared_camp_markers.run_if(any_with_component::<CampMarker>()))
+            .add_systems(Update, highlight_hill_marker.run_if(any_with_component::<CampMarker>()))
             .add_systems(Update, spawn_enemy_player_markers.run_if(any_with_component::<LocalPlayer>()))
             .add_systems(Update, show_enemy_player_markers.run_if(player::local_player_dead))
             .add_systems(Update, hide_enemy_player_markers.run_if(not(player::local_player_dead)))
@@ -191,44 +197,45 @@ fn spawn_camp_markers(
     }
 }
 
+/// one marker for every other player in the game, added and removed as they come and go
 fn spawn_enemy_player_markers(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut minimap: Query<Entity, With<Minimap>>,
-    enemy_player_markers: Query<Entity, With<EnemyPlayerMarker>>,
-    local_player_id: Res<PlayerId>
+    minimap: Query<Entity, With<Minimap>>,
+    enemy_player_markers: Query<(Entity, &EnemyPlayerMarker)>,
+    players: Query<&Player, Without<LocalPlayer>>,
 ) {
-    // Return immediately if the enemy player markers already exist
-    for _marker in &enemy_player_markers {
-        return;
+    for (marker, marker_id) in &enemy_player_markers {
+        if !players.iter().any(|pl| pl.0 == marker_id.0) {
+            commands.entity(marker).despawn_recursive();
+        }
     }
 
-    for parent in &mut minimap {
-        for player_id in 0..MAX_PLAYERS {
-            if player_id as u8 != local_player_id.0 {
-                let enemy_player_marker_ent = commands.spawn((
-                    SpriteBundle {
-                        texture: asset_server.load("player_marker.png"),
-                        transform: Transform {
-                            translation: Vec3 {
-                                x: 0.,
-                                y: 0.,
-                                z: 2.
-                            },
-                            ..Default::default()
-                        },
-                        sprite: Sprite {
-                            color: ENEMY_PLAYER_COLOR,
-                            ..Default::default()
+    for parent in &minimap {
+        for player in &players {
+            if enemy_player_markers.iter().any(|(_, marker_id)| marker_id.0 == player.0) { continue }
+            let enemy_player_marker_ent = commands.spawn((
+                SpriteBundle {
+                    texture: asset_server.load("player_marker.png"),
+                    transform: Transform {
+                        translation: Vec3 {
+                            x: 0.,
+                            y: 0.,
+                            z: 2.
                         },
-                        visibility: Visibility::Hidden,
                         ..Default::default()
                     },
-                    EnemyPlayerMarker(player_id as u8),
-                )).id();
-        
-                commands.entity(parent).add_child(enemy_player_marker_ent);
-            }
+                    sprite: Sprite {
+                        color: ENEMY_PLAYER_COLOR,
+                        ..Default::default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                EnemyPlayerMarker(player.0),
+            )).id();
+
+            commands.entity(parent).add_child(enemy_player_marker_ent);
         }
     }
 }
@@ -237,13 +244,15 @@ fn spawn_enemy_player_markers(
 fn hide_cleared_camp_markers(
     mut camp_markers: Query<(&CampMarker, &mut Visibility), With<CampMarker>>,
     camps: Query<(&Camp, &CampStatus), With<Camp>>,
+    hill: Res<Hill>,
     input: Res<Input<KeyCode>>,
     app_state_current_state: Res<State<AppState>>,
 ) {
     for (marker_num, mut marker_visibility) in &mut camp_markers {
         for (camp_num, camp_status) in &camps {
             if camp_num.0 == marker_num.0 {
-                if !camp_status.0 || input.pressed(KeyCode::Tab) ||
+                // the hill stays on the map even once it's been cleared
+                if (!camp_status.0 && camp_num.0 != hill.camp) || input.pressed(KeyCode::Tab) ||
                     *app_state_current_state.get() == AppState::GameOver {
                     *marker_visibility = Visibility::Hidden;
                 }
@@ -255,14 +264,15 @@ fn hide_cleared_camp_markers(
     }
 }
 
+/// everyone else is red, unless there are teams and then they're their team's colour
 fn show_enemy_player_markers(
-    mut enemy_player_markers: Query<(&EnemyPlayerMarker, &mut Visibility, &mut Transform), With<EnemyPlayerMarker>>,
-    players: Query<(&Player, &Transform, &Health), (With<Player>, Without<LocalPlayer>, Without<EnemyPlayerMarker>)>,
+    mut enemy_player_markers: Query<(&EnemyPlayerMarker, &mut Visibility, &mut Transform, &mut Sprite), With<EnemyPlayerMarker>>,
+    players: Query<(&Player, &Transform, &Health, &Team), (With<Player>, Without<LocalPlayer>, Without<EnemyPlayerMarker>)>,
     input: Res<Input<KeyCode>>,
     app_state_current_state: Res<State<AppState>>,
 ) {
-    for (marker_id, mut marker_visibility, mut marker_transform) in &mut enemy_player_markers {
-        for (player_id, player_transform, player_health) in &players {
+    for (marker_id, mut marker_visibility, mut marker_transform, mut marker_sprite) in &mut enemy_player_markers {
+        for (player_id, player_transform, player_health, team) in &players {
             if input.pressed(KeyCode::Tab) ||
                     *app_state_current_state.get() == AppState::GameOver {
                     *marker_visibility = Visibility::Hidden;
@@ -270,6 +280,7 @@ fn show_enemy_player_markers(
                 else {
                     if marker_id.0 == player_id.0 && !player_health.dead {
                         *marker_visibility = Visibility::Visible;
+                        marker_sprite.color = team.color().unwrap_or(ENEMY_PLAYER_COLOR);
                         marker_transform.translation.x = make_position_not_float(player_transform.translation.x / map::TILESIZE as f32);
                         marker_transform.translation.y = make_position_not_float(player_transform.translation.y / map::TILESIZE as f32);
                     }
@@ -414,7 +425,11 @@ fn make_position_not_float(position: f32) -> f32 {
 fn spawn_update(
     mouse_button_inputs: Res<Input<MouseButton>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
-    mut local_player: Query<(&mut Transform, &mut Health, &mut EventBuffer, &mut Visibility), With<LocalPlayer>>,
+    mut local_player: Query<(&mut Transform, &mut Health, &mut EventBuffer, &mut Visibility, &Respawn), With<LocalPlayer>>,
+    others: Query<(&Transform, &Health), (With<Player>, Without<LocalPlayer>)>,
+    enemies: Query<(&Transform, &Health), (With<Enemy>, Without<LocalPlayer>)>,
+    rules: Res<SpawnRules>,
+    mut announcements: Query<(&mut Text, &mut Announcement)>,
     map: Res<map::WorldMap>,
     is_host: Res<IsHost>,
     minimap: Query<Entity, With<Minimap>>,
@@ -439,7 +454,15 @@ fn spawn_update(
             cursor_to_map.y = ((cursor_position.y as u32 - ((super::WIN_H / 2.) as u32 - MINIMAP_DIMENSIONS.y)) / 2).clamp(0, (map::MAPSIZE - 1) as u32);
             let tile = map.biome_map[cursor_to_map.y as usize][cursor_to_map.x as usize];
             if tile != map::Biome::Wall {
-                let (mut lp_tf, mut lp_hp, mut lp_eb, mut lp_vis) = local_player.single_mut();
+                let (mut lp_tf, _, mut lp_eb, _, respawn) = local_player.single_mut();
+                // the host checks these too, this just saves asking when the answer's no
+                if respawn.waiting(tick.0) > 0 { return }
+                let pos = Vec2::new((cursor_to_map.x as f32 - 128.) * 16., -(cursor_to_map.y as f32 - 128.) * 16.);
+                let living = others.iter().chain(enemies.iter()).filter(|(_, hp)| hp.current > 0).map(|(tf, _)| tf.translation.truncate());
+                if !player::spawn_clear(pos, living, rules.clearance) {
+                    events::announce(&mut announcements, "Too close to someone, pick another spot".to_string());
+                    return;
+                }
 
                 let events = lp_eb.0.get(tick.0).clone();
                 if events.is_none() {
@@ -451,10 +474,10 @@ fn spawn_update(
                 }
                 if is_host.0 {
                     lp_spawn_writer.send(LocalPlayerSpawnEvent);
-                    spawn_writer.send(SpawnEvent { id: 0 });
+                    spawn_writer.send(SpawnEvent { id: 0, pos });
                 }
-                lp_tf.translation.x = (cursor_to_map.x as f32 - 128.) * 16.;
-                lp_tf.translation.y = -(cursor_to_map.y as f32 - 128.) * 16.;
+                lp_tf.translation.x = pos.x;
+                lp_tf.translation.y = pos.y;
 
                 // Spawn local player marker if necessary
                 if local_player_marker.get_single().is_ok() { return }
@@ -523,4 +546,25 @@ fn game_update(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// the hill's camp marker is drawn bigger and white, the rest are their grade's colour
+fn highlight_hill_marker(
+    hill: Res<Hill>,
+    mut camp_markers: Query<(&CampMarker, &mut Sprite, &mut Transform)>,
+    camps: Query<(&Camp, &Grade)>,
+) {
+    for (marker_num, mut sprite, mut tf) in &mut camp_markers {
+        if hill.camp != NO_HILL && marker_num.0 == hill.camp {
+            sprite.color = HILL_MARKER_COLOR;
+            tf.scale = Vec3::splat(HILL_MARKER_SCALE);
+            continue;
+        }
+        for (camp_num, camp_grade) in &camps {
+            if camp_num.0 == marker_num.0 {
+                sprite.color = CAMP_MARKER_COLORS[(camp_grade.0 - 1) as usize];
+                tf.scale = Vec3::ONE;
+            }
+        }
+    }
+}
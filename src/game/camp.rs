@@ -13,8 +13,12 @@ use crate::game::enemy::ENEMY_MAX_HP;
 use crate::game::map::setup_map;
 use crate::map::MapSeed;
 use crate::map::ChestCoords;
-use crate::net::{is_host, TickNum};
+use crate::net::{is_host, IsHost, TickNum};
 use crate::PowerupAtlas;
+use crate::game::events::{GameEvent, GameplayEvent};
+use crate::game::powerup::{self, NextNetworkId};
+use crate::game::weapon::{self, Weapons, WEAPON_DROP_CHANCE};
+use crate::game::item::{self, CHEST_ITEMS, ITEM_DROP_CHANCE};
 
 const CAMP_ENEMIES: u8 = 5;
 const NUM_GRADES: u8 = 5;
@@ -57,8 +61,8 @@ pub fn setup_camps(
     //TODO: respawn enemies in a camp after a certain amount of time
 
     // Iterate through the MST of camps generated by perlin noise and spawn a camp at each node
-    let mut campid: u8 = 0; 
-    let mut id: u8 = 0;
+    let mut campid: u16 = 0; 
+    let mut id: u16 = 0;
     for camps in camp_nodes.0.iter(){
         // x-y position of the camp
         let camp_pos: Vec2 = get_spawn_vec(camps.x, camps.y);
@@ -114,9 +118,9 @@ pub fn setup_camps(
         for n in 0..CAMP_ENEMIES{
             let is_special = n == special_enemy_index;
             //generate a random powerup to drop from each enemy
-            let powerups: [PowerUpType; 5] = [PowerUpType::Meat, PowerUpType::DamageDealtUp, PowerUpType::DamageReductionUp, PowerUpType::AttackSpeedUp, PowerUpType::MovementSpeedUp];
             //TODO: make this a random percentage based on the mapconfig resource
-            let power_up_to_drop = powerups[camp_grade as usize - 1];
+            let grade_powerups = grade_powerups(camp_grade);
+            let power_up_to_drop = POWERUP_TYPES[rng.gen_range(grade_powerups)];
             let mut chance_drop_powerup = rng.gen_range(0..100) < POWERUP_DROP_CHANCE;
 
             if is_special{
@@ -150,6 +154,7 @@ pub fn setup_chests(
     chest_coords: Res<ChestCoords>,
     map_seed: Res<MapSeed>,
     chest_atlas: Res<Chests>,
+    weapons: Res<Weapons>,
 ){
 
     // for chests in chest_coords, commands.spawn with chest component and health
@@ -160,11 +165,23 @@ pub fn setup_chests(
         let chest_pos: Vec2 = get_spawn_vec(chest.x, chest.y);
 
         let pb = PosBuffer(CircularBuffer::new_from(Some(chest_pos)));
+        // 5 random powerups
+        let contents: [u8; CHEST_CONTENTS] = std::array::from_fn(|_| rng.gen_range(0..NUM_POWERUPS as u8));
+        // and maybe something better than the sword. Everyone rolls the same, so clients know what's in it too
+        let mut weapon = None;
+        if weapons.0.len() > 1 && rng.gen_range(0..100) < WEAPON_DROP_CHANCE {
+            weapon = Some(rng.gen_range(1..weapons.0.len()) as u8);
+        }
+        let mut item = None;
+        if rng.gen_range(0..100) < ITEM_DROP_CHANCE {
+            item = Some(CHEST_ITEMS[rng.gen_range(0..CHEST_ITEMS.len())]);
+        }
         commands.spawn((
             ItemChest{
                 id: i,
-                // 5 random powerups
-                contents: [rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5)]
+                contents,
+                weapon,
+                item,
             },
             pb,
             Health {
@@ -208,6 +225,10 @@ pub fn handle_chest_hit(
     mut chest_query: Query<(&mut Health, &mut TextureAtlasSprite, &ItemChest, &Transform), With<ItemChest>>,
     chest_atlas: Res<Chests>,
     powerup_atlas: Res<PowerupAtlas>,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Weapons>,
+    mut next_id: ResMut<NextNetworkId>,
+    is_host: Res<IsHost>,
 ){
     for (mut chest_hp, mut chest_sprite, chest, tf) in chest_query.iter_mut(){
         if chest_hp.current == 0 && !chest_hp.dead{
@@ -215,25 +236,24 @@ pub fn handle_chest_hit(
             chest_hp.dead = true;
             //change the sprite of the chest
             *chest_sprite = TextureAtlasSprite {index: chest_atlas.coord_to_index(0, 0), ..Default::default()};
-            // spawn the powerups
+            // everyone knows what weapon was in it, so everyone spawns their own
+            if let Some(w) = chest.weapon {
+                weapon::spawn_weapon_drop(&mut commands, &asset_server, &weapons, chest.id, w, tf.translation.truncate() - Vec2::new(0., CHEST_SIZE.y));
+            }
+            if let Some(it) = chest.item {
+                item::spawn_item_drop(&mut commands, chest.id, it, tf.translation.truncate() + Vec2::new(0., CHEST_SIZE.y));
+            }
+            // spawn the powerups, clients get theirs from the host
+            if !is_host.0 { continue }
 
             let mut i = 0;
             for powerups in chest.contents.iter(){
-                commands.spawn((
-                    SpriteSheetBundle{
-                        texture_atlas: powerup_atlas.handle.clone(),
-                        sprite: TextureAtlasSprite {
-                            index: powerup_atlas.coord_to_index(0, *powerups as i32),
-                            ..Default::default()
-                        },
-                        transform: Transform {
-                            translation: Vec3::new(tf.translation.x + CHEST_REWARDS[i], tf.translation.y + CHEST_REWARDS[i+1], 1.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    PowerUp(unsafe {std::mem::transmute(*powerups as u8)}),
-                ));
+                powerup::spawn_powerup(
+                    &mut commands,
+                    &powerup_atlas,
+                    next_id.next(),
+                    POWERUP_TYPES[*powerups as usize],
+                    Vec2::new(tf.translation.x + CHEST_REWARDS[i], tf.translation.y + CHEST_REWARDS[i+1]));
 
                 i+=2;
             }
@@ -241,6 +261,14 @@ pub fn handle_chest_hit(
     }
 }
 
+/// which powerup types a camp of this grade drops, out of POWERUP_TYPES. Higher grades get the later ones,
+/// and every type belongs to some grade however many of them there are
+fn grade_powerups(grade: u8) -> std::ops::Range<usize> {
+    let start = (grade as usize - 1) * NUM_POWERUPS / NUM_GRADES as usize;
+    let end = grade as usize * NUM_POWERUPS / NUM_GRADES as usize;
+    return start.min(NUM_POWERUPS - 1)..end.max(start + 1).min(NUM_POWERUPS);
+}
+
 // convert given row and col into x and y coordinates. Returns a vec2 of these coordinates
 fn get_spawn_vec(row: f32, col:f32) -> Vec2{
     let x_coord = TILESIZE as f32 * (row - (MAPSIZE as f32/2. + 0.5));
@@ -299,6 +327,7 @@ pub fn respawn_camp_enemies(
     mut enemies: Query<(&EnemyCamp, &mut Health, &mut Visibility, &mut HpBuffer), With<Enemy>>,
     tick: Res<TickNum>,
     time: Res<Time>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ){
     for (camp_id, mut enemies_in_camp, mut camp_status, 
         grade, mut respawn_timer, pos) in camp_query.iter_mut(){
@@ -310,6 +339,7 @@ pub fn respawn_camp_enemies(
         {
             respawn_timer.0.reset();
             camp_status.0 = true;
+            gameplay_writer.send(GameplayEvent(GameEvent::CampRespawned { camp: camp_id.0 }));
             enemies_in_camp.current_enemies = enemies_in_camp.max_enemies;
             for (parent_camp, mut hp, mut vis, mut hb) in enemies.iter_mut() {
                 if parent_camp.0 == camp_id.0 {
@@ -320,4 +350,20 @@ pub fn respawn_camp_enemies(
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_powerup_drops_from_some_grade() {
+        let mut next = 0;
+        for grade in 1..=NUM_GRADES {
+            let range = grade_powerups(grade);
+            assert!(!range.is_empty());
+            assert_eq!(range.start, next, "grade {} skips or repeats a type", grade);
+            next = range.end;
+        }
+        assert_eq!(next, NUM_POWERUPS);
+    }
+}
@@ -0,0 +1,164 @@
+//! Character classes, one per row of assets/classes.csv, picked on the host and join pages.
+//! A class sets the base numbers powerups build on
+use std::error::Error;
+use std::io::Read;
+use bevy::prelude::*;
+use crate::game::components::*;
+use crate::game::table;
+use crate::net::TICKLEN_S;
+
+pub const CLASSES_FILE: &str = "classes.csv";
+pub const DEFAULT_CLASS: u8 = 0;  // the first class in the file is what everyone gets if they don't pick, bots included
+
+/// One row of classes.csv
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassDef {
+    pub name: String,
+    pub hp: u8,
+    pub speed: f32,  // pixels per second
+    pub damage: f32,  // times what their weapon does
+    pub cooldown: f32,  // times how long their weapon takes between swings
+    pub shield_regen: u8,  // durability back per tick while the shield is down
+    pub dash: f32,  // seconds between dashes
+    pub bow: f32,  // seconds between shots
+}
+
+impl ClassDef {
+    /// what everyone was before there were classes, for when classes.csv can't be read
+    fn fallback() -> ClassDef {
+        return ClassDef {
+            name: "Adventurer".to_string(),
+            hp: 100,
+            speed: 250.,
+            damage: 1.,
+            cooldown: 1.,
+            shield_regen: 2,
+            dash: 2.5,
+            bow: 0.6,
+        };
+    }
+
+    /// how fast they walk with their movement speed ups
+    pub fn speed(&self, spu: &StoredPowerUps) -> f32 {
+        return self.speed + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32;
+    }
+
+    pub fn dash_ticks(&self) -> u16 {
+        return (self.dash / TICKLEN_S).round() as u16;
+    }
+}
+
+/// Every class there is, in file order. A class's id is its index
+#[derive(Resource)]
+pub struct Classes(pub Vec<ClassDef>);
+
+impl Classes {
+    /// reads classes.csv out of the assets folder, or makes do with one class that plays like the old game
+    pub fn load() -> Classes {
+        return Classes(table::load(CLASSES_FILE, read_classes, ClassDef::fallback(), "everyone gets the same class"));
+    }
+
+    /// a class someone picked from a classes.csv we don't have plays as the default one
+    pub fn get(&self, id: u8) -> &ClassDef {
+        return self.0.get(id as usize).unwrap_or(&self.0[DEFAULT_CLASS as usize]);
+    }
+
+    /// the class after this one, for flipping through them on the menus
+    pub fn next(&self, id: u8) -> u8 {
+        return ((id as usize + 1) % self.0.len()) as u8;
+    }
+}
+
+/// parses classes.csv: a header row, then name, hp, speed, damage, cooldown, shield_regen, dash, bow
+pub fn read_classes<R: Read>(reader: R) -> Result<Vec<ClassDef>, Box<dyn Error>> {
+    return table::read(reader, "classes", |row| {
+        let class = ClassDef {
+            name: row.field(0)?,
+            hp: row.field(1)?,
+            speed: row.positive(2)?,
+            damage: row.positive(3)?,
+            // the rest end up in Durations and tick counts
+            cooldown: row.positive(4)?,
+            shield_regen: row.field(5)?,
+            dash: row.positive(6)?,
+            bow: row.positive(7)?,
+        };
+        if class.hp == 0 {
+            return Err(format!("{} has no hp", class.name).into());
+        }
+        return Ok(class);
+    });
+}
+
+/// Which class a player is
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerClass(pub u8);
+
+/// The class picked on the host or join page, for our own player
+#[derive(Resource)]
+pub struct ChosenClass(pub u8);
+
+pub struct ClassPlugin;
+
+impl Plugin for ClassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, startup);
+    }
+}
+
+fn startup(mut commands: Commands) {
+    commands.insert_resource(Classes::load());
+    commands.insert_resource(ChosenClass(DEFAULT_CLASS));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_classes_csv() {
+        let csv = "name, hp, speed, damage, cooldown, shield_regen, dash, bow\n\
+            Adventurer, 100, 250, 1, 1, 2, 2.5, 0.6\n\
+            Rogue, 80, 300, 1, 0.8, 2, 1.2, 0.6\n";
+        let classes = read_classes(csv.as_bytes()).unwrap();
+        assert_eq!(classes.len(), 2);
+        assert_eq!(classes[0], ClassDef::fallback());
+        assert_eq!(classes[1].name, "Rogue");
+        assert_eq!(classes[1].dash_ticks(), 12);
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        let header = "name, hp, speed, damage, cooldown, shield_regen, dash, bow\n";
+        assert!(read_classes(header.as_bytes()).is_err());
+        assert!(read_classes(format!("{}Ghost, 0, 250, 1, 1, 2, 2.5, 0.6\n", header).as_bytes()).is_err());
+        assert!(read_classes(format!("{}Knight, 140, 220\n", header).as_bytes()).is_err());
+        // these would panic as Durations or never let anyone dash
+        assert!(read_classes(format!("{}Knight, 140, 220, 1, NaN, 4, 3, 0.8\n", header).as_bytes()).is_err());
+        assert!(read_classes(format!("{}Knight, 140, 220, 1, 1, 4, -3, 0.8\n", header).as_bytes()).is_err());
+        assert!(read_classes(format!("{}Knight, 140, 220, 1, 1, 4, 3, 0\n", header).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn the_default_class_plays_like_the_old_game() {
+        let classes = Classes::load();
+        assert_eq!(classes.get(DEFAULT_CLASS), &ClassDef::fallback());
+    }
+
+    #[test]
+    fn unknown_ids_get_the_default_and_flipping_wraps() {
+        let classes = Classes(vec![ClassDef::fallback(), ClassDef { name: "Rogue".to_string(), ..ClassDef::fallback() }]);
+        assert_eq!(classes.get(200).name, "Adventurer");
+        assert_eq!(classes.next(0), 1);
+        assert_eq!(classes.next(1), 0);
+    }
+
+    #[test]
+    fn speed_ups_add_to_the_class_speed() {
+        let mut spu = StoredPowerUps { power_ups: [0; NUM_POWERUPS] };
+        let rogue = ClassDef { speed: 300., ..ClassDef::fallback() };
+        assert_eq!(rogue.speed(&spu), 300.);
+        spu.power_ups[PowerUpType::MovementSpeedUp as usize] = 2;
+        assert_eq!(rogue.speed(&spu), 300. + 2. * MOVEMENT_SPEED_UP as f32);
+    }
+}
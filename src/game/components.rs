@@ -2,6 +2,8 @@
 // Components which are only used locally can be left inside a more localized file.
 use bevy::prelude::*;
 use core::fmt::Debug;
+use crate::game::events::Attacker;
+use crate::game::item::ItemType;
 
 #[derive(Component)]
 pub struct Health {
@@ -16,13 +18,25 @@ pub struct Fade {
     pub max: f32
 }
 
-pub const NUM_POWERUPS: usize = 5;
+pub const NUM_POWERUPS: usize = 8;
 pub const DAMAGE_DEALT_UP: u8 = 10;
 pub const DAMAGE_REDUCTION_UP: f32 = 0.9;
 pub const MEAT_VALUE: u8 = 30;
 pub const ATTACK_SPEED_UP: f32 = 1.1;
 pub const MOVEMENT_SPEED_UP: u8 = 15;
+pub const MAX_HP_UP: u8 = 20;
+pub const LIFESTEAL: u16 = 10;  // percent of sword damage dealt that comes back as health, per stack
+pub const CRIT_CHANCE: u8 = 10;  // percent, per stack
+pub const CRIT_MULTIPLIER: f32 = 2.;
 pub const CHEST_CONTENTS: usize = 5;
+pub const STAGGER_TICKS: u16 = 10;  // how long a parried attacker is left open
+pub const HITSTUN_TICKS: u16 = 3;  // how long a hit leaves someone unable to move or attack
+pub const KNOCKBACK_FALLOFF: f32 = 0.5;  // fraction of a knockback still left to slide after each tick
+pub const KNOCKBACK_UP: f32 = 1.15;  // per stack of damage dealt up
+pub const KILL_SCORE: u16 = 20;
+pub const ASSIST_SCORE: u16 = 5;
+pub const ASSIST_TICKS: u16 = 100;  // how long a hit still counts toward a kill
+pub const ASSIST_DAMAGE: u16 = 10;  // how much someone has to have done in that time to get an assist
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum PowerUpType {
@@ -31,12 +45,62 @@ pub enum PowerUpType {
     DamageReductionUp,
     AttackSpeedUp,
     MovementSpeedUp,
+    MaxHPUp,
+    Lifesteal,
+    CritChance,
 }
 
+/// every type in sheet order, so the sprites, HUD rows and drop tables can go by index
+pub const POWERUP_TYPES: [PowerUpType; NUM_POWERUPS] = [
+    PowerUpType::Meat,
+    PowerUpType::DamageDealtUp,
+    PowerUpType::DamageReductionUp,
+    PowerUpType::AttackSpeedUp,
+    PowerUpType::MovementSpeedUp,
+    PowerUpType::MaxHPUp,
+    PowerUpType::Lifesteal,
+    PowerUpType::CritChance,
+];
+
 #[derive(Component, Eq, PartialEq, Clone)]
 pub struct StoredPowerUps{
     pub power_ups: [u8; NUM_POWERUPS],
-    // 0: MaxHPUp, 1: DamageReductionUp, 2: DamageDealtUp, 3: AttackSpeedUp, 4: MovementSpeedUp
+    // how many of each PowerUpType have been picked up, indexed by the type
+}
+
+impl StoredPowerUps {
+    /// base is the hp their class starts with
+    pub fn max_hp(&self, base: u8) -> u8 {
+        return base.saturating_add(self.power_ups[PowerUpType::MaxHPUp as usize].saturating_mul(MAX_HP_UP));
+    }
+
+    /// how much health landing a hit for this much damage gives back
+    pub fn lifesteal(&self, dealt: u16) -> u8 {
+        // in u32, a big hit with a lot of stacks doesn't fit in a u16
+        let stolen = dealt as u32 * self.power_ups[PowerUpType::Lifesteal as usize] as u32 * LIFESTEAL as u32 / 100;
+        return stolen.min(u8::MAX as u32) as u8;
+    }
+
+    pub fn crit_chance(&self) -> u8 {
+        return self.power_ups[PowerUpType::CritChance as usize].saturating_mul(CRIT_CHANCE).min(100);
+    }
+
+    /// whether a roll out of 100 lands a critical hit
+    pub fn crits(&self, roll: u8) -> bool {
+        return roll < self.crit_chance();
+    }
+
+    /// takes percent of every stack (rounded to the nearest) out to be dropped, and returns how many of each.
+    /// Meat is only counted for eating, so there's never any to drop
+    pub fn take_share(&mut self, percent: u8) -> [u8; NUM_POWERUPS] {
+        let mut taken = [0; NUM_POWERUPS];
+        for (index, stacks) in self.power_ups.iter_mut().enumerate() {
+            if index == PowerUpType::Meat as usize { continue }
+            taken[index] = ((*stacks as u16 * percent.min(100) as u16 + 50) / 100) as u8;
+            *stacks -= taken[index];
+        }
+        return taken;
+    }
 }
 
 #[derive(Component)]
@@ -45,6 +109,10 @@ pub struct ChanceDropPWU(pub bool);
 #[derive(Component)]
 pub struct PowerUp(pub PowerUpType);
 
+/// Stable id for entities that come and go during a match, shared between the host and clients
+#[derive(Component, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NetworkId(pub u16);
+
 /// Just a vec2 that describes the size of a bounding box around the entity
 #[derive(Component)]
 pub struct Collider(pub Vec2);
@@ -55,28 +123,142 @@ pub struct ScoreDisplay;
 #[derive(Component)]
 pub struct PowerupDisplayText(pub u8);
 
+#[derive(Component)]
+pub struct DashDisplay;
+
+#[derive(Component)]
+pub struct WeaponDisplay;
+
+#[derive(Component)]
+pub struct ShieldDisplay;
+
+#[derive(Component)]
+pub struct RespawnDisplay;
+
+/// Hitstun for a player or enemy. While it lasts they can't move or attack, and they slide along `push`.
+/// The host decides it, `since` is its tick, which is how clients tell a new hit from one they've already seen.
+/// A parry staggers without a push
+#[derive(Component, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Stagger {
+    pub since: u16,
+    pub ticks: u16,  // 0 if they've never been staggered
+    pub push: Vec2,  // pixels still to slide
+}
+
+impl Stagger {
+    pub fn active(&self, tick: u16) -> bool {
+        return tick.wrapping_sub(self.since) < self.ticks;
+    }
+
+    pub fn stun(&mut self, tick: u16, ticks: u16, push: Vec2) {
+        self.since = tick;
+        self.ticks = ticks;
+        self.push = push;
+    }
+
+    /// how far to slide over `ticks` (fractions for frames). It starts fast and eases out,
+    /// and adds up to the whole push whether it's done a tick at a time or a frame at a time
+    pub fn slide(&mut self, ticks: f32) -> Vec2 {
+        let step = self.push * (1. - KNOCKBACK_FALLOFF.powf(ticks));
+        self.push -= step;
+        if self.push.length() < 0.5 {
+            return step + std::mem::take(&mut self.push);
+        }
+        return step;
+    }
+}
+
+/// the push for a hit landing on `to` from `from`, `distance` pixels straight away from the hit
+pub fn knockback(from: Vec2, to: Vec2, distance: f32) -> Vec2 {
+    return (to - from).normalize_or_zero() * distance;
+}
+
 #[derive(Component, Clone)]
 pub struct Stats{
-    pub score: u8,
+    pub score: u16,  // a whole round holding the hill is more than a u8
     pub enemies_killed: u8,
     pub players_killed: u8,
+    pub assists: u8,
     pub camps_captured: u8,
     pub deaths: u8,
     pub kd_ratio: f32,
 }
 
+impl Stats {
+    pub fn update_kd(&mut self) {
+        if self.deaths != 0 {
+            self.kd_ratio = self.players_killed as f32 / self.deaths as f32;
+        }
+        else {
+            self.kd_ratio = self.players_killed as f32;
+        }
+    }
+}
+
+/// One attacker's share of the damage someone's taken lately, and when they last added to it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hit {
+    pub attacker: Attacker,
+    pub damage: u16,
+    pub tick: u16,
+}
+
+/// Who's hurt a player or enemy lately and by how much, the latest hit last.
+/// Only the host keeps these, it's what kill and assist credit comes from
+#[derive(Component, Default, Clone, Debug)]
+pub struct DamageLedger(pub Vec<Hit>);
+
+impl DamageLedger {
+    pub fn record(&mut self, attacker: Attacker, damage: u8, tick: u16) {
+        if damage == 0 { return }
+        self.0.retain(|hit| tick.wrapping_sub(hit.tick) < ASSIST_TICKS);
+        let mut hit = Hit { attacker, damage: damage as u16, tick };
+        if let Some(i) = self.0.iter().position(|old| old.attacker == attacker) {
+            hit.damage = hit.damage.saturating_add(self.0.remove(i).damage);
+        }
+        self.0.push(hit);
+    }
+
+    fn recent(&self, tick: u16) -> impl Iterator<Item = &Hit> {
+        return self.0.iter().filter(move |hit| tick.wrapping_sub(hit.tick) < ASSIST_TICKS);
+    }
+
+    /// whoever hurt them last, if it wasn't too long ago
+    pub fn killer(&self, tick: u16) -> Option<Attacker> {
+        return self.recent(tick).last().map(|hit| hit.attacker);
+    }
+
+    /// every other player who did enough to them lately, as a bit per player id
+    pub fn assists(&self, tick: u16) -> u16 {
+        let killer = self.killer(tick);
+        let mut assists = 0;
+        for hit in self.recent(tick) {
+            if Some(hit.attacker) == killer || hit.damage < ASSIST_DAMAGE { continue }
+            if let Attacker::Player(id) = hit.attacker {
+                assists |= 1 << id;
+            }
+        }
+        return assists;
+    }
+}
+
+/// the player ids in an assists bitmask
+pub fn assisters(assists: u16) -> impl Iterator<Item = u8> {
+    return (0..16).filter(move |id| assists & (1 << id) != 0);
+}
+
 #[derive(Component)]
 pub struct StatDisplayText(pub u8);
 
 #[derive(Component)]
-pub struct Enemy(pub u8);  // holds id
+pub struct Enemy(pub u16);  // holds id
 
 #[derive(Component)]
 pub struct Player(pub u8);  // holds id
 
 // camp stuff
 #[derive(Component)]
-pub struct Camp(pub u8); // holds id
+pub struct Camp(pub u16); // holds id
 
 
 #[derive(Component)]
@@ -92,12 +274,98 @@ pub struct CampEnemies{
 pub struct CampStatus(pub bool); // true if camp is captured, false if not
 
 #[derive(Component)]
-pub struct EnemyCamp(pub u8); // holds id of enemy's parent camp
+pub struct EnemyCamp(pub u16); // holds id of enemy's parent camp
 
 #[derive(Component)]
 pub struct ItemChest{
-    pub id: u8,
+    pub id: u16,
     pub contents: [u8; CHEST_CONTENTS],
+    pub weapon: Option<u8>,  // weapon id, if there's one in it as well
+    pub item: Option<ItemType>,  // and maybe a consumable
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knockback_slides_the_same_distance_by_tick_or_by_frame() {
+        let push = knockback(Vec2::ZERO, Vec2::new(3., 4.), 40.);
+        let mut by_tick = Stagger { since: 0, ticks: HITSTUN_TICKS, push };
+        let mut by_frame = by_tick;
+        let mut tick_total = Vec2::ZERO;
+        for _ in 0..20 {
+            tick_total += by_tick.slide(1.);
+        }
+        let mut frame_total = Vec2::ZERO;
+        for _ in 0..20 * 6 {
+            frame_total += by_frame.slide(1. / 6.);
+        }
+        assert!(tick_total.distance(push) < 0.01);
+        assert!(frame_total.distance(push) < 0.01);
+        assert_eq!(by_tick.push, Vec2::ZERO);
+    }
+
+    #[test]
+    fn powerup_stacks_scale_their_effects() {
+        let mut spu = StoredPowerUps { power_ups: [0; NUM_POWERUPS] };
+        assert_eq!(spu.max_hp(100), 100);
+        assert_eq!(spu.lifesteal(80), 0);
+        assert!(!spu.crits(0));
+        spu.power_ups[PowerUpType::MaxHPUp as usize] = 2;
+        spu.power_ups[PowerUpType::Lifesteal as usize] = 2;
+        spu.power_ups[PowerUpType::CritChance as usize] = 3;
+        assert_eq!(spu.max_hp(100), 100 + 2 * MAX_HP_UP);
+        assert_eq!(spu.lifesteal(80), 16);
+        assert!(spu.crits(29));
+        assert!(!spu.crits(30));
+        // stacking forever doesn't wrap around
+        spu.power_ups = [u8::MAX; NUM_POWERUPS];
+        assert_eq!(spu.max_hp(100), u8::MAX);
+        assert_eq!(spu.crit_chance(), 100);
+        assert_eq!(spu.lifesteal(u16::MAX), u8::MAX);
+    }
+
+    #[test]
+    fn dying_gives_up_a_share_of_each_stack() {
+        let mut spu = StoredPowerUps { power_ups: [4, 1, 2, 3, 0, 5, 0, 10] };
+        let taken = spu.take_share(50);
+        assert_eq!(taken, [0, 1, 1, 2, 0, 3, 0, 5]);
+        assert_eq!(spu.power_ups, [4, 0, 1, 1, 0, 2, 0, 5]);
+        assert_eq!(spu.take_share(0), [0; NUM_POWERUPS]);
+        let all = spu.take_share(100);
+        assert_eq!(all, [0, 0, 1, 1, 0, 2, 0, 5]);
+        assert_eq!(spu.power_ups, [4, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn last_recent_hit_gets_the_kill_and_the_rest_assist() {
+        let mut ledger = DamageLedger::default();
+        assert_eq!(ledger.killer(0), None);
+        ledger.record(Attacker::Player(3), 30, 65400);
+        ledger.record(Attacker::Player(1), 5, 10);
+        ledger.record(Attacker::Enemy(7), 40, 20);
+        ledger.record(Attacker::Player(2), 20, 30);
+        ledger.record(Attacker::Player(1), 5, 40);
+        // player 3's hit is too old, and player 1 only just did enough
+        assert_eq!(ledger.killer(40), Some(Attacker::Player(1)));
+        assert_eq!(ledger.assists(40), 1 << 2);
+        assert_eq!(ledger.0.len(), 3);
+        ledger.record(Attacker::Enemy(7), 1, 50);
+        assert_eq!(ledger.killer(50), Some(Attacker::Enemy(7)));
+        assert_eq!(assisters(ledger.assists(50)).collect::<Vec<u8>>(), vec![1, 2]);
+        assert_eq!(ledger.killer(50 + ASSIST_TICKS), None);
+    }
+
+    #[test]
+    fn stagger_wears_off() {
+        let mut stagger = Stagger::default();
+        assert!(!stagger.active(0));
+        stagger.stun(65534, 3, Vec2::ZERO);
+        assert!(stagger.active(65535));
+        assert!(stagger.active(0));
+        assert!(!stagger.active(1));
+    }
+}
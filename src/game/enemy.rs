@@ -2,18 +2,22 @@ use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 use crate::{AppState, net};
 use crate::Atlas;
-use movement::correct_wall_collisions;
+use movement::{correct_wall_collisions, knockback_destination};
 use crate::game::buffers::*;
 use crate::game::components::*;
 use crate::net::{is_client, is_host, TickNum};
 use crate::game::components::PowerUpType;
 use crate::game::map::{Biome, TILESIZE, MAPSIZE, WorldMap};
+use crate::game::events::{Attacker, GameEvent, GameplayEvent};
 use crate::game::movement;
-use crate::game::player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_DEF, PLAYER_DEFAULT_HP, PlayerShield};
+use crate::game::powerup::{self, NextNetworkId};
+use crate::game::player::{Block, LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_DEF, PlayerShield, Dash, Respawn};
 use std::collections::{BinaryHeap, HashMap};
 use std::cmp::Ordering;
 use bevy::ecs::component::Tick;
 use crate::PowerupAtlas;
+use crate::net::lerp::JitterBuffer;
+use crate::game::status::{self, StatusEffect, StatusEffects, StatusType};
 
 pub const ENEMY_SIZE: Vec2 = Vec2 { x: 32., y: 32. };
 pub const ENEMY_SPEED: f32 = 150. / net::TICKRATE as f32;
@@ -30,13 +34,15 @@ pub const AGGRO_BITFLAG: u8 = 2;
 
 const CIRCLE_RADIUS: f32 = 64.;
 const CIRCLE_DAMAGE: u8 = 15;
+const CIRCLE_KNOCKBACK: f32 = 40.;
+const POISON: (u8, f32) = (3, 4.);  // (hp a second, seconds) on everyone a normal enemy hits
+const BURN: (u8, f32) = (6, 3.);  // special enemies burn and slow instead
+const SLOW: (u8, f32) = (40, 2.);
+const PARRY_VULNERABILITY: (u8, f32) = (25, 3.);
 
 #[derive(Component)]
 pub struct EnemyWeapon;
 
-#[derive(Component)]
-pub struct LastAttacker(pub Option<u8>);
-
 #[derive(Component)]
 pub struct Aggro(pub Option<u8>);
 
@@ -82,8 +88,8 @@ pub fn spawn_enemy(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     entity_atlas: &Res<Atlas>, 
-    id: u8, 
-    campid: u8, 
+    id: u16, 
+    campid: u16, 
     pos: Vec2, 
     sprite: i32, 
     power_up_type: PowerUpType,
@@ -107,7 +113,9 @@ pub fn spawn_enemy(
         Enemy(id),
         (PosBuffer(CircularBuffer::new_from(Some(pos))),
         HpBuffer(CircularBuffer::new_from(Some(enemy_hp))),
-        EventBuffer(CircularBuffer::new())),
+        EventBuffer(CircularBuffer::new()),
+        Stagger::default(),
+        StatusEffects::default()),
         SpawnPosition(pos),
         Health {
             current: enemy_hp,
@@ -123,7 +131,7 @@ pub fn spawn_enemy(
             ..default()
         },
         Collider(ENEMY_SIZE),
-        LastAttacker(None),
+        DamageLedger::default(),
         StoredPowerUps
         {
             power_ups: pu,
@@ -133,6 +141,7 @@ pub fn spawn_enemy(
         SpawnEnemyWeaponTimer(Timer::from_seconds(enemy_attack_rate, TimerMode::Once)),//add a timer to spawn the enemy attack very 4 seconds
         EnemyRegenTimer(Timer::from_seconds(1.0, TimerMode::Repeating)),
         IsSpecial(is_special),
+        JitterBuffer::new(),
     )).id();
     if is_special {
         let special_entity = commands.spawn(SpriteBundle {
@@ -142,6 +151,7 @@ pub fn spawn_enemy(
         }).id();
         commands.entity(enemy_entity).add_child(special_entity);
     }
+    status::spawn_status_icons(commands, enemy_entity);
 }
 
 pub fn remove_enemies(mut commands: Commands, enemies: Query<Entity, With<Enemy>>) {
@@ -201,16 +211,17 @@ pub fn attack_simulate(
     asset_server: Res<AssetServer>,
     time: Res<Time>,
     tick: Res<TickNum>,
-    mut query_enemies: Query<(Entity, &Health, &mut EventBuffer, &Transform, &mut SpawnEnemyWeaponTimer, &Aggro, &IsSpecial), (With<Enemy>, Without<Player>)>,
-    mut player_query: Query<(&Transform, &mut HpBuffer, &StoredPowerUps, &PlayerShield), With<Player>>
+    mut query_enemies: Query<(&Enemy, &Health, &mut EventBuffer, &Transform, &mut SpawnEnemyWeaponTimer, &Aggro, &IsSpecial, &mut Stagger, &mut StatusEffects), Without<Player>>,
+    mut player_query: Query<(&Player, &Transform, &mut HpBuffer, &StoredPowerUps, &mut PlayerShield, &Dash, &mut Stagger, &mut StatusEffects, &Respawn, &mut DamageLedger)>,
 ) {
-    for (enemy_entity, enemy_hp, mut enemy_eb, enemy_transform, mut spawn_timer, aggro, is_special) in query_enemies.iter_mut() {
+    for (enemy, enemy_hp, mut enemy_eb, enemy_transform, mut spawn_timer, aggro, is_special, mut stagger, mut effects) in query_enemies.iter_mut() {
         if enemy_hp.current <= 0 || aggro.0 == None { continue; }
+        if stagger.active(tick.0) { continue }
         if spawn_timer.0.finished() {
             spawn_timer.0.reset();
             let events = enemy_eb.0.get(tick.0).unwrap_or(0);
             enemy_eb.0.set(tick.0, Some(events | ATTACK_BITFLAG));
-            for (player_transform, mut player_hp, player_power_ups, shield) in player_query.iter_mut() {
+            for (pl, player_transform, mut player_hp, player_power_ups, mut shield, dash, mut player_stagger, mut player_effects, respawn, mut ledger) in player_query.iter_mut() {
                 let circle_radius;
                 if is_special.0 {
                     circle_radius = CIRCLE_RADIUS * SPECIAL_ATTACK_RADIUS_MOD;
@@ -219,12 +230,36 @@ pub fn attack_simulate(
                 }
                 if player_transform.translation.distance(enemy_transform.translation) < circle_radius {
                     // must check if damage reduction is greater than damage dealt, otherwise subtraction overflow or player will gain health
-                    if shield.active { continue }
+                    if dash.invulnerable(tick.0) || respawn.protected(tick.0) { continue }
                     // Multiply enemy's damage value by player's default defense and DAMAGE_REDUCTION_UP ^ stacks of damage reduction
                     let dmg: u8 = (CIRCLE_DAMAGE as f32 * PLAYER_DEFAULT_DEF * DAMAGE_REDUCTION_UP.powf(player_power_ups.power_ups[PowerUpType::DamageReductionUp as usize] as f32)) as u8;
+                    match shield.block(tick.0, dmg) {
+                        Block::Parried => {
+                            stagger.stun(tick.0, STAGGER_TICKS, Vec2::ZERO);
+                            effects.add(tick.0, StatusEffect::new(StatusType::Vulnerability, PARRY_VULNERABILITY.0, tick.0, PARRY_VULNERABILITY.1, Attacker::Player(pl.0)));
+                            continue;
+                        },
+                        Block::Blocked => continue,
+                        Block::Hit => {},
+                    }
+                    // bigger circles hit harder, damage reduction ups take the edge off
+                    let push = CIRCLE_KNOCKBACK * circle_radius / CIRCLE_RADIUS
+                        * DAMAGE_REDUCTION_UP.powi(player_power_ups.power_ups[PowerUpType::DamageReductionUp as usize] as i32);
+                    player_stagger.stun(tick.0, HITSTUN_TICKS, knockback(enemy_transform.translation.xy(), player_transform.translation.xy(), push));
+                    let source = Attacker::Enemy(enemy.0);
+                    if is_special.0 {
+                        player_effects.add(tick.0, StatusEffect::new(StatusType::Burn, BURN.0, tick.0, BURN.1, source));
+                        player_effects.add(tick.0, StatusEffect::new(StatusType::Slow, SLOW.0, tick.0, SLOW.1, source));
+                    }
+                    else {
+                        player_effects.add(tick.0, StatusEffect::new(StatusType::Poison, POISON.0, tick.0, POISON.1, source));
+                    }
+                    let dmg = player_effects.damage_taken(dmg, tick.0);
                     if dmg > 0 {
-                        let hp = player_hp.0.get(tick.0).unwrap_or(PLAYER_DEFAULT_HP).saturating_sub(dmg);
+                        let prev_hp = player_hp.0.get(tick.0).unwrap_or(0);
+                        let hp = prev_hp.saturating_sub(dmg);
                         player_hp.0.set(tick.0, Some(hp));
+                        ledger.record(source, prev_hp - hp, tick.0);
                     }
                     commands.spawn(AudioBundle {
                         source: asset_server.load("playerHurt.ogg"),
@@ -239,12 +274,14 @@ pub fn attack_simulate(
 pub fn update_enemies(
     mut commands: Commands,
     tick: Res<TickNum>,
-    mut enemies: Query<(&mut Health, &HpBuffer, &LastAttacker, &StoredPowerUps, &mut TextureAtlasSprite, &Transform, &EnemyCamp, &ChanceDropPWU, &mut Visibility), With<Enemy>>,
+    mut enemies: Query<(&Enemy, &mut Health, &HpBuffer, &mut DamageLedger, &StoredPowerUps, &mut TextureAtlasSprite, &Transform, &EnemyCamp, &ChanceDropPWU, &mut Visibility)>,
     mut player: Query<(&mut Stats, &Player)>,
     powerup_atlas: Res<PowerupAtlas>,
+    mut next_id: ResMut<NextNetworkId>,
     mut camp_query: Query<(&Camp, &mut CampEnemies, &CampStatus), With<Camp>>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ) {
-    for (mut hp, hb, la, spu, mut sp, tf, ec_num, cdpu, mut vis) in enemies.iter_mut() {
+    for (enemy_id, mut hp, hb, mut ledger, spu, mut sp, tf, ec_num, cdpu, mut vis) in enemies.iter_mut() {
         let next_hp = hb.0.get(tick.0);
         if next_hp.is_none() { continue }
         hp.current = next_hp.unwrap();
@@ -256,33 +293,43 @@ pub fn update_enemies(
                 for (index, &element) in spu.power_ups.iter().enumerate() {
                     if element == 1
                     {
-                        commands.spawn((SpriteSheetBundle {
-                            texture_atlas: powerup_atlas.handle.clone(),
-                            sprite: TextureAtlasSprite { index: powerup_atlas.coord_to_index(0, index as i32), ..Default::default() },
-                            transform: Transform {
-                                translation: Vec3::new(tf.translation.x, tf.translation.y, 1.0),
-                                ..Default::default()
-                            },
-                            ..Default::default()},
-                                        PowerUp(unsafe { std::mem::transmute(index as u8) } ),
-                        ));
+                        powerup::spawn_powerup(
+                            &mut commands,
+                            &powerup_atlas,
+                            next_id.next(),
+                            POWERUP_TYPES[index],
+                            tf.translation.truncate());
                     }
                 }
             }
+            // only a player gets credit, anything else that kills an enemy still counts against its camp
+            let killer = match ledger.killer(tick.0) {
+                Some(Attacker::Player(id)) => Some(id),
+                _ => None,
+            };
+            ledger.0.clear();
+            if let Some(killer) = killer {
+                gameplay_writer.send(GameplayEvent(GameEvent::EnemyKilled {
+                    enemy: enemy_id.0,
+                    killer,
+                    pos: tf.translation.truncate(),
+                }));
+            }
             // decrement the enemy counter of the camp that this enemy is apart of
             for (camp_num, mut enemies_in_camp, camp_status) in camp_query.iter_mut() {
-                if camp_num.0 == ec_num.0 {
-                    enemies_in_camp.current_enemies = enemies_in_camp.current_enemies.saturating_sub(1);
-                }
+                if camp_num.0 != ec_num.0 { continue }
+                enemies_in_camp.current_enemies = enemies_in_camp.current_enemies.saturating_sub(1);
 
                 // check if the camp is cleared and assign 5 points for clearing the camp
-                if enemies_in_camp.current_enemies == 0 && camp_status.0 == true{
+                if enemies_in_camp.current_enemies == 0 && camp_status.0 == true && killer.is_some() {
+                    let killer = killer.unwrap();
                     for (mut stats, pl) in player.iter_mut() {
-                        if pl.0 == la.0.expect("camp has no attacker") {
+                        if pl.0 == killer {
                             stats.score = stats.score.saturating_add(5);
                             stats.camps_captured = stats.camps_captured.saturating_add(1);
                         }
                     }
+                    gameplay_writer.send(GameplayEvent(GameEvent::CampCleared { camp: camp_num.0, player: killer }));
                 }
             }
 
@@ -290,7 +337,7 @@ pub fn update_enemies(
             hp.dead = true;
             *vis = Visibility::Hidden;
             for (mut stats, pl) in player.iter_mut() {
-                if pl.0 == la.0.expect("died with no attacker?") {
+                if Some(pl.0) == killer {
                     stats.score = stats.score.saturating_add(1);
                     stats.enemies_killed = stats.enemies_killed.saturating_add(1);
                 }
@@ -356,23 +403,31 @@ pub fn fixed_aggro(
 
 pub fn fixed_move(
     tick: Res<net::TickNum>,
-    mut enemies: Query<(&mut PosBuffer, &Aggro, &SpawnPosition), (With<Enemy>, Without<Player>)>,
+    mut enemies: Query<(&mut PosBuffer, &Aggro, &SpawnPosition, &Collider, &mut Stagger, &StatusEffects), (With<Enemy>, Without<Player>)>,
     players: Query<(&Player, &PosBuffer), (With<Player>, Without<Enemy>)>,
     map: Res<WorldMap>
 ) {
-    for (mut epb, aggro, spawn_pos) in &mut enemies {
+    for (mut epb, aggro, spawn_pos, collider, mut stagger, effects) in &mut enemies {
+        let speed = ENEMY_SPEED * effects.speed(tick.0);
         let prev = epb.0.get(tick.0.wrapping_sub(1));
         if prev.is_none() { continue }
         let prev = prev.unwrap();
         let mut next = prev.clone();
 
         'mov: {
+            // knocked back enemies slide instead of walking
+            if stagger.push != Vec2::ZERO {
+                let step = stagger.slide(1.);
+                next = knockback_destination(prev, step, &collider.0, &map.biome_map);
+                break 'mov;
+            }
+            if stagger.active(tick.0) { break 'mov }
             if aggro.0.is_none() {
                 // move the enemy to their spawn position
                 let displacement = spawn_pos.0 - prev;
                 if !(displacement.length() < CIRCLE_RADIUS) {
                     let posit = find_next(&map.biome_map, prev, spawn_pos.0);
-                    let movement = (posit - prev).normalize() * ENEMY_SPEED;
+                    let movement = (posit - prev).normalize() * speed;
                     next += movement;
                 }
             } else {
@@ -389,7 +444,7 @@ pub fn fixed_move(
                 let displacement = player_pos - prev;
                 if !(displacement.length() < CIRCLE_RADIUS) {
                     let posit = find_next(&map.biome_map, prev, player_pos);
-                    let movement = (posit - prev).normalize() * ENEMY_SPEED;
+                    let movement = (posit - prev).normalize() * speed;
                     next += movement;
                 }
             }
@@ -696,15 +751,18 @@ pub fn health_simulate(
 
 pub fn handle_packet(
     tick: Res<TickNum>,
+    time: Res<Time>,
     mut enemy_reader: EventReader<net::packets::EnemyTickEvent>,
-    mut enemy_query: Query<(Entity, &Enemy, &mut PosBuffer, &mut HpBuffer, &mut EventBuffer, &IsSpecial)>,
+    mut enemy_query: Query<(Entity, &Enemy, &mut PosBuffer, &mut HpBuffer, &mut EventBuffer, &mut StatusEffects, &IsSpecial, &mut JitterBuffer)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
     for ev in enemy_reader.iter() {
-        for (e, en, mut pb, mut hb, mut eb, is) in &mut enemy_query {
+        for (e, en, mut pb, mut hb, mut eb, mut effects, is, mut jb) in &mut enemy_query {
             if en.0 == ev.tick.id {
-                pb.0.set(ev.seq_num, Some(ev.tick.pos));
+                effects.0 = ev.tick.effects.clone();
+                pb.0.set_with_time(ev.seq_num, Some(ev.tick.pos), ev.seq_num);
+                jb.observe(ev.seq_num, time.elapsed_seconds());
                 hb.0.set(tick.0, Some(ev.tick.hp));
                 eb.0.set(tick.0, Some(ev.tick.events));
                 if ev.tick.events & ATTACK_BITFLAG != 0 {
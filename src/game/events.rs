@@ -0,0 +1,367 @@
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::camera::SpatialCameraBundle;
+use crate::game::class::Classes;
+use crate::game::components::{assisters, Camp, Fade, ItemChest, Player, ASSIST_SCORE, KILL_SCORE};
+use crate::game::item::BOMB_RADIUS;
+use crate::game::player::LocalPlayer;
+
+const HEARING_DISTANCE: f32 = 640.;
+const ANNOUNCEMENT_TIME: f32 = 3.;
+const POPUP_TIME: f32 = 1.;
+const POPUP_RISE: f32 = 40.;  // pixels per second
+const KILL_FEED_TOP: f32 = 304.;  // just under the minimap
+const KILL_FEED_LEN: usize = 5;
+const KILL_FEED_TIME: f32 = 6.;
+
+/// who landed the killing blow on a player
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Attacker {
+    Player(u8),
+    Enemy(u16),
+}
+
+/// Gameplay outcomes decided on the host. The host sends these to every client so everyone gets the same feedback
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    EnemyKilled { enemy: u16, killer: u8, pos: Vec2 },
+    ChestOpened { chest: u16, opener: u8 },
+    CampCleared { camp: u16, player: u8 },
+    CampRespawned { camp: u16 },
+    PlayerKilled { victim: u8, attacker: Attacker, assists: u16 },  // a bit for each player id that assisted
+    PlayerJoined { player: u8, class: u8 },
+    PlayerLeft { player: u8 },
+    WeaponTaken { chest: u16, player: u8 },
+    ItemTaken { chest: u16, player: u8 },
+    Exploded { pos: Vec2, player: u8 },
+    DashRejected { player: u8, pos: Vec2 },  // where the host put them back
+    SpawnRejected { player: u8 },  // someone got in the way before the host saw them spawn
+}
+
+/// Sent on the host when something happens, and on clients when the host tells them about it
+#[derive(Event)]
+pub struct GameplayEvent(pub GameEvent);
+
+#[derive(Component)]
+pub struct Announcement(pub Timer);
+
+#[derive(Component)]
+pub struct AnnouncementBanner;
+
+#[derive(Component)]
+pub struct ScorePopup(pub Timer);
+
+/// The list of recent kills down the right side of the screen
+#[derive(Component)]
+pub struct KillFeed;
+
+#[derive(Component)]
+pub struct KillFeedEntry(pub Timer);
+
+pub struct GameEventsPlugin;
+
+impl Plugin for GameEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Game), (spawn_announcement, spawn_kill_feed))
+            .add_systems(Update, (
+                play_feedback,
+                update_announcement,
+                update_popups,
+                update_kill_feed,
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(OnExit(AppState::Game), despawn_feedback)
+            .add_event::<GameplayEvent>();
+    }
+}
+
+fn spawn_announcement(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.),
+            top: Val::Px(120.),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    }, AnnouncementBanner)).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                }
+            ).with_text_alignment(TextAlignment::Center),
+            Announcement(Timer::from_seconds(ANNOUNCEMENT_TIME, TimerMode::Once)),
+        ));
+    });
+}
+
+fn spawn_kill_feed(mut commands: Commands) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(20.),
+            top: Val::Px(KILL_FEED_TOP),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::FlexEnd,
+            ..Default::default()
+        },
+        ..Default::default()
+    }, KillFeed));
+}
+
+fn despawn_feedback(
+    mut commands: Commands,
+    banners: Query<Entity, With<AnnouncementBanner>>,
+    feeds: Query<Entity, With<KillFeed>>,
+    popups: Query<Entity, With<ScorePopup>>,
+) {
+    for e in &banners {
+        commands.entity(e).despawn_recursive();
+    }
+    for e in &feeds {
+        commands.entity(e).despawn_recursive();
+    }
+    for e in &popups {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn spawn_popup(commands: &mut Commands, asset_server: &AssetServer, text: &str, pos: Vec2) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 28.0,
+                    color: Color::GOLD,
+                }
+            ),
+            transform: Transform::from_xyz(pos.x, pos.y + 32., 10.),
+            ..Default::default()
+        },
+        ScorePopup(Timer::from_seconds(POPUP_TIME, TimerMode::Once)),
+    ));
+}
+
+pub fn announce(announcements: &mut Query<(&mut Text, &mut Announcement)>, message: String) {
+    for (mut text, mut announcement) in announcements.iter_mut() {
+        text.sections[0].value = message.clone();
+        announcement.0.reset();
+    }
+}
+
+/// what the kill feed says about a kill
+pub fn kill_feed_line(victim: u8, attacker: Attacker, assists: u16) -> String {
+    let mut line = match attacker {
+        Attacker::Player(id) => format!("Player {} slew Player {}", id + 1, victim + 1),
+        Attacker::Enemy(_) => format!("An enemy slew Player {}", victim + 1),
+    };
+    let helpers: Vec<String> = assisters(assists).map(|id| format!("Player {}", id + 1)).collect();
+    if !helpers.is_empty() {
+        line.push_str(&format!(", helped by {}", helpers.join(" and ")));
+    }
+    return line;
+}
+
+/// the same sounds, popups and announcements on every machine
+pub fn play_feedback(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    classes: Res<Classes>,
+    mut event_reader: EventReader<GameplayEvent>,
+    local_player: Query<&Player, With<LocalPlayer>>,
+    players: Query<(&Player, &Transform)>,
+    chests: Query<(&ItemChest, &Transform)>,
+    camps: Query<(&Camp, &GlobalTransform)>,
+    cameras: Query<&Transform, With<SpatialCameraBundle>>,
+    mut announcements: Query<(&mut Text, &mut Announcement)>,
+) {
+    let local_id = local_player.get_single().ok().map(|pl| pl.0);
+    let listener = cameras.get_single().ok().map(|tf| tf.translation.truncate());
+    let can_hear = |pos: Vec2| listener.is_some_and(|listener| listener.distance(pos) < HEARING_DISTANCE);
+    for ev in event_reader.iter() {
+        match ev.0 {
+            GameEvent::EnemyKilled { killer, pos, .. } => {
+                if local_id == Some(killer) {
+                    spawn_popup(&mut commands, &asset_server, "+1", pos);
+                }
+            },
+            GameEvent::ChestOpened { chest, .. } => {
+                for (ic, tf) in &chests {
+                    if ic.id != chest || !can_hear(tf.translation.truncate()) { continue }
+                    commands.spawn(AudioBundle {
+                        source: asset_server.load("chest.ogg"),
+                        settings: PlaybackSettings::DESPAWN,
+                    });
+                }
+            },
+            GameEvent::CampCleared { camp, player } => {
+                if local_id != Some(player) { continue }
+                for (c, tf) in &camps {
+                    if c.0 != camp { continue }
+                    spawn_popup(&mut commands, &asset_server, "+5", tf.translation().truncate());
+                }
+                announce(&mut announcements, "Camp cleared!".to_string());
+            },
+            GameEvent::CampRespawned { camp } => {
+                for (c, tf) in &camps {
+                    if c.0 != camp || !can_hear(tf.translation().truncate()) { continue }
+                    commands.spawn(AudioBundle {
+                        source: asset_server.load("horse.ogg"),
+                        settings: PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(0.5)),
+                    });
+                    announce(&mut announcements, "A camp nearby has respawned".to_string());
+                }
+            },
+            GameEvent::PlayerKilled { victim, attacker, assists } => {
+                if local_id.is_some_and(|id| assists & (1 << id) != 0) {
+                    for (pl, tf) in &players {
+                        if pl.0 != victim { continue }
+                        spawn_popup(&mut commands, &asset_server, &format!("+{}", ASSIST_SCORE), tf.translation.truncate());
+                    }
+                }
+                if local_id == Some(victim) {
+                    match attacker {
+                        Attacker::Player(id) => announce(&mut announcements, format!("Slain by Player {}", id + 1)),
+                        Attacker::Enemy(_) => announce(&mut announcements, "Slain by an enemy".to_string()),
+                    }
+                }
+                else if attacker == Attacker::Player(local_id.unwrap_or(u8::MAX)) {
+                    for (pl, tf) in &players {
+                        if pl.0 != victim { continue }
+                        spawn_popup(&mut commands, &asset_server, &format!("+{}", KILL_SCORE), tf.translation.truncate());
+                    }
+                    announce(&mut announcements, format!("You slew Player {}", victim + 1));
+                }
+            },
+            GameEvent::PlayerJoined { player, class } => {
+                if local_id == Some(player) { continue }
+                announce(&mut announcements, format!("Player {} joined as {}", player + 1, classes.get(class).name));
+            },
+            GameEvent::PlayerLeft { player } => {
+                announce(&mut announcements, format!("Player {} left", player + 1));
+            },
+            // the weapon display shows it, nobody else needs telling
+            GameEvent::WeaponTaken { .. } => {},
+            // same for the hotbar
+            GameEvent::ItemTaken { .. } => {},
+            // their own client puts them back, see movement::undo_rejected_dash
+            GameEvent::DashRejected { .. } => {},
+            // they're still dead, so they only need to pick somewhere else
+            GameEvent::SpawnRejected { player } => {
+                if local_id != Some(player) { continue }
+                announce(&mut announcements, "Too close to someone, pick another spot".to_string());
+            },
+            GameEvent::Exploded { pos, .. } => {
+                let scale = BOMB_RADIUS / 64.;
+                commands.spawn((
+                    SpriteBundle {
+                        texture: asset_server.load("EnemyAttack01.png").into(),
+                        transform: Transform {
+                            translation: pos.extend(5.),
+                            scale: Vec3::new(scale, scale, 1.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Fade { current: 1., max: 1. },
+                ));
+                if can_hear(pos) {
+                    commands.spawn(AudioBundle {
+                        source: asset_server.load("hitHurt.ogg"),
+                        settings: PlaybackSettings::DESPAWN,
+                    });
+                }
+            },
+        }
+    }
+}
+
+/// adds a line for every kill the host tells us about, and lets old ones fade out
+fn update_kill_feed(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut event_reader: EventReader<GameplayEvent>,
+    feeds: Query<(Entity, Option<&Children>), With<KillFeed>>,
+    mut entries: Query<(&mut Text, &mut KillFeedEntry)>,
+) {
+    let feed = feeds.get_single();
+    if feed.is_err() { return }
+    let (feed, children) = feed.unwrap();
+    let children: Vec<Entity> = children.map_or(Vec::new(), |children| children.to_vec());
+    // the oldest lines make room for new ones
+    let mut evicted: Vec<Entity> = Vec::new();
+    let mut shown = children.len();
+    for ev in event_reader.iter() {
+        if let GameEvent::PlayerKilled { victim, attacker, assists } = ev.0 {
+            if shown >= KILL_FEED_LEN && shown - KILL_FEED_LEN < children.len() {
+                let old = children[shown - KILL_FEED_LEN];
+                commands.entity(old).despawn_recursive();
+                evicted.push(old);
+            }
+            let entry = commands.spawn((
+                TextBundle::from_section(
+                    kill_feed_line(victim, attacker, assists),
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    }
+                ),
+                KillFeedEntry(Timer::from_seconds(KILL_FEED_TIME, TimerMode::Once)),
+            )).id();
+            commands.entity(feed).add_child(entry);
+            shown += 1;
+        }
+    }
+    for child in children {
+        if evicted.contains(&child) { continue }
+        let entry = entries.get_mut(child);
+        if entry.is_err() { continue }
+        let (mut text, mut entry) = entry.unwrap();
+        entry.0.tick(time.delta());
+        if entry.0.finished() {
+            commands.entity(child).despawn_recursive();
+            continue;
+        }
+        let left = 1. - entry.0.percent();
+        text.sections[0].style.color = Color::rgba(1., 1., 1., (left * 4.).min(1.));
+    }
+}
+
+fn update_announcement(
+    time: Res<Time>,
+    mut announcements: Query<(&mut Text, &mut Announcement)>,
+) {
+    for (mut text, mut announcement) in &mut announcements {
+        announcement.0.tick(time.delta());
+        let left = 1. - announcement.0.percent();
+        text.sections[0].style.color = Color::rgba(1., 1., 1., left.min(0.5) * 2.);
+    }
+}
+
+fn update_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popups: Query<(Entity, &mut Transform, &mut Text, &mut ScorePopup)>,
+) {
+    for (e, mut tf, mut text, mut popup) in &mut popups {
+        popup.0.tick(time.delta());
+        if popup.0.finished() {
+            commands.entity(e).despawn_recursive();
+            continue;
+        }
+        tf.translation.y += POPUP_RISE * time.delta_seconds();
+        text.sections[0].style.color.set_a(1. - popup.0.percent());
+    }
+}
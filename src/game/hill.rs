@@ -0,0 +1,175 @@
+//! King of the Hill. Every so often the host moves the hill to the next camp, and whoever's standing in it
+//! with nobody from another side earns points every second. Everyone else hears where the hill is, who holds it
+//! and how long it's got left in every HostTick
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::{HpBuffer, PosBuffer};
+use crate::game::components::{Camp, Player, Stats};
+use crate::game::map::{CAMPSIZE, TILESIZE};
+use crate::game::player::LocalPlayer;
+use crate::game::team::{Team, NO_TEAM};
+use crate::net::{is_host, TICKLEN_S, TickNum};
+
+pub const NO_HILL: u16 = u16::MAX;  // the camp id when there's no hill, like in every other mode
+pub const HILL_EMPTY: u8 = u8::MAX;
+pub const HILL_CONTESTED: u8 = u8::MAX - 1;
+const HILL_POINTS: u16 = 1;  // per second held, for everyone on it
+const HILL_SCORE_INTERVAL: f32 = 1.;  // seconds
+
+/// Where the hill is and who's on it. The host works it out and clients get a copy every tick
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct Hill {
+    pub camp: u16,
+    pub rotates_in: u16,  // ticks until it moves on
+    pub holder: u8,  // the lowest id holding it, or HILL_EMPTY or HILL_CONTESTED
+}
+
+impl Default for Hill {
+    fn default() -> Hill {
+        return Hill { camp: NO_HILL, rotates_in: 0, holder: HILL_EMPTY };
+    }
+}
+
+/// How long the hill stays at each camp. A period of 0 turns the mode off
+#[derive(Resource, Default)]
+pub struct HillRules {
+    pub period: u16,  // ticks
+    pub score_ticks: u16,
+}
+
+impl HillRules {
+    pub fn with_period(seconds: f32) -> HillRules {
+        return HillRules {
+            period: (seconds.max(0.) / TICKLEN_S).round().min(u16::MAX as f32) as u16,
+            score_ticks: (HILL_SCORE_INTERVAL / TICKLEN_S).round() as u16,
+        };
+    }
+}
+
+/// The hill's status on the HUD
+#[derive(Component)]
+pub struct HillDisplay;
+
+/// the camp after this one, starting over from the first
+pub fn next_hill(camp: u16, num_camps: u16) -> u16 {
+    if num_camps == 0 { return NO_HILL }
+    if camp == NO_HILL { return 0 }
+    return (camp + 1) % num_camps;
+}
+
+/// who holds a hill with these players on it. Anyone not on the first one's side makes it contested
+pub fn hill_holder(on_hill: &[(u8, Team)]) -> u8 {
+    if on_hill.is_empty() { return HILL_EMPTY }
+    let (first, team) = on_hill[0];
+    if on_hill.iter().any(|(id, other)| *id != first && !team.allied(*other)) { return HILL_CONTESTED }
+    return on_hill.iter().map(|(id, _)| *id).min().unwrap_or(first);
+}
+
+pub struct HillPlugin;
+
+impl Plugin for HillPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Game), reset_hill)
+            .add_systems(FixedUpdate, update_hill.run_if(in_state(AppState::Game)).run_if(is_host).before(crate::net::host::fixed))
+            .add_systems(Update, update_hill_display.run_if(in_state(AppState::Game)))
+            .init_resource::<Hill>()
+            .init_resource::<HillRules>();
+    }
+}
+
+fn reset_hill(mut hill: ResMut<Hill>) {
+    *hill = Hill::default();
+}
+
+/// moves the hill along when its time's up, then works out who's on it and pays them, on the host
+pub fn update_hill(
+    tick: Res<TickNum>,
+    rules: Res<HillRules>,
+    mut hill: ResMut<Hill>,
+    camps: Query<(&Camp, &Transform)>,
+    mut players: Query<(&Player, &PosBuffer, &HpBuffer, &Team, &mut Stats)>,
+) {
+    let num_camps = camps.iter().count() as u16;
+    // a map with no camps has nowhere to put it
+    if rules.period == 0 || num_camps == 0 { return }
+    if hill.camp == NO_HILL || hill.rotates_in == 0 {
+        hill.camp = next_hill(hill.camp, num_camps);
+        hill.rotates_in = rules.period;
+        println!("the hill moved to camp {}", hill.camp);
+    }
+    else {
+        hill.rotates_in -= 1;
+    }
+    let center = camps.iter().find(|(camp, _)| camp.0 == hill.camp).map(|(_, tf)| tf.translation.truncate());
+    if center.is_none() { return }
+    let center = center.unwrap();
+    let radius = (CAMPSIZE / 2 * TILESIZE) as f32;
+    let mut on_hill: Vec<(u8, Team)> = Vec::new();
+    for (pl, pb, hb, team, _) in &players {
+        if hb.0.get(tick.0).unwrap_or(0) == 0 { continue }
+        let pos = pb.0.get(tick.0);
+        if pos.is_none() || pos.unwrap().distance(center) > radius { continue }
+        on_hill.push((pl.0, *team));
+    }
+    hill.holder = hill_holder(&on_hill);
+    if hill.holder == HILL_CONTESTED || hill.holder == HILL_EMPTY { return }
+    if rules.score_ticks == 0 || tick.0 % rules.score_ticks != 0 { return }
+    for (pl, _, _, _, mut stats) in &mut players {
+        if on_hill.iter().any(|(id, _)| *id == pl.0) {
+            stats.score = stats.score.saturating_add(HILL_POINTS);
+        }
+    }
+}
+
+/// who has the hill and when it moves, hidden when there's no hill
+pub fn update_hill_display(
+    hill: Res<Hill>,
+    players: Query<(&Player, &Team)>,
+    local: Query<&Player, With<LocalPlayer>>,
+    mut displays: Query<(&mut Text, &mut Visibility), With<HillDisplay>>,
+) {
+    for (mut text, mut vis) in &mut displays {
+        if hill.camp == NO_HILL {
+            *vis = Visibility::Hidden;
+            continue;
+        }
+        *vis = Visibility::Visible;
+        let holder = match hill.holder {
+            HILL_EMPTY => "nobody holds it".to_string(),
+            HILL_CONTESTED => "contested".to_string(),
+            id if local.get_single().is_ok_and(|pl| pl.0 == id) => "you hold it".to_string(),
+            id => {
+                // on teams it's the whole team's hill
+                let team = players.iter().find(|(pl, _)| pl.0 == id).map(|(_, team)| *team);
+                match team {
+                    Some(team) if team.0 != NO_TEAM => format!("{} Team holds it", team.name()),
+                    _ => format!("Player {} holds it", id + 1),
+                }
+            },
+        };
+        let seconds = (hill.rotates_in as f32 * TICKLEN_S).ceil() as u32;
+        text.sections[0].value = format!("Hill: {}, moves in {}s", holder, seconds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hill_goes_round_the_camps() {
+        assert_eq!(next_hill(NO_HILL, 3), 0);
+        assert_eq!(next_hill(1, 3), 2);
+        assert_eq!(next_hill(2, 3), 0);
+        assert_eq!(next_hill(NO_HILL, 0), NO_HILL);
+    }
+
+    #[test]
+    fn anyone_from_another_side_contests_it() {
+        assert_eq!(hill_holder(&[]), HILL_EMPTY);
+        assert_eq!(hill_holder(&[(3, Team(NO_TEAM))]), 3);
+        assert_eq!(hill_holder(&[(3, Team(NO_TEAM)), (1, Team(NO_TEAM))]), HILL_CONTESTED);
+        assert_eq!(hill_holder(&[(3, Team(1)), (1, Team(1))]), 1);
+        assert_eq!(hill_holder(&[(3, Team(1)), (1, Team(1)), (2, Team(0))]), HILL_CONTESTED);
+    }
+}
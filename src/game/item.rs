@@ -0,0 +1,351 @@
+//! Consumables. Players carry a few in a hotbar and use them when they want to, instead of on contact like powerups.
+//! Using one only asks the host to, through the event bits, and the host sends back what's left
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::{DirBuffer, EventBuffer, HpBuffer, PosBuffer};
+use crate::game::class::{Classes, PlayerClass};
+use crate::game::components::*;
+use crate::game::events::{Attacker, GameEvent, GameplayEvent};
+use crate::game::movement::KeyBinds;
+use crate::game::player::{self, InteractEvent, LocalPlayer, Respawn};
+use crate::game::status::{StatusEffect, StatusEffects, StatusType};
+use crate::game::team::Team;
+use crate::net::{is_client, is_host, TickNum};
+
+pub const HOTBAR_SLOTS: usize = 3;
+pub const ITEM_SHIFT: u8 = 6;  // the top two event bits are which slot to use, plus one
+pub const ITEM_BITS: u8 = 0b1100_0000;
+pub const ITEM_DROP_CHANCE: u32 = 50;  // percent of chests with a consumable in them
+const STACK_MAX: u8 = 5;
+const GRAB_DISTANCE: f32 = 48.;
+const DROP_Z: f32 = 1.5;
+const DROP_SIZE: f32 = 10.;
+const POTION_HEAL: u8 = 60;
+const TONIC: (u8, f32) = (50, 6.);  // (haste percent, seconds)
+const BOMB_THROW: f32 = 96.;  // how far in front of the player it goes off
+pub const BOMB_RADIUS: f32 = 64.;
+const BOMB_DAMAGE: u8 = 40;
+const BOMB_KNOCKBACK: f32 = 60.;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ItemType {
+    #[default]
+    Meat,
+    HealthPotion,
+    SpeedTonic,
+    Bomb,
+}
+
+/// what chests can have in them, meat comes from powerups
+pub const CHEST_ITEMS: [ItemType; 3] = [ItemType::HealthPotion, ItemType::SpeedTonic, ItemType::Bomb];
+
+impl ItemType {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ItemType::Meat => "Meat",
+            ItemType::HealthPotion => "Potion",
+            ItemType::SpeedTonic => "Tonic",
+            ItemType::Bomb => "Bomb",
+        };
+    }
+
+    fn color(&self) -> Color {
+        return match self {
+            ItemType::Meat => Color::rgb(0.7, 0.3, 0.2),
+            ItemType::HealthPotion => Color::rgb(1., 0.2, 0.3),
+            ItemType::SpeedTonic => Color::rgb(1., 1., 0.3),
+            ItemType::Bomb => Color::rgb(0.2, 0.2, 0.2),
+        };
+    }
+}
+
+/// a stack of one kind of item, empty when count is 0
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ItemSlot {
+    pub item: ItemType,
+    pub count: u8,
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Hotbar {
+    pub slots: [ItemSlot; HOTBAR_SLOTS],
+}
+
+impl Hotbar {
+    /// goes on top of a stack of the same thing if there's room, otherwise in the first empty slot. false if it's full
+    pub fn add(&mut self, item: ItemType) -> bool {
+        let stack = self.slots.iter_mut().find(|s| s.count > 0 && s.item == item && s.count < STACK_MAX);
+        if let Some(stack) = stack {
+            stack.count += 1;
+            return true;
+        }
+        let empty = self.slots.iter_mut().find(|s| s.count == 0);
+        if let Some(empty) = empty {
+            *empty = ItemSlot { item, count: 1 };
+            return true;
+        }
+        return false;
+    }
+
+    pub fn peek(&self, slot: usize) -> Option<ItemType> {
+        let slot = self.slots.get(slot)?;
+        if slot.count == 0 { return None }
+        return Some(slot.item);
+    }
+
+    pub fn take(&mut self, slot: usize) {
+        self.slots[slot].count = self.slots[slot].count.saturating_sub(1);
+    }
+}
+
+/// which hotbar slot a tick's events ask to use, if any
+pub fn used_slot(events: u8) -> Option<usize> {
+    let slot = (events & ITEM_BITS) >> ITEM_SHIFT;
+    if slot == 0 { return None }
+    return Some(slot as usize - 1);
+}
+
+/// A consumable lying where its chest was opened
+#[derive(Component)]
+pub struct ItemDrop {
+    pub chest: u16,
+    pub item: ItemType,
+}
+
+#[derive(Component)]
+pub struct HotbarDisplay;
+
+/// Someone asking to use what's in one of their hotbar slots, on the host
+#[derive(Event)]
+pub struct UseItemEvent {
+    pub seq_num: u16,
+    pub id: u8,
+    pub slot: usize,
+}
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (
+                item_grab_simulate.after(player::attack_host),
+                use_item_simulate.after(player::attack_host).after(player::attack_simulate),
+            ).run_if(in_state(AppState::Game)).run_if(is_host).before(crate::net::host::fixed))
+            .add_systems(Update, (
+                item_input,
+                update_hotbar_display,
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(Update, handle_item_events.run_if(is_client).run_if(in_state(AppState::Game)))
+            .add_systems(OnExit(AppState::Game), remove_item_drops)
+            .init_resource::<Events<UseItemEvent>>();
+    }
+}
+
+pub fn spawn_item_drop(
+    commands: &mut Commands,
+    chest: u16,
+    item: ItemType,
+    pos: Vec2,
+) {
+    commands.spawn((
+        ItemDrop { chest, item },
+        SpriteBundle {
+            sprite: Sprite {
+                color: item.color(),
+                custom_size: Some(Vec2::splat(DROP_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_translation(pos.extend(DROP_Z)),
+            ..default()
+        },
+    ));
+}
+
+/// number keys ask to use a hotbar slot. Nothing happens here until the host says so
+pub fn item_input(
+    tick: Res<TickNum>,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    mut players: Query<&mut EventBuffer, With<LocalPlayer>>,
+) {
+    let eb = players.get_single_mut();
+    if eb.is_err() { return }
+    let slot = key_binds.hotbar.iter().position(|key| keyboard_input.just_pressed(*key));
+    if slot.is_none() { return }
+    let mut eb = eb.unwrap();
+    let events = eb.0.get(tick.0).unwrap_or(0) & !ITEM_BITS;
+    eb.0.set(tick.0, Some(events | ((slot.unwrap() as u8 + 1) << ITEM_SHIFT)));
+}
+
+/// players pressing interact next to a consumable, on the host. Full hotbars leave it where it is
+pub fn item_grab_simulate(
+    mut commands: Commands,
+    mut interact_reader: EventReader<InteractEvent>,
+    mut players: Query<(&Player, &PosBuffer, &Health, &mut Hotbar)>,
+    drops: Query<(Entity, &ItemDrop, &Transform)>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
+) {
+    let mut taken: Vec<Entity> = Vec::new();
+    for ev in interact_reader.iter() {
+        for (pl, pb, hp, mut hotbar) in &mut players {
+            if pl.0 != ev.id || hp.dead { continue }
+            let pos = pb.0.get(ev.seq_num);
+            if pos.is_none() { continue }
+            let pos = pos.unwrap();
+            let nearest = drops.iter()
+                .filter(|(e, _, tf)| !taken.contains(e) && tf.translation.truncate().distance(pos) < GRAB_DISTANCE)
+                .min_by(|(_, _, a), (_, _, b)| a.translation.truncate().distance(pos).total_cmp(&b.translation.truncate().distance(pos)));
+            if nearest.is_none() { continue }
+            let (e, drop, _) = nearest.unwrap();
+            if !hotbar.add(drop.item) { continue }
+            commands.entity(e).despawn();
+            taken.push(e);
+            gameplay_writer.send(GameplayEvent(GameEvent::ItemTaken { chest: drop.chest, player: pl.0 }));
+        }
+    }
+}
+
+/// does what an item does, if the player has it and it'd do anything. Heals aren't wasted at full health
+pub fn use_item_simulate(
+    tick: Res<TickNum>,
+    classes: Res<Classes>,
+    mut use_reader: EventReader<UseItemEvent>,
+    mut players: Query<(&Player, &PosBuffer, &DirBuffer, &mut HpBuffer, &StoredPowerUps, &PlayerClass, &mut Hotbar, &mut StatusEffects, &mut Stagger, &mut DamageLedger, &mut Respawn, &Team), Without<Enemy>>,
+    mut enemies: Query<(&PosBuffer, &mut HpBuffer, &mut DamageLedger, &mut Stagger, &StatusEffects), With<Enemy>>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
+) {
+    for ev in use_reader.iter() {
+        let mut blast = None;
+        for (pl, pb, db, mut hb, spu, class, mut hotbar, mut effects, stagger, _, mut respawn, team) in &mut players {
+            if pl.0 != ev.id { continue }
+            let hp = hb.0.get(tick.0).unwrap_or(0);
+            let item = hotbar.peek(ev.slot);
+            if hp == 0 || item.is_none() || stagger.active(tick.0) { continue }
+            match item.unwrap() {
+                ItemType::Meat | ItemType::HealthPotion => {
+                    let max_hp = spu.max_hp(classes.get(class.0).hp);
+                    if hp >= max_hp { continue }
+                    let heal = if item == Some(ItemType::Meat) { MEAT_VALUE } else { POTION_HEAL };
+                    hb.0.set(tick.0, Some(hp.saturating_add(heal).min(max_hp)));
+                },
+                ItemType::SpeedTonic => {
+                    effects.add(tick.0, StatusEffect::new(StatusType::Haste, TONIC.0, tick.0, TONIC.1, Attacker::Player(pl.0)));
+                },
+                ItemType::Bomb => {
+                    let pos = pb.0.get(ev.seq_num);
+                    if pos.is_none() { continue }
+                    let dir = db.0.get(ev.seq_num).unwrap_or(0.);
+                    blast = Some((pos.unwrap() + Vec2::from_angle(dir) * BOMB_THROW, *team));
+                    respawn.unprotect();
+                },
+            }
+            hotbar.take(ev.slot);
+        }
+        if blast.is_none() { continue }
+        let (center, thrower_team) = blast.unwrap();
+        gameplay_writer.send(GameplayEvent(GameEvent::Exploded { pos: center, player: ev.id }));
+        for (pb, mut hb, mut ledger, mut stagger, effects) in &mut enemies {
+            let pos = pb.0.get(tick.0);
+            if pos.is_none() || pos.unwrap().distance(center) > BOMB_RADIUS { continue }
+            let hp = hb.0.get(tick.0).unwrap_or(0);
+            if hp == 0 { continue }
+            let next_hp = hp.saturating_sub(effects.damage_taken(BOMB_DAMAGE, tick.0));
+            hb.0.set(tick.0, Some(next_hp));
+            ledger.record(Attacker::Player(ev.id), hp - next_hp, tick.0);
+            stagger.stun(tick.0, HITSTUN_TICKS, knockback(center, pos.unwrap(), BOMB_KNOCKBACK));
+        }
+        // it doesn't hurt whoever threw it, or their team
+        for (pl, pb, _, mut hb, _, _, _, effects, mut stagger, mut ledger, respawn, team) in &mut players {
+            if pl.0 == ev.id || thrower_team.allied(*team) || respawn.protected(tick.0) { continue }
+            let pos = pb.0.get(tick.0);
+            if pos.is_none() || pos.unwrap().distance(center) > BOMB_RADIUS { continue }
+            let prev_hp = hb.0.get(tick.0).unwrap_or(0);
+            if prev_hp == 0 { continue }
+            let hp = prev_hp.saturating_sub(effects.damage_taken(BOMB_DAMAGE, tick.0));
+            hb.0.set(tick.0, Some(hp));
+            ledger.record(Attacker::Player(ev.id), prev_hp - hp, tick.0);
+            stagger.stun(tick.0, HITSTUN_TICKS, knockback(center, pos.unwrap(), BOMB_KNOCKBACK));
+        }
+    }
+}
+
+/// clients take the item off the ground when the host says someone picked it up,
+/// what's in their hotbar comes in their PlayerTicks
+pub fn handle_item_events(
+    mut commands: Commands,
+    mut gameplay_reader: EventReader<GameplayEvent>,
+    drops: Query<(Entity, &ItemDrop)>,
+) {
+    for ev in gameplay_reader.iter() {
+        if let GameEvent::ItemTaken { chest, .. } = ev.0 {
+            for (e, drop) in &drops {
+                if drop.chest == chest {
+                    commands.entity(e).despawn();
+                }
+            }
+        }
+    }
+}
+
+pub fn update_hotbar_display(
+    players: Query<&Hotbar, With<LocalPlayer>>,
+    mut displays: Query<&mut Text, With<HotbarDisplay>>,
+) {
+    let hotbar = players.get_single();
+    if hotbar.is_err() { return }
+    let hotbar = hotbar.unwrap();
+    for mut text in &mut displays {
+        let slots: Vec<String> = hotbar.slots.iter().enumerate().map(|(i, slot)| {
+            if slot.count == 0 { return format!("{}: -", i + 1) }
+            if slot.count == 1 { return format!("{}: {}", i + 1, slot.item.name()) }
+            return format!("{}: {} x{}", i + 1, slot.item.name(), slot.count);
+        }).collect();
+        text.sections[0].value = slots.join("   ");
+    }
+}
+
+pub fn remove_item_drops(
+    mut commands: Commands,
+    drops: Query<Entity, With<ItemDrop>>,
+) {
+    for e in &drops {
+        commands.entity(e).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_then_fills_empty_slots() {
+        let mut hotbar = Hotbar::default();
+        for _ in 0..STACK_MAX + 1 {
+            assert!(hotbar.add(ItemType::Meat));
+        }
+        assert!(hotbar.add(ItemType::Bomb));
+        assert_eq!(hotbar.slots[0], ItemSlot { item: ItemType::Meat, count: STACK_MAX });
+        assert_eq!(hotbar.slots[1], ItemSlot { item: ItemType::Meat, count: 1 });
+        assert_eq!(hotbar.slots[2], ItemSlot { item: ItemType::Bomb, count: 1 });
+        assert!(!hotbar.add(ItemType::HealthPotion));
+        assert!(hotbar.add(ItemType::Bomb));
+    }
+
+    #[test]
+    fn taking_the_last_one_empties_the_slot() {
+        let mut hotbar = Hotbar::default();
+        hotbar.add(ItemType::SpeedTonic);
+        assert_eq!(hotbar.peek(0), Some(ItemType::SpeedTonic));
+        hotbar.take(0);
+        assert_eq!(hotbar.peek(0), None);
+        assert_eq!(hotbar.peek(HOTBAR_SLOTS), None);
+    }
+
+    #[test]
+    fn slot_bits_round_trip() {
+        assert_eq!(used_slot(0b0011_1111), None);
+        for slot in 0..HOTBAR_SLOTS {
+            assert_eq!(used_slot(((slot as u8 + 1) << ITEM_SHIFT) | 1), Some(slot));
+        }
+    }
+}
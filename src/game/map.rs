@@ -43,7 +43,7 @@ pub struct CampNodes(pub Vec<Vec2>);
 pub struct MapSeed(pub u64);
 
 #[derive(Resource)]
-pub struct NumCamps(pub u8);
+pub struct NumCamps(pub u16);
 
 #[derive(Resource)]
 pub struct ChestCoords(pub Vec<Vec2>);
@@ -160,9 +160,9 @@ fn set_num_camps(
     num_camps_input_query: Query<&NumCampsInput>,
     mut num_camps: ResMut<NumCamps>,
 ) {
-    let mut num: u8 = 10;
+    let mut num: u16 = 10;
     for input in num_camps_input_query.iter() {
-        if let Ok(parsed_num) = input.value.parse::<u8>() {
+        if let Ok(parsed_num) = input.value.parse::<u16>() {
             num = parsed_num;
         }
     }
@@ -170,10 +170,10 @@ fn set_num_camps(
 }
 
 // Generate the map using Perlin noise
-fn read_map(
+pub fn read_map(
     map: &mut WorldMap,
     camp_nodes: &mut Vec<Vec2>,
-    num_camps: &Res<NumCamps>,
+    num_camps: u16,
     mut rng: &mut ChaChaRng,
     chest_coords: &mut Vec<Vec2>,
 ) -> Result<(), Box<dyn Error>> {
@@ -200,8 +200,8 @@ fn read_map(
     // and shuffle them, then truncate the vector to the number of camps
     refine_coordinates(camp_nodes);
     camp_nodes.shuffle(&mut rng);
-    if camp_nodes.len() > num_camps.0 as usize {
-        camp_nodes.truncate(num_camps.0 as usize);
+    if camp_nodes.len() > num_camps as usize {
+        camp_nodes.truncate(num_camps as usize);
     }
 
     // Create a vector of coordinates for extra nodes for the graph equal to EXTRANODES variable
@@ -426,7 +426,7 @@ pub fn setup_map(
     let mut rng = rand_chacha::ChaChaRng::seed_from_u64(map_seed.0);
 
     // Generate the map, camp nodes, and item nodes
-    let _ = read_map(&mut world_map, &mut camp_nodes.0, &num_camps, &mut rng, &mut chest_coords.0);
+    let _ = read_map(&mut world_map, &mut camp_nodes.0, num_camps.0, &mut rng, &mut chest_coords.0);
 
     // Get a handle for a pure white TILESIZE x TILESIZE image to be colored based on tile type later
     let tile_handle = assets.add(create_tile_image());
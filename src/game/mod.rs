@@ -8,6 +8,16 @@ pub mod noise;
 pub mod movement;
 pub mod buffers;
 pub mod camp;
+pub mod powerup;
+pub mod projectile;
+pub mod weapon;
+pub mod class;
+pub mod table;
+pub mod team;
+pub mod hill;
+pub mod item;
+pub mod status;
+pub mod events;
 pub mod components;
 
 pub const TITLE: &str = "JORDQUEST: SPAWNED INTO A PIXELATED WORLD WITH ENEMIES, CAMPS, AND... ANOTHER PLAYER!? CAN I EARN ENOUGH UPGRADES TO BE VICTORIOUS AND FILL MY DIAPER?";
@@ -18,7 +28,7 @@ pub const ROUND_TIME: f32 = 5.0 * 60.0;
 
 pub const ENTITY_SHEET_DIMS: Vec2 = Vec2 {x: 6., y: 4.}; // (rows, columns)
 pub const DECORATION_SHEET_DIMS: Vec2 = Vec2{x: 6., y: 3.};
-pub const POWERUP_SHEET_DIMS: Vec2 = Vec2{x: 5., y: 1.};
+pub const POWERUP_SHEET_DIMS: Vec2 = Vec2{x: components::NUM_POWERUPS as f32, y: 1.};
 pub const CHEST_SHEET_DIMS: Vec2 = Vec2{x: 1., y: 2.};
 
 #[derive(Resource)]
@@ -94,31 +104,35 @@ pub struct MapConfig {
     pub enemy_per_camp: String,
     pub map_seed: String,
     pub eid_percentage: String,
+    pub max_players: String,
+    pub respawn_delay: String,
+    pub loot_drop: String,
+    pub teams: String,
+    pub hill: String,
 }
 
+/// The game itself. It leaves out bevy's own plugins so the tests can run it without a window
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin{
     fn build(&self, app: &mut App) {
-        app.add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: TITLE.into(),
-                resolution: (WIN_W, WIN_H).into(),
-                present_mode: bevy::window::PresentMode::Fifo,
-                ..default()
-            }),
-            ..default()
-        })
-            .set(ImagePlugin::default_nearest())
-        )
-        .add_systems(Startup, startup)
+        app.add_systems(Startup, startup)
         .add_systems(Update, update_fades)
         .add_plugins((
             player::PlayerPlugin,
             enemy::EnemyPlugin,
             map::MapPlugin,
             camera::CameraPlugin,
-            camp::CampPlugin
+            camp::CampPlugin,
+            powerup::PowerupPlugin,
+            projectile::ProjectilePlugin,
+            weapon::WeaponPlugin,
+            class::ClassPlugin,
+            team::TeamPlugin,
+            hill::HillPlugin,
+            item::ItemPlugin,
+            status::StatusPlugin,
+            events::GameEventsPlugin
         ));
     }
 }
@@ -179,7 +193,9 @@ pub fn startup(mut commands: Commands, asset_server: Res<AssetServer>, mut textu
     commands.insert_resource(PlayerId(0xFF));
     commands.insert_resource(MapConfig{
         num_camps: String::new(), num_chests: String::new(), enemy_per_camp: String::new(),
-        map_seed: String::new(), eid_percentage: String::new(),
+        map_seed: String::new(), eid_percentage: String::new(), max_players: String::new(),
+        respawn_delay: String::new(), loot_drop: String::new(), teams: String::new(),
+        hill: String::new(),
     });
     commands.insert_resource(movement::KeyBinds::new());
 }
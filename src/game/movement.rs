@@ -9,9 +9,13 @@ use crate::components::*;
 use crate::game::buffers;
 use crate::game::buffers::{DirBuffer, PosBuffer};
 use crate::game::camera::SpatialCameraBundle;
+use crate::game::class::{Classes, PlayerClass};
+use crate::game::events::{GameEvent, GameplayEvent};
 use crate::game::map::Biome::Wall;
 use crate::game::map::{get_pos_in_tile, get_tile_at_pos, TILESIZE};
-use crate::net::TickNum;
+use crate::game::item::HOTBAR_SLOTS;
+use crate::game::status::StatusEffects;
+use crate::net::{TickNum, TICKLEN_S};
 
 pub const WALL_DAMAGE: u8 = 5;
 
@@ -20,7 +24,11 @@ pub struct KeyBinds {
     pub up: KeyCode,
     pub down: KeyCode,
     pub left: KeyCode,
-    pub right: KeyCode
+    pub right: KeyCode,
+    pub dash: KeyCode,
+    pub switch_weapon: KeyCode,
+    pub interact: KeyCode,
+    pub hotbar: [KeyCode; HOTBAR_SLOTS],
 }
 
 impl KeyBinds {
@@ -30,7 +38,11 @@ impl KeyBinds {
             up: KeyCode::W,
             down: KeyCode::S,
             left: KeyCode::A,
-            right: KeyCode::D
+            right: KeyCode::D,
+            dash: KeyCode::Space,
+            switch_weapon: KeyCode::Q,
+            interact: KeyCode::E,
+            hotbar: [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3],
         }
     }
 }
@@ -57,34 +69,43 @@ pub const MOVE_VECTORS: [Vec2; 16] = [
     Vec2 { x:0., y:0. },  // 1111
 ];
 
+const DASH_STEP: f32 = TILESIZE as f32 / 2.;  // how far a dash moves between wall checks
+const KNOCKBACK_STEP: f32 = TILESIZE as f32 / 2.;  // same for being knocked back
+
+/// index into MOVE_VECTORS for the movement keys being held
+fn move_index(keyboard_input: &Input<KeyCode>, key_binds: &KeyBinds) -> usize {
+    let mut mv: usize = keyboard_input.pressed(key_binds.up) as usize * 0b0001;
+    mv |= keyboard_input.pressed(key_binds.down) as usize * 0b0010;
+    mv |= keyboard_input.pressed(key_binds.left) as usize * 0b0100;
+    mv |= keyboard_input.pressed(key_binds.right) as usize * 0b1000;
+    return mv;
+}
+
 /// Player movement function. Runs on Update schedule.
 pub fn handle_move(
     tick: Res<TickNum>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut players: Query<(&Player, &mut Transform, &mut Health, &Collider, &StoredPowerUps, &PlayerShield, &mut buffers::HpBuffer), With<LocalPlayer>>,
+    mut players: Query<(&Player, &mut Transform, &mut Health, &Collider, &StoredPowerUps, &PlayerShield, &mut buffers::HpBuffer, &Stagger, &StatusEffects, &PlayerClass), With<LocalPlayer>>,
     other_colliders: Query<(&Transform, &Collider, Option<&Health>), Without<LocalPlayer>>,
     map: Res<map::WorldMap>,
+    classes: Res<Classes>,
     time: Res<Time>,
     key_binds: Res<KeyBinds>
 ) {
     // should only be a single entry in this query (with localplayer)
     let player = players.get_single_mut();
     if player.is_err() { return; }
-    let (_, mut pos, mut hp, collider, spu, shield, mut hb) = player.unwrap();
+    let (_, mut pos, hp, collider, spu, shield, mut hb, stagger, effects, class) = player.unwrap();
 
-    if hp.dead || shield.active { return }
+    if hp.dead || shield.active || stagger.active(tick.0) { return }
 
-    let mut mv: usize = keyboard_input.pressed(key_binds.up) as usize * 0b0001;
-    mv |= keyboard_input.pressed(key_binds.down) as usize * 0b0010;
-    mv |= keyboard_input.pressed(key_binds.left) as usize * 0b0100;
-    mv |= keyboard_input.pressed(key_binds.right) as usize * 0b1000;
-    let dir = MOVE_VECTORS[mv];
+    let dir = MOVE_VECTORS[move_index(&keyboard_input, &key_binds)];
     let can_move = true;
-
+    let speed = classes.get(class.0).speed(spu) * effects.speed(tick.0);
 
     let mut new_pos = Vec3 {
-        x: pos.translation.x + dir.x * (PLAYER_SPEED + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32) * time.delta_seconds(),
-        y: pos.translation.y + dir.y * (PLAYER_SPEED + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32) * time.delta_seconds(),
+        x: pos.translation.x + dir.x * speed * time.delta_seconds(),
+        y: pos.translation.y + dir.y * speed * time.delta_seconds(),
         z: 0.0,
     };
 
@@ -117,6 +138,99 @@ pub fn handle_move(
     }
 }
 
+/// Dashes in the direction we're moving, or where we're aiming if we're standing still.
+/// We move ourselves right away, the host checks it when the DASH_BITFLAG gets there
+pub fn dash_input(
+    tick: Res<TickNum>,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    map: Res<map::WorldMap>,
+    mut players: Query<(&mut Transform, &mut buffers::EventBuffer, &DirBuffer, &Health, &PlayerShield, &mut Dash, &Stagger), With<LocalPlayer>>,
+) {
+    let player = players.get_single_mut();
+    if player.is_err() { return }
+    let (mut tf, mut eb, db, hp, shield, mut dash, stagger) = player.unwrap();
+    if hp.dead || shield.active || stagger.active(tick.0) || !dash.ready(tick.0) { return }
+    if !keyboard_input.just_pressed(key_binds.dash) { return }
+    let mut dir = MOVE_VECTORS[move_index(&keyboard_input, &key_binds)];
+    if dir == Vec2::ZERO {
+        dir = Vec2::from_angle(db.0.get(tick.0).unwrap_or(0.));
+    }
+    let dest = dash_destination(tf.translation.xy(), dir, &map.biome_map);
+    tf.translation.x = dest.x;
+    tf.translation.y = dest.y;
+    let events = eb.0.get(tick.0).unwrap_or(0);
+    eb.0.set(tick.0, Some(events | DASH_BITFLAG));
+    dash.last = Some(tick.0);
+}
+
+/// The host didn't allow our last dash, usually because our cooldown ran out before its did,
+/// so we go back to where it says we are
+pub fn undo_rejected_dash(
+    tick: Res<TickNum>,
+    mut gameplay_reader: EventReader<GameplayEvent>,
+    mut players: Query<(&Player, &mut Transform, &mut PosBuffer), With<LocalPlayer>>,
+) {
+    let player = players.get_single_mut();
+    if player.is_err() { return }
+    let (pl, mut tf, mut pb) = player.unwrap();
+    for ev in gameplay_reader.iter() {
+        if let GameEvent::DashRejected { player, pos } = ev.0 {
+            if player != pl.0 { continue }
+            tf.translation.x = pos.x;
+            tf.translation.y = pos.y;
+            pb.0.set(tick.0, Some(pos));
+        }
+    }
+}
+
+/// where a dash from `from` ends up, stopping short of the first wall in the way
+pub fn dash_destination(
+    from: Vec2,
+    dir: Vec2,
+    map: &[[map::Biome; map::MAPSIZE]; map::MAPSIZE],
+) -> Vec2 {
+    let mut pos = from;
+    for _ in 0..(DASH_DISTANCE / DASH_STEP) as usize {
+        let next = pos + dir * DASH_STEP;
+        if get_tile_at_pos(&next.extend(0.), map) == Wall { break }
+        pos = next;
+    }
+    return correct_wall_collisions(&pos.extend(0.), &PLAYER_SIZE, map).xy();
+}
+
+/// moves from `from` by `step`, a bit at a time so a hard hit can't knock anything through a wall
+pub fn knockback_destination(
+    from: Vec2,
+    step: Vec2,
+    collider: &Vec2,
+    map: &[[map::Biome; map::MAPSIZE]; map::MAPSIZE],
+) -> Vec2 {
+    let steps = (step.length() / KNOCKBACK_STEP).ceil().max(1.);
+    let mut pos = from;
+    for _ in 0..steps as usize {
+        pos = correct_wall_collisions(&(pos + step / steps).extend(0.), collider, map).xy();
+    }
+    return pos;
+}
+
+/// Slides the local player along a knockback. The host decides who gets hit,
+/// but our position is ours to send, so we do the moving
+pub fn knockback_move(
+    time: Res<Time>,
+    map: Res<map::WorldMap>,
+    mut players: Query<(&mut Transform, &Collider, &mut Stagger), With<LocalPlayer>>,
+) {
+    let player = players.get_single_mut();
+    if player.is_err() { return }
+    let (mut tf, collider, mut stagger) = player.unwrap();
+    if stagger.push == Vec2::ZERO { return }
+    let step = stagger.slide(time.delta_seconds() / TICKLEN_S);
+    let pos = knockback_destination(tf.translation.xy(), step, &collider.0, &map.biome_map);
+    tf.translation.x = pos.x;
+    tf.translation.y = pos.y;
+}
+
 pub fn correct_wall_collisions(
     pos: &Vec3,
     collider: &Vec2,
@@ -162,6 +276,9 @@ pub fn update_buffer(
     if player.is_err() { return }
     let (tf, mut pb, mut db, current_pos) = player.unwrap();
     pb.0.set(tick.0, Some(Vec2::new(current_pos.translation.x, current_pos.translation.y)));
+    // keep pointing the same way while the cursor is off the window
+    let last_angle = db.0.get(tick.0.wrapping_sub(1)).unwrap_or(0.);
+    db.0.set(tick.0, Some(last_angle));
 
     let window = windows.single();
     let camera = cameras.get_single();
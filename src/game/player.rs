@@ -1,28 +1,60 @@
-use std::time::Duration;
+use std::collections::HashMap;
 use bevy::prelude::*;
+use rand::Rng;
 use crate::{enemy, net};
 use crate::game::movement::*;
 use crate::{Atlas, AppState};
 use crate::buffers::*;
 use crate::game::components::*;
-use crate::game::enemy::LastAttacker;
-use crate::game::PlayerId;
-use crate::net::{is_client, is_host, TICKLEN_S, TickNum};
+use crate::game::{PlayerId, PowerupAtlas};
+use crate::game::class::{ChosenClass, Classes, PlayerClass};
+use crate::game::map::WorldMap;
+use crate::game::events::{Attacker, GameEvent, GameplayEvent};
+use crate::game::item::{self, Hotbar, ItemType, UseItemEvent};
+use crate::game::powerup::{self, NextNetworkId, PowerupGrabEvent};
+use crate::game::projectile::FireEvent;
+use crate::game::status::{self, StatusEffect, StatusEffects, StatusType};
+use crate::game::team::{Team, NO_TEAM};
+use crate::game::weapon::{self, Weapon, Weapons};
+use crate::net::{is_client, is_host, IsHost, TICKLEN_S, TickNum};
+use crate::net::lerp::JitterBuffer;
 use crate::net::packets::{PlayerTickEvent, UserCmdEvent};
 use crate::menus::layout::{toggle_leaderboard, update_leaderboard};
 
-pub const PLAYER_SPEED: f32 = 250.;
-pub const PLAYER_DEFAULT_HP: u8 = 100;
 pub const PLAYER_DEFAULT_DEF: f32 = 1.;
 pub const PLAYER_SIZE: Vec2 = Vec2 { x: 32., y: 32. };
-pub const MAX_PLAYERS: usize = 4;
-pub const SWORD_DAMAGE: u8 = 40;
-pub const SWORD_LENGTH: f32 = 90.0;
-pub const SWORD_DEGREES: f32 = 70.0;
-const DEFAULT_COOLDOWN: f32 = 0.8;
+pub const MAX_PLAYERS: usize = 16;  // ids have to fit in this, the host picks the actual cap
+pub const DEFAULT_PLAYER_CAP: usize = 12;
+const PLAYER_SPRITES: u8 = 4;  // columns of the entity sheet that are player sprites
+const PLAYER_TINTS: [Color; 4] = [
+    Color::WHITE,
+    Color::rgb(1., 0.6, 0.6),
+    Color::rgb(0.6, 0.8, 1.),
+    Color::rgb(0.7, 1., 0.6),
+];
 pub const ATTACK_BITFLAG: u8 = 1;
 pub const SPAWN_BITFLAG: u8 = 2;
 pub const SHIELD_BITFLAG: u8 = 4;
+pub const DASH_BITFLAG: u8 = 8;
+pub const FIRE_BITFLAG: u8 = 16;
+pub const INTERACT_BITFLAG: u8 = 32;
+pub const SHIELD_MAX: u8 = 100;
+const SHIELD_RECOVER: u8 = 40;  // a broken shield can't go back up until it's back to this
+const PARRY_TICKS: u16 = 2;  // hits this soon after raising the shield get parried
+const STAGGER_TINT: Color = Color::rgb(1., 0.4, 0.4);
+const PARRY_VULNERABILITY: u8 = 25;  // percent more damage a parried attacker takes
+const PARRY_VULNERABILITY_SECONDS: f32 = 3.;
+const SWING_RADIUS: f32 = 0.55;  // how far out the weapon sprite swings, as a fraction of its reach
+pub const DASH_DISTANCE: f32 = 120.;
+pub const DASH_IFRAME_TICKS: u16 = 3;  // how long nothing can hurt you after a dash
+const DASH_HOLD_SLACK: f32 = 48.;  // how close a held player has to say they are before we believe them again
+pub const DEFAULT_RESPAWN_DELAY: f32 = 5.;  // seconds, the host can pick something else
+pub const MAX_RESPAWN_DELAY: f32 = 60.;
+pub const SPAWN_CLEARANCE: f32 = 240.;  // how far from anyone alive a spawn has to be
+const SPAWN_PROTECTION: f32 = 3.;  // seconds of not taking damage after spawning, unless they attack
+const PROTECTED_ALPHA: f32 = 0.5;
+pub const DEFAULT_LOOT_DROP: u8 = 50;  // percent of each powerup stack a dying player drops, the host can pick something else
+const LOOT_SCATTER: f32 = 40.;  // how far from where they died the drops land
 
 #[derive(Event)]
 pub struct SetIdEvent(pub u8);
@@ -33,9 +65,18 @@ pub struct AttackEvent {
     pub id: u8,
 }
 
+/// someone pressed interact, seq_num is the tick they pressed it on
+#[derive(Event)]
+pub struct InteractEvent {
+    pub seq_num: u16,
+    pub id: u8,
+}
+
+/// someone asking to spawn at pos, which the host can turn down
 #[derive(Event)]
 pub struct SpawnEvent {
-    pub id: u8
+    pub id: u8,
+    pub pos: Vec2,
 }
 
 #[derive(Event)]
@@ -56,6 +97,8 @@ pub struct SwordAnimation{
     pub current: f32,
     pub cursor_vector: Vec2,
     pub max: f32,
+    pub arc: f32,  // degrees either side of the cursor
+    pub radius: f32,  // how far out the sprite swings
 }
 
 #[derive(Component)]
@@ -67,9 +110,157 @@ pub struct HealthBar;
 #[derive(Component)]
 pub struct Shield;
 
+/// The host's copy decides what gets blocked. Clients get durability from the host and work out `broken` from it the same way
 #[derive(Component)]
 pub struct PlayerShield {
     pub active: bool,
+    pub durability: u8,  // blocked hits take this down instead of hp
+    pub raised: Option<u16>,  // tick it last went up, for the parry window
+    pub broken: bool,
+}
+
+/// what a shield did about a hit
+#[derive(Debug, PartialEq)]
+pub enum Block {
+    Hit,  // it wasn't up, the hit goes through
+    Blocked,
+    Parried,
+}
+
+impl PlayerShield {
+    pub fn new() -> PlayerShield {
+        return PlayerShield { active: false, durability: SHIELD_MAX, raised: None, broken: false };
+    }
+
+    pub fn raise(&mut self, tick: u16) {
+        if self.active || self.broken { return }
+        self.active = true;
+        self.raised = Some(tick);
+    }
+
+    pub fn parrying(&self, tick: u16) -> bool {
+        return self.active && self.raised.is_some_and(|raised| tick.wrapping_sub(raised) < PARRY_TICKS);
+    }
+
+    /// a hit for damage lands on this player. Parries are free, blocks cost durability and can break the shield
+    pub fn block(&mut self, tick: u16, damage: u8) -> Block {
+        if !self.active { return Block::Hit }
+        if self.parrying(tick) { return Block::Parried }
+        self.set_durability(self.durability.saturating_sub(damage));
+        return Block::Blocked;
+    }
+
+    pub fn set_durability(&mut self, durability: u8) {
+        self.durability = durability;
+        if durability == 0 {
+            self.broken = true;
+            self.active = false;
+        }
+        else if durability >= SHIELD_RECOVER {
+            self.broken = false;
+        }
+    }
+}
+
+/// When this player last dashed. The host's copy is the one that decides
+/// whether a dash is allowed and whether they're still invulnerable
+#[derive(Component)]
+pub struct Dash {
+    pub last: Option<u16>,  // tick of the last dash
+    pub cooldown: u16,  // ticks between dashes, from their class
+    pub held: Option<Vec2>,  // where the host put them back after a dash it didn't allow, until they agree
+}
+
+/// The bow, and whether it's what the player has out instead of their sword
+#[derive(Component)]
+pub struct Bow {
+    pub equipped: bool,
+    pub cooldown: Timer,
+}
+
+impl Dash {
+    pub fn ready(&self, tick: u16) -> bool {
+        return self.last.map_or(true, |last| tick.wrapping_sub(last) >= self.cooldown);
+    }
+
+    pub fn invulnerable(&self, tick: u16) -> bool {
+        return self.last.is_some_and(|last| tick.wrapping_sub(last) < DASH_IFRAME_TICKS);
+    }
+
+    /// seconds until the next dash
+    pub fn remaining(&self, tick: u16) -> f32 {
+        if self.ready(tick) { return 0. }
+        let since = tick.wrapping_sub(self.last.unwrap());
+        return (self.cooldown - since) as f32 * TICKLEN_S;
+    }
+}
+
+/// When a player last died and last spawned, and how long each of those holds them up, like Stagger.
+/// The host decides these and everyone else gets them in PlayerTicks
+#[derive(Component, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Respawn {
+    pub died: u16,
+    pub delay: u16,  // ticks after dying before they can spawn again
+    pub spawned: u16,
+    pub protection: u16,  // ticks after spawning that nothing can hurt them
+}
+
+impl Respawn {
+    pub fn die(&mut self, tick: u16, delay: u16) {
+        self.died = tick;
+        self.delay = delay;
+        self.protection = 0;
+    }
+
+    pub fn spawn(&mut self, tick: u16, protection: u16) {
+        self.spawned = tick;
+        self.protection = protection;
+    }
+
+    /// ticks until they're allowed to spawn
+    pub fn waiting(&self, tick: u16) -> u16 {
+        return self.delay.saturating_sub(tick.wrapping_sub(self.died));
+    }
+
+    pub fn protected(&self, tick: u16) -> bool {
+        return tick.wrapping_sub(self.spawned) < self.protection;
+    }
+
+    /// attacking gives up what's left of the protection
+    pub fn unprotect(&mut self) {
+        self.protection = 0;
+    }
+}
+
+/// How the host handles deaths and respawns, in ticks, pixels and percent
+#[derive(Resource)]
+pub struct SpawnRules {
+    pub delay: u16,
+    pub clearance: f32,
+    pub protection: u16,
+    pub loot_drop: u8,
+}
+
+impl SpawnRules {
+    pub fn with_delay(seconds: f32) -> SpawnRules {
+        return SpawnRules {
+            delay: (seconds.clamp(0., MAX_RESPAWN_DELAY) / TICKLEN_S).round() as u16,
+            clearance: SPAWN_CLEARANCE,
+            protection: (SPAWN_PROTECTION / TICKLEN_S).round() as u16,
+            loot_drop: DEFAULT_LOOT_DROP,
+        };
+    }
+}
+
+impl Default for SpawnRules {
+    fn default() -> SpawnRules {
+        return SpawnRules::with_delay(DEFAULT_RESPAWN_DELAY);
+    }
+}
+
+/// whether pos is at least clearance away from everyone in others
+pub fn spawn_clear(pos: Vec2, mut others: impl Iterator<Item = Vec2>, clearance: f32) -> bool {
+    return others.all(|other| other.distance(pos) >= clearance);
 }
 
 pub struct PlayerPlugin;
@@ -81,12 +272,22 @@ impl Plugin for PlayerPlugin{
                 ).run_if(in_state(AppState::Game)).run_if(is_host).before(net::host::fixed))
             .add_systems(Update, (
                 attack_input,
+                weapon_switch_input.before(attack_input),
+                interact_input,
                 shield_input,
+                dash_input.after(handle_move),
+                knockback_move.before(handle_move),
+                update_stagger_tint,
                 animate_sword,
                 handle_move,
                 update_score,
+                update_dash_display,
+                update_weapon_display,
+                update_shield_display,
+                update_respawn_display,
                 powerup_feedback,
                 handle_player_ticks.run_if(is_client),
+                undo_rejected_dash.run_if(is_client).after(dash_input),
                 ).run_if(in_state(AppState::Game)))
             .add_systems(FixedUpdate, (
                 attack_host.before(attack_simulate),
@@ -98,15 +299,19 @@ impl Plugin for PlayerPlugin{
                 update_buffer.before(attack_host),
                 attack_draw.after(attack_simulate),
                 shield_draw,
+                shield_simulate.after(shield_draw).before(attack_simulate).run_if(is_host),
                 health_simulate.after(spawn_simulate),
                 health_draw.after(health_simulate),
                 ).run_if(in_state(AppState::Game)).before(net::client::fixed).before(net::host::fixed))
             .add_systems(Update, handle_id_events.run_if(is_client).run_if(in_state(AppState::Connecting)))
-            .add_systems(OnEnter(AppState::Game), (spawn_players, reset_cooldowns))
+            .add_systems(OnEnter(AppState::Game), spawn_players)
+            .add_systems(Update, update_roster.run_if(in_state(AppState::Game)).before(handle_player_ticks))
             .add_systems(OnEnter(AppState::GameOver), remove_players.after(toggle_leaderboard).after(update_leaderboard))
             .add_event::<SetIdEvent>()
             .init_resource::<Events<AttackEvent>>()
+            .init_resource::<Events<InteractEvent>>()
             .init_resource::<Events<SpawnEvent>>()
+            .init_resource::<SpawnRules>()
             .add_event::<PlayerTickEvent>()
             .add_event::<UserCmdEvent>()
             .add_event::<LocalPlayerDeathEvent>()
@@ -114,86 +319,160 @@ impl Plugin for PlayerPlugin{
     }
 }
 
-pub fn reset_cooldowns(mut query: Query<&mut Cooldown, With<Player>>) {
-    for mut c in &mut query {
-        c.0.tick(Duration::from_secs_f32(100.));
-    }
+/// the entity sheet only has a few player sprites, so after that they get tinted
+pub fn player_color(id: u8) -> Color {
+    return PLAYER_TINTS[(id / PLAYER_SPRITES) as usize % PLAYER_TINTS.len()];
 }
 
-pub fn spawn_players(
-    mut commands: Commands,
-    entity_atlas: Res<Atlas>,
-    asset_server: Res<AssetServer>,
-    res_id: Res<PlayerId>
-) {
-    for i in 0..MAX_PLAYERS {
-        let pl;
-        pl = commands.spawn((
-            Player(i as u8),
+pub fn spawn_player(
+    commands: &mut Commands,
+    entity_atlas: &Atlas,
+    asset_server: &AssetServer,
+    weapons: &Weapons,
+    classes: &Classes,
+    id: u8,
+    class: u8,
+    local: bool,
+) -> Entity {
+    let def = classes.get(class);
+    let spu = StoredPowerUps {
+        power_ups: [0; NUM_POWERUPS],
+    };
+    // start off ready to swing
+    let swing = weapons.get(weapon::SWORD).cooldown(&spu, def);
+    let mut cooldown = Timer::new(swing, TimerMode::Once);
+    cooldown.tick(swing);
+    let pl = commands.spawn((
+        Player(id),
+        (
             PosBuffer(CircularBuffer::new()),
             DirBuffer(CircularBuffer::new()),
             EventBuffer(CircularBuffer::new()),
             HpBuffer(CircularBuffer::new()),
-            Stats {
-                score: 0,
-                enemies_killed: 0,
-                players_killed: 0,
-                camps_captured: 0,
-                deaths: 0,
-                kd_ratio: 0.
-            },
-            Health {
-                current: 0,
-                max: PLAYER_DEFAULT_HP,
-                dead: true
-            },
-            SpriteSheetBundle {
-                texture_atlas: entity_atlas.handle.clone(),
-                sprite: TextureAtlasSprite { index: entity_atlas.coord_to_index(i as i32, 0), ..default()},
-                visibility: Visibility::Hidden,
-                transform: Transform::from_xyz(0., 0., 1.),
+        ),
+        Stats {
+            score: 0,
+            enemies_killed: 0,
+            players_killed: 0,
+            assists: 0,
+            camps_captured: 0,
+            deaths: 0,
+            kd_ratio: 0.
+        },
+        Health {
+            current: 0,
+            max: def.hp,
+            dead: true
+        },
+        SpriteSheetBundle {
+            texture_atlas: entity_atlas.handle.clone(),
+            sprite: TextureAtlasSprite {
+                index: entity_atlas.coord_to_index((id % PLAYER_SPRITES) as i32, 0),
+                color: player_color(id),
                 ..default()
             },
-            Collider(PLAYER_SIZE),
-            Cooldown(Timer::from_seconds(DEFAULT_COOLDOWN, TimerMode::Once)),
-            StoredPowerUps {
-                power_ups: [0; NUM_POWERUPS],
-            },
-            PlayerShield {
-                active: false,
-            },
-        )).id();
-
-        if i as u8 == res_id.0 {
-            commands.entity(pl).insert(LocalPlayer);
-        }
-
-        let health_bar = commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load("healthbar.png"),
-                transform: Transform {
-                    translation: Vec3::new(0., 24., 2.),
-                    ..Default::default()
-                },
-                ..Default::default()},
-            HealthBar,
-        )).id();
-
-        let shield = commands.spawn(
-            (SpriteBundle {
-            texture: asset_server.load("shield01.png").clone(),
             visibility: Visibility::Hidden,
+            transform: Transform::from_xyz(0., 0., 1.),
+            ..default()
+        },
+        Collider(PLAYER_SIZE),
+        Cooldown(cooldown),
+        Weapon(weapon::SWORD),
+        spu,
+        (PlayerShield::new(), Stagger::default(), StatusEffects::default(), Hotbar::default(), Respawn::default(), DamageLedger::default(), PlayerClass(class), Team(NO_TEAM)),
+        Dash {
+            last: None,
+            cooldown: def.dash_ticks(),
+            held: None,
+        },
+        Bow {
+            equipped: false,
+            cooldown: Timer::from_seconds(def.bow, TimerMode::Once),
+        },
+        JitterBuffer::new(),
+    )).id();
+
+    if local {
+        commands.entity(pl).insert(LocalPlayer);
+    }
+
+    let health_bar = commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("healthbar.png"),
             transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.5),
+                translation: Vec3::new(0., 24., 2.),
                 ..Default::default()
             },
+            ..Default::default()},
+        HealthBar,
+    )).id();
+
+    let shield = commands.spawn(
+        (SpriteBundle {
+        texture: asset_server.load("shield01.png").clone(),
+        visibility: Visibility::Hidden,
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 0.5),
             ..Default::default()
-            },
-            Shield)
-        ).id();
+        },
+        ..Default::default()
+        },
+        Shield)
+    ).id();
+
+    commands.entity(pl).add_child(health_bar);
+    commands.entity(pl).add_child(shield);
+    status::spawn_status_icons(commands, pl);
+    return pl;
+}
+
+/// everyone else shows up as they join, so this is just us
+pub fn spawn_players(
+    mut commands: Commands,
+    entity_atlas: Res<Atlas>,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    chosen: Res<ChosenClass>,
+    res_id: Res<PlayerId>
+) {
+    if res_id.0 as usize >= MAX_PLAYERS { return }  // watching a replay
+    spawn_player(&mut commands, &entity_atlas, &asset_server, &weapons, &classes, res_id.0, chosen.0, true);
+}
 
-        commands.entity(pl).add_child(health_bar);
-        commands.entity(pl).add_child(shield);
+/// Spawns players when they join (or when we first hear about them) and despawns them when they leave
+pub fn update_roster(
+    mut commands: Commands,
+    entity_atlas: Res<Atlas>,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    res_id: Res<PlayerId>,
+    mut gameplay_reader: EventReader<GameplayEvent>,
+    mut player_reader: EventReader<PlayerTickEvent>,
+    players: Query<(Entity, &Player)>,
+) {
+    let mut roster: HashMap<u8, Entity> = players.iter().map(|(e, pl)| (pl.0, e)).collect();
+    // anyone who was already here when we joined only shows up in PlayerTicks
+    let mut joined: Vec<(u8, u8)> = player_reader.iter().map(|ev| (ev.tick.id, ev.tick.class)).collect();  // (player, class)
+    let mut left: Vec<u8> = Vec::new();
+    for ev in gameplay_reader.iter() {
+        match ev.0 {
+            GameEvent::PlayerJoined { player, class } => joined.push((player, class)),
+            GameEvent::PlayerLeft { player } => left.push(player),
+            _ => {}
+        }
+    }
+    for (id, class) in joined {
+        if id as usize >= MAX_PLAYERS || roster.contains_key(&id) || left.contains(&id) { continue }
+        let pl = spawn_player(&mut commands, &entity_atlas, &asset_server, &weapons, &classes, id, class, id == res_id.0);
+        roster.insert(id, pl);
+    }
+    for id in left {
+        if id == res_id.0 { continue }
+        if let Some(pl) = roster.remove(&id) {
+            commands.entity(pl).despawn_recursive();
+        }
     }
 }
 
@@ -220,24 +499,124 @@ pub fn update_score(
     text.sections[0].value = format!("Score: {}", stats.score);
     for (mut powerup, index) in &mut powerup_displays {
         if index.0 == PowerUpType::Meat as u8 {
-            powerup.sections[0].value = format!("{}%", 100. * hp.current as f32 / PLAYER_DEFAULT_HP as f32);
+            powerup.sections[0].value = format!("{}%", 100. * hp.current as f32 / hp.max as f32);
+        }
+    }
+}
+
+pub fn update_dash_display(
+    tick: Res<TickNum>,
+    players: Query<&Dash, With<LocalPlayer>>,
+    mut dash_displays: Query<&mut Text, With<DashDisplay>>,
+) {
+    let dash_display = dash_displays.get_single_mut();
+    let dash = players.get_single();
+    if dash_display.is_err() || dash.is_err() { return }
+    let mut text = dash_display.unwrap();
+    let remaining = dash.unwrap().remaining(tick.0);
+    if remaining > 0. {
+        text.sections[0].value = format!("Dash: {:.1}s", remaining);
+    }
+    else {
+        text.sections[0].value = "Dash: ready".to_string();
+    }
+}
+
+pub fn update_shield_display(
+    players: Query<&PlayerShield, (With<LocalPlayer>, Changed<PlayerShield>)>,
+    mut shield_displays: Query<&mut Text, With<ShieldDisplay>>,
+) {
+    let shield_display = shield_displays.get_single_mut();
+    let shield = players.get_single();
+    if shield_display.is_err() || shield.is_err() { return }
+    let mut text = shield_display.unwrap();
+    let shield = shield.unwrap();
+    if shield.broken {
+        text.sections[0].value = "Shield: broken".to_string();
+    }
+    else {
+        text.sections[0].value = format!("Shield: {}", shield.durability);
+    }
+}
+
+/// players flash while they're in hitstun, and are see-through while spawn protection lasts.
+/// On teams they're tinted their team's colour instead of their own
+pub fn update_stagger_tint(
+    tick: Res<TickNum>,
+    mut players: Query<(&Player, &Stagger, &Respawn, &Team, &mut TextureAtlasSprite)>,
+) {
+    for (pl, stagger, respawn, team, mut sprite) in &mut players {
+        if stagger.active(tick.0) {
+            sprite.color = STAGGER_TINT;
+        }
+        else {
+            sprite.color = team.tint().unwrap_or(player_color(pl.0));
+        }
+        if respawn.protected(tick.0) {
+            sprite.color.set_a(PROTECTED_ALPHA);
         }
     }
 }
 
+/// the countdown on the death screen, and then what to do once it's done
+pub fn update_respawn_display(
+    tick: Res<TickNum>,
+    players: Query<(&Health, &Respawn), With<LocalPlayer>>,
+    mut respawn_displays: Query<(&mut Text, &mut Visibility), With<RespawnDisplay>>,
+) {
+    let respawn_display = respawn_displays.get_single_mut();
+    let player = players.get_single();
+    if respawn_display.is_err() || player.is_err() { return }
+    let (mut text, mut vis) = respawn_display.unwrap();
+    let (hp, respawn) = player.unwrap();
+    if !hp.dead {
+        *vis = Visibility::Hidden;
+        return;
+    }
+    *vis = Visibility::Visible;
+    let waiting = respawn.waiting(tick.0);
+    if waiting > 0 {
+        text.sections[0].value = format!("Respawn in {:.1}s", waiting as f32 * TICKLEN_S);
+    }
+    else {
+        text.sections[0].value = "Click the map to respawn".to_string();
+    }
+}
+
+pub fn update_weapon_display(
+    weapons: Res<Weapons>,
+    players: Query<(&Bow, &Weapon), (With<LocalPlayer>, Or<(Changed<Bow>, Changed<Weapon>)>)>,
+    mut weapon_displays: Query<&mut Text, With<WeaponDisplay>>,
+) {
+    let weapon_display = weapon_displays.get_single_mut();
+    let player = players.get_single();
+    if weapon_display.is_err() || player.is_err() { return }
+    let mut text = weapon_display.unwrap();
+    let (bow, weapon) = player.unwrap();
+    if bow.equipped {
+        text.sections[0].value = "Weapon: Bow".to_string();
+    }
+    else {
+        text.sections[0].value = format!("Weapon: {}", weapons.get(weapon.0).name);
+    }
+}
+
 /// sets powerup ui text, if it changed from before play powerup collection sound
 pub fn powerup_feedback(
-    mut players: Query<(&Transform, &mut HpBuffer, &mut Cooldown, &mut StoredPowerUps), With<LocalPlayer>>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    players: Query<(&Cooldown, &StoredPowerUps, &Weapon, &PlayerClass), With<LocalPlayer>>,
     mut powerup_displays: Query<(&mut Text, &PowerupDisplayText)>,
 ) {
-    let mut player = players.get_single_mut();
+    let player = players.get_single();
     if player.is_err() { return }
-    let (tf, mut hb, mut cd, mut spu) = player.unwrap();
+    let (cd, spu, weapon, class) = player.unwrap();
+    let weapon = weapons.get(weapon.0);
+    let class = classes.get(class.0);
+    let no_powerups = StoredPowerUps { power_ups: [0; NUM_POWERUPS] };
     for (mut powerup, index) in &mut powerup_displays {
         if index.0 == PowerUpType::DamageDealtUp as u8 {
-            powerup.sections[0].value = format!("{:.2}x",
-                (SWORD_DAMAGE as f32 + spu.power_ups[PowerUpType::DamageDealtUp as usize] as f32 * DAMAGE_DEALT_UP as f32)
-                    / SWORD_DAMAGE as f32);
+            powerup.sections[0].value = format!("{:.2}x", weapon.damage(&spu, class) as f32 / weapon.damage(&no_powerups, class) as f32);
         }
         else if index.0 == PowerUpType::DamageReductionUp as u8 {
             powerup.sections[0].value = format!("{:.2}x",
@@ -246,13 +625,20 @@ pub fn powerup_feedback(
         }
         else if index.0 == PowerUpType::AttackSpeedUp as u8 {
             powerup.sections[0].value = format!("{:.2}x",
-                                                (DEFAULT_COOLDOWN
+                                                (weapon.cooldown * class.cooldown
                                                     / (cd.0.duration().as_millis() as f32 / 1000.)));
         }
         else if index.0 == PowerUpType::MovementSpeedUp as u8 {
-            powerup.sections[0].value = format!("{:.2}x",
-                                                (PLAYER_SPEED + (spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32))
-                                                    / PLAYER_SPEED);
+            powerup.sections[0].value = format!("{:.2}x", class.speed(&spu) / class.speed);
+        }
+        else if index.0 == PowerUpType::MaxHPUp as u8 {
+            powerup.sections[0].value = format!("{:.2}x", spu.max_hp(class.hp) as f32 / class.hp as f32);
+        }
+        else if index.0 == PowerUpType::Lifesteal as u8 {
+            powerup.sections[0].value = format!("{}%", spu.lifesteal(100));
+        }
+        else if index.0 == PowerUpType::CritChance as u8 {
+            powerup.sections[0].value = format!("{}%", spu.crit_chance());
         }
     }
 }
@@ -261,31 +647,44 @@ pub fn powerup_feedback(
 pub fn powerup_grab_simulate(
     mut commands: Commands,
     tick: Res<TickNum>,
-    asset_server: Res<AssetServer>,
-    mut player_query: Query<(&Transform, &mut HpBuffer, &mut Cooldown, &mut StoredPowerUps, Option<&LocalPlayer>), With<Player>>,
-    powerup_query: Query<(Entity, &Transform, &PowerUp), With<PowerUp>>,
+    mut player_query: Query<(&Player, &Transform, &mut HpBuffer, &mut Cooldown, &mut StoredPowerUps, &mut Hotbar)>,
+    powerup_query: Query<(Entity, &Transform, &PowerUp, &NetworkId), With<PowerUp>>,
+    mut grab_writer: EventWriter<PowerupGrabEvent>,
 ) {
-    for (player_transform, mut player_health, mut cooldown, mut player_power_ups, lp) in player_query.iter_mut() {
-        for (powerup_entity, powerup_transform, power_up) in powerup_query.iter() {
+    // despawns don't happen until later, so keep track of what's been taken this tick
+    let mut grabbed: Vec<Entity> = Vec::new();
+    for (pl, player_transform, mut player_health, mut cooldown, mut player_power_ups, mut hotbar) in player_query.iter_mut() {
+        // the dead don't pick anything up, including what they just dropped
+        if player_health.0.get(tick.0).unwrap_or(0) == 0 { continue }
+        for (powerup_entity, powerup_transform, power_up, id) in powerup_query.iter() {
+            if grabbed.contains(&powerup_entity) { continue }
             let player_pos = player_transform.translation.truncate();
             let powerup_pos = powerup_transform.translation.truncate();
             if player_pos.distance(powerup_pos) < 32. {
                 player_power_ups.power_ups[power_up.0 as usize] = player_power_ups.power_ups[power_up.0 as usize].saturating_add(1);
                 commands.entity(powerup_entity).despawn();
-                if power_up.0 == PowerUpType::Meat {
+                grabbed.push(powerup_entity);
+                // meat gets carried for later, and only eaten now if there's no room for it
+                if power_up.0 == PowerUpType::Meat && !hotbar.add(ItemType::Meat) {
                     let hp = player_health.0.get(tick.0).unwrap().saturating_add(MEAT_VALUE);
                     player_health.0.set(tick.0, Some(hp));
                 }
+                else if power_up.0 == PowerUpType::MaxHPUp {
+                    // the new health comes filled in
+                    let hp = player_health.0.get(tick.0).unwrap();
+                    if hp > 0 {
+                        player_health.0.set(tick.0, Some(hp.saturating_add(MAX_HP_UP)));
+                    }
+                }
                 else if power_up.0 == PowerUpType::AttackSpeedUp {
                     let updated_duration = cooldown.0.duration().mul_f32(1. / ATTACK_SPEED_UP);
                     cooldown.0.set_duration(updated_duration);
                 }
-                if lp.is_some() {
-                    commands.spawn(AudioBundle {
-                        source: asset_server.load("powerup.ogg"),
-                        ..default()
-                    });
-                }
+                grab_writer.send(PowerupGrabEvent {
+                    id: id.0,
+                    player: pl.0,
+                    pos: powerup_pos,
+                });
             }
         }
     }
@@ -296,13 +695,25 @@ pub fn attack_input(
     time: Res<Time>,
     tick: Res<TickNum>,
     mouse_button_inputs: Res<Input<MouseButton>>,
-    mut players: Query<(&mut Cooldown, &mut EventBuffer, &PlayerShield), With<LocalPlayer>>,
+    mut players: Query<(&mut Cooldown, &mut EventBuffer, &PlayerShield, &mut Bow, &Stagger, &StatusEffects), With<LocalPlayer>>,
 ) {
     let player = players.get_single_mut();
     if player.is_err() { return }
-    let (mut c, mut eb, shield) = player.unwrap();
-    c.0.tick(time.delta());
-    if shield.active { return }
+    let (mut c, mut eb, shield, mut bow, stagger, effects) = player.unwrap();
+    // haste and slow change how fast the cooldowns run down
+    let delta = time.delta().mul_f32(effects.speed(tick.0));
+    c.0.tick(delta);
+    bow.cooldown.tick(delta);
+    if shield.active || stagger.active(tick.0) { return }
+    if bow.equipped {
+        if !(mouse_button_inputs.pressed(MouseButton::Left) && bow.cooldown.finished()) {
+            return;
+        }
+        let events = eb.0.get(tick.0).unwrap_or(0);
+        eb.0.set(tick.0, Some(events | FIRE_BITFLAG));
+        bow.cooldown.reset();
+        return;
+    }
     if !(mouse_button_inputs.pressed(MouseButton::Left) && c.0.finished()) {
         return;
     }
@@ -320,7 +731,10 @@ pub fn attack_input(
 pub fn attack_host(
     players: Query<(&EventBuffer, &PlayerShield), With<LocalPlayer>>,
     tick: Res<TickNum>,
-    mut attack_writer: EventWriter<AttackEvent>
+    mut attack_writer: EventWriter<AttackEvent>,
+    mut fire_writer: EventWriter<FireEvent>,
+    mut interact_writer: EventWriter<InteractEvent>,
+    mut use_item_writer: EventWriter<UseItemEvent>,
 ) {
     let player = players.get_single();
     if player.is_err() { return }
@@ -334,15 +748,62 @@ pub fn attack_host(
             id: 0
         });
     }
+    if events.unwrap() & FIRE_BITFLAG != 0 {
+        fire_writer.send(FireEvent {
+            seq_num: tick.0,
+            id: 0
+        });
+    }
+    if events.unwrap() & INTERACT_BITFLAG != 0 {
+        interact_writer.send(InteractEvent {
+            seq_num: tick.0,
+            id: 0
+        });
+    }
+    if let Some(slot) = item::used_slot(events.unwrap()) {
+        use_item_writer.send(UseItemEvent {
+            seq_num: tick.0,
+            id: 0,
+            slot
+        });
+    }
+}
+
+pub fn interact_input(
+    tick: Res<TickNum>,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    mut players: Query<&mut EventBuffer, With<LocalPlayer>>,
+) {
+    let eb = players.get_single_mut();
+    if eb.is_err() { return }
+    if !keyboard_input.just_pressed(key_binds.interact) { return }
+    let mut eb = eb.unwrap();
+    let events = eb.0.get(tick.0).unwrap_or(0);
+    eb.0.set(tick.0, Some(events | INTERACT_BITFLAG));
+}
+
+/// swaps between the sword and the bow
+pub fn weapon_switch_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    mut players: Query<&mut Bow, With<LocalPlayer>>,
+) {
+    let bow = players.get_single_mut();
+    if bow.is_err() { return }
+    if !keyboard_input.just_pressed(key_binds.switch_weapon) { return }
+    let mut bow = bow.unwrap();
+    bow.equipped = !bow.equipped;
 }
 
 pub fn attack_draw(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     tick: Res<TickNum>,
-    players: Query<(Entity, &EventBuffer, &DirBuffer, &PlayerShield, Option<&LocalPlayer>)>,
+    weapons: Res<Weapons>,
+    players: Query<(Entity, &EventBuffer, &DirBuffer, &PlayerShield, &Weapon, Option<&LocalPlayer>)>,
 ) {
-    for (e, eb, db, shield, lp) in &players {
+    for (e, eb, db, shield, weapon, lp) in &players {
         let tick = if lp.is_some() { tick.0 } else { tick.0.saturating_sub(net::DELAY) };
         if shield.active { continue }
         let events = eb.0.get(tick);
@@ -352,6 +813,7 @@ pub fn attack_draw(
             if dir.is_none() { continue }
             let dir = dir.unwrap();
             let cursor_vector = Vec2 { x: dir.cos(), y: dir.sin() };
+            let weapon = weapons.get(weapon.0);
             commands.spawn(AudioBundle {
                 source: asset_server.load("player-swing.ogg"),
                 ..default()
@@ -359,7 +821,7 @@ pub fn attack_draw(
             commands.entity(e).with_children(|parent| {
                 parent.spawn((
                     SpriteBundle {
-                        texture: asset_server.load("sword01.png").into(),
+                        texture: asset_server.load(&weapon.sprite).into(),
                         visibility: Visibility::Hidden,
                         ..Default::default()
                     },
@@ -368,6 +830,9 @@ pub fn attack_draw(
                         current: 0.0,
                         cursor_vector,
                         max: TICKLEN_S,
+                        arc: weapon.arc,
+                        // the sprite sits a bit inside the reach, like the sword always did
+                        radius: weapon.reach * SWING_RADIUS,
                     })
                 );
             });
@@ -379,138 +844,97 @@ pub fn attack_simulate(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     tick: Res<TickNum>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
     mut attack_reader: EventReader<AttackEvent>,
-    mut players: Query<(&Player, &PosBuffer, &DirBuffer, &mut HpBuffer, &StoredPowerUps, &PlayerShield, &mut Stats), (Without<ItemChest>, Without<Enemy>)>,
-    mut enemies: Query<(&PosBuffer, &mut HpBuffer, &mut LastAttacker), With<Enemy>>,
-    mut chest: Query<(&Transform, &mut Health), (With<ItemChest>, Without<Enemy>)>,
+    mut players: Query<(&Player, &PosBuffer, &DirBuffer, &mut HpBuffer, &StoredPowerUps, &mut PlayerShield, &mut DamageLedger, &Dash, &Weapon, &mut Stagger, &mut StatusEffects, &mut Respawn, &PlayerClass, &Team), (Without<ItemChest>, Without<Enemy>)>,
+    mut enemies: Query<(&PosBuffer, &mut HpBuffer, &mut DamageLedger, &mut Stagger, &StatusEffects), (With<Enemy>, Without<Player>)>,
+    mut chest: Query<(&ItemChest, &Transform, &mut Health), Without<Enemy>>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ) {
     for ev in &mut attack_reader {
-        for (pl, pb, db, _, spu, shield, mut stats) in &players {
+        // where the attacker was, which way they faced, and what they swung, as they saw it
+        let mut swing = None;
+        for (pl, pb, db, _, spu, shield, _, _, weapon, stagger, _, mut respawn, class, team) in &mut players {
             if pl.0 != ev.id { continue }
-            if shield.active { continue }
+            if shield.active || stagger.active(tick.0) { continue }
+            respawn.unprotect();
             let sword_angle = db.0.get(ev.seq_num);
             let player_pos = pb.0.get(ev.seq_num);
             if sword_angle.is_none() || player_pos.is_none() { println!("attack_simulate:none"); continue }
-            let sword_angle = sword_angle.unwrap();
-            let player_pos = player_pos.unwrap();
-            for (enemy_pb, mut enemy_hb, mut last_attacker) in enemies.iter_mut() {
-                let enemy_pos = enemy_pb.0.get(ev.seq_num);
-                if enemy_pos.is_none() { println!("attack_simulate:enemynone"); continue }
-                let enemy_pos = enemy_pos.unwrap();
-                let hp = enemy_hb.0.get(tick.0).unwrap();
-                if hp <= 0 { continue }
-
-                let combat_angle = (enemy_pos - player_pos).y.atan2((enemy_pos - player_pos).x);
-                let angle_diff = sword_angle - combat_angle;
-                let angle_diff = angle_diff.sin().atan2(angle_diff.cos());
-                if player_pos.distance(enemy_pos) > SWORD_LENGTH { continue; } // enemy too far
-                if angle_diff.abs() > SWORD_DEGREES.to_radians() { continue; } // enemy not in sector
-                last_attacker.0 = Some(pl.0);
-                let damage = SWORD_DAMAGE.saturating_add(spu.power_ups[PowerUpType::DamageDealtUp as usize].saturating_mul(DAMAGE_DEALT_UP));
-                enemy_hb.0.set(tick.0, Some(hp.saturating_sub(damage)));
-                commands.spawn(AudioBundle {
-                    source: asset_server.load("hitHurt.ogg"),
-                    ..default()
-                });
-            }
-            for (chest_tf, mut chest_hp) in chest.iter_mut() {
-                let chest_pos = chest_tf.translation.truncate();
-                if player_pos.distance(chest_pos) > SWORD_LENGTH { continue; } // chest too far
-
-                let combat_angle = (chest_pos - player_pos).y.atan2((chest_pos - player_pos).x);
-                let angle_diff = sword_angle - combat_angle;
-                let angle_diff = angle_diff.sin().atan2(angle_diff.cos());
-                if angle_diff.abs() > SWORD_DEGREES.to_radians() { continue; } // chest not in sector
-
-                chest_hp.current = 0;
-                /*
-                TODO this only spawns on host?
-                commands.spawn(AudioBundle {
-                    source: asset_server.load("chest.ogg"),
-                    ..default()
-                });*/
+            let weapon = weapons.get(weapon.0);
+            let mut damage = weapon.damage(spu, classes.get(class.0));
+            if spu.crits(rand::thread_rng().gen_range(0..100)) {
+                damage = (damage as f32 * CRIT_MULTIPLIER).min(u8::MAX as f32) as u8;
             }
+            swing = Some((player_pos.unwrap(), sword_angle.unwrap(), weapon, damage, weapon.knockback(spu), *team));
         }
-        let mut combinations = players.iter_combinations_mut();
-        while let Some([(pl, pb, db, _, spu, attacker_shield, mut attacker_stats), (target_pl, target_pb, _, mut target_hb, target_spu, target_shield, mut target_stats)]) = combinations.fetch_next() {
-            if pl.0 != ev.id { continue }
-            if target_shield.active || attacker_shield.active { continue }
-            let sword_angle = db.0.get(ev.seq_num);
-            let player_pos = pb.0.get(ev.seq_num);
-            if sword_angle.is_none() || player_pos.is_none() { continue }
-            let sword_angle = sword_angle.unwrap();
-            let player_pos = player_pos.unwrap();
-            if target_pl.0 == ev.id { continue }
+        if swing.is_none() { continue }
+        let (player_pos, sword_angle, weapon, damage, push, team) = swing.unwrap();
+        // everything the swing took off of enemies and players, for lifesteal
+        let mut dealt: u16 = 0;
+        for (enemy_pb, mut enemy_hb, mut enemy_ledger, mut enemy_stagger, enemy_effects) in enemies.iter_mut() {
+            let enemy_pos = enemy_pb.0.get(ev.seq_num);
+            if enemy_pos.is_none() { println!("attack_simulate:enemynone"); continue }
+            let hp = enemy_hb.0.get(tick.0).unwrap();
+            if hp <= 0 { continue }
+            if !weapon.hits(player_pos, sword_angle, enemy_pos.unwrap()) { continue }
+            let next_hp = hp.saturating_sub(enemy_effects.damage_taken(damage, tick.0));
+            enemy_hb.0.set(tick.0, Some(next_hp));
+            enemy_ledger.record(Attacker::Player(ev.id), hp - next_hp, tick.0);
+            dealt += (hp - next_hp) as u16;
+            enemy_stagger.stun(tick.0, HITSTUN_TICKS, knockback(player_pos, enemy_pos.unwrap(), push));
+            commands.spawn(AudioBundle {
+                source: asset_server.load("hitHurt.ogg"),
+                ..default()
+            });
+        }
+        for (ic, chest_tf, mut chest_hp) in chest.iter_mut() {
+            if chest_hp.current == 0 { continue } // already open
+            if !weapon.hits(player_pos, sword_angle, chest_tf.translation.truncate()) { continue }
+            chest_hp.current = 0;
+            gameplay_writer.send(GameplayEvent(GameEvent::ChestOpened { chest: ic.id, opener: ev.id }));
+        }
+        // the attacker is in the same query as the targets, so their lifesteal and staggers wait until everyone's been hit
+        let mut parried_by = None;
+        for (target_pl, target_pb, _, mut target_hb, target_spu, mut target_shield, mut target_ledger, target_dash, _, mut target_stagger, target_effects, target_respawn, _, target_team) in &mut players {
+            // no friendly fire
+            if target_pl.0 == ev.id || team.allied(*target_team) { continue }
+            if target_dash.invulnerable(tick.0) || target_respawn.protected(tick.0) { continue }
             let target_pos = target_pb.0.get(ev.seq_num);
             if target_pos.is_none() { continue }
-            let target_pos = target_pos.unwrap();
-            if player_pos.distance(target_pos) > SWORD_LENGTH { continue; } // target too far
-
-            let combat_angle = (target_pos - player_pos).y.atan2((target_pos - player_pos).x);
-            let angle_diff = sword_angle - combat_angle;
-            let angle_diff = angle_diff.sin().atan2(angle_diff.cos());
-            if angle_diff.abs() > SWORD_DEGREES.to_radians() { continue; } // target not in sector
-
-            let damage = SWORD_DAMAGE.saturating_add(spu.power_ups[PowerUpType::DamageDealtUp as usize].saturating_mul(DAMAGE_DEALT_UP));
-            let hp = target_hb.0.get(tick.0).unwrap().saturating_sub(damage);
-            target_hb.0.set(tick.0, Some(hp));
-            if hp <= 0 {
-                target_stats.deaths = target_stats.deaths.saturating_add(1);
-                if target_stats.deaths != 0 {
-                    target_stats.kd_ratio = target_stats.players_killed as f32 / target_stats.deaths as f32;
-                }
-                else {
-                    target_stats.kd_ratio = target_stats.players_killed as f32;
+            if !weapon.hits(player_pos, sword_angle, target_pos.unwrap()) { continue }
+            let prev_hp = target_hb.0.get(tick.0).unwrap();
+            if prev_hp == 0 { continue } // already dead
+            let block = target_shield.block(tick.0, damage);
+            if block != Block::Hit {
+                if block == Block::Parried {
+                    parried_by = Some(target_pl.0);
                 }
-                attacker_stats.players_killed = attacker_stats.players_killed.saturating_add(1);
-                if attacker_stats.deaths != 0 {
-                    attacker_stats.kd_ratio = attacker_stats.players_killed as f32 / attacker_stats.deaths as f32;
-                }
-                else {
-                    attacker_stats.kd_ratio = attacker_stats.players_killed as f32;
-                }
-                attacker_stats.score = attacker_stats.score.saturating_add(20);
+                commands.spawn(AudioBundle {
+                    source: asset_server.load("shield.ogg"),
+                    ..default()
+                });
+                continue;
             }
+            let hp = prev_hp.saturating_sub(target_effects.damage_taken(damage, tick.0));
+            target_hb.0.set(tick.0, Some(hp));
+            target_ledger.record(Attacker::Player(ev.id), prev_hp - hp, tick.0);
+            dealt += (prev_hp - hp) as u16;
+            let push = push * DAMAGE_REDUCTION_UP.powi(target_spu.power_ups[PowerUpType::DamageReductionUp as usize] as i32);
+            target_stagger.stun(tick.0, HITSTUN_TICKS, knockback(player_pos, target_pos.unwrap(), push));
         }
-        let mut combinations = players.iter_combinations_mut();
-        while let Some([(target_pl, target_pb, _, mut target_hb, target_spu, target_shield, mut target_stats), (pl, pb, db, _, spu, attacker_shield, mut attacker_stats)]) = combinations.fetch_next() {
+        if parried_by.is_none() && dealt == 0 { continue }
+        for (pl, _, _, mut attacker_hb, attacker_spu, _, _, _, _, mut stagger, mut effects, _, class, _) in &mut players {
             if pl.0 != ev.id { continue }
-            if target_shield.active || attacker_shield.active { continue }
-            let sword_angle = db.0.get(ev.seq_num);
-            let player_pos = pb.0.get(ev.seq_num);
-            if sword_angle.is_none() || player_pos.is_none() { continue }
-            let sword_angle = sword_angle.unwrap();
-            let player_pos = player_pos.unwrap();
-            if target_pl.0 == ev.id { continue }
-            let target_pos = target_pb.0.get(ev.seq_num);
-            if target_pos.is_none() { continue }
-            let target_pos = target_pos.unwrap();
-            if player_pos.distance(target_pos) > SWORD_LENGTH { continue; } // target too far
-
-            let combat_angle = (target_pos - player_pos).y.atan2((target_pos - player_pos).x);
-            let angle_diff = sword_angle - combat_angle;
-            let angle_diff = angle_diff.sin().atan2(angle_diff.cos());
-            if angle_diff.abs() > SWORD_DEGREES.to_radians() { continue; } // target not in sector
-
-            let damage = SWORD_DAMAGE.saturating_add(spu.power_ups[PowerUpType::DamageDealtUp as usize].saturating_mul(DAMAGE_DEALT_UP));
-            let hp = target_hb.0.get(tick.0).unwrap().saturating_sub(damage);
-            target_hb.0.set(tick.0, Some(hp));
-            if hp <= 0 {
-                target_stats.deaths = target_stats.deaths.saturating_add(1);
-                if target_stats.deaths != 0 {
-                    target_stats.kd_ratio = target_stats.players_killed as f32 / target_stats.deaths as f32;
-                }
-                else {
-                    target_stats.kd_ratio = target_stats.players_killed as f32;
-                }
-                attacker_stats.players_killed = attacker_stats.players_killed.saturating_add(1);
-                if attacker_stats.deaths != 0 {
-                    attacker_stats.kd_ratio = attacker_stats.players_killed as f32 / attacker_stats.deaths as f32;
-                }
-                else {
-                    attacker_stats.kd_ratio = attacker_stats.players_killed as f32;
-                }
-                attacker_stats.score += 20;
+            let hp = attacker_hb.0.get(tick.0).unwrap_or(0);
+            let stolen = attacker_spu.lifesteal(dealt);
+            if hp > 0 && stolen > 0 {
+                attacker_hb.0.set(tick.0, Some(hp.saturating_add(stolen).min(attacker_spu.max_hp(classes.get(class.0).hp))));
+            }
+            if let Some(parrier) = parried_by {
+                stagger.stun(tick.0, STAGGER_TICKS, Vec2::ZERO);
+                effects.add(tick.0, StatusEffect::new(StatusType::Vulnerability, PARRY_VULNERABILITY, tick.0, PARRY_VULNERABILITY_SECONDS, Attacker::Player(parrier)));
             }
         }
     }
@@ -521,15 +945,15 @@ pub fn animate_sword(
     mut query: Query<(&mut Transform, &mut Visibility, &mut SwordAnimation), With<PlayerWeapon>>,
 ) {
     for (mut tf, mut vis, mut anim) in query.iter_mut() {
-        let attack_radius = 50.0;
+        let attack_radius = anim.radius;
         let current_step = anim.current / anim.max;
 
         let cursor_angle = anim.cursor_vector.y.atan2(anim.cursor_vector.x);
         let sword_translation_angle;
         if anim.cursor_vector.x > 0.0 {
-            sword_translation_angle = current_step * SWORD_DEGREES.to_radians() * 2.0 - SWORD_DEGREES.to_radians() - cursor_angle;
+            sword_translation_angle = current_step * anim.arc.to_radians() * 2.0 - anim.arc.to_radians() - cursor_angle;
         } else {
-            sword_translation_angle = current_step * SWORD_DEGREES.to_radians() * 2.0 - SWORD_DEGREES.to_radians() + cursor_angle;
+            sword_translation_angle = current_step * anim.arc.to_radians() * 2.0 - anim.arc.to_radians() + cursor_angle;
         }
         let sword_rotation_vector = Vec3::new(sword_translation_angle.cos(), sword_translation_angle.sin(), 0.0);
         let sword_rotation_angle = sword_rotation_vector.y.atan2(sword_rotation_vector.x);
@@ -556,11 +980,11 @@ pub fn animate_sword(
 pub fn shield_input(
     tick: Res<TickNum>,
     mouse_button_inputs: Res<Input<MouseButton>>,
-    mut players: Query<(&mut EventBuffer, &mut PlayerShield), With<LocalPlayer>>
+    mut players: Query<(&mut EventBuffer, &PlayerShield), With<LocalPlayer>>
 ) {
-    for (mut eb, mut shield) in &mut players {
+    for (mut eb, shield) in &mut players {
         let events = if eb.0.get(tick.0).is_some() {eb.0.get(tick.0).unwrap()} else {0};
-        if mouse_button_inputs.pressed(MouseButton::Right) {
+        if mouse_button_inputs.pressed(MouseButton::Right) && !shield.broken {
             eb.0.set(tick.0, Some(events | SHIELD_BITFLAG));
         }
         else {
@@ -571,19 +995,29 @@ pub fn shield_input(
 
 pub fn shield_draw(
     tick: Res<TickNum>,
-    mut players: Query<(&EventBuffer, &mut PlayerShield, &Children)>,
+    is_host: Res<IsHost>,
+    mut players: Query<(&EventBuffer, &mut PlayerShield, &Children, Option<&LocalPlayer>)>,
     mut shields: Query<&mut Visibility, With<Shield>>,
 ) {
-    for (eb, mut ps, children) in &mut players {
+    for (eb, mut ps, children, local) in &mut players {
+        // the host raises and lowers everyone else's shield as their ClientTicks come in, see handle_usercmd_events
+        if !is_host.0 || local.is_some() {
+            // nothing in the buffer means a lost packet, which isn't the same as letting go
+            let events = eb.0.get(tick.0.saturating_sub(net::DELAY));
+            if events.is_some_and(|events| events & SHIELD_BITFLAG != 0) {
+                ps.raise(tick.0);
+            }
+            else if events.is_some() {
+                ps.active = false;
+            }
+        }
         for child in children.iter() {
             let vis = shields.get_mut(*child);
             if let Ok(mut vis) = vis {
-                if eb.0.get(tick.0.saturating_sub(net::DELAY)).unwrap_or(0) & SHIELD_BITFLAG != 0 {
-                    ps.active = true;
+                if ps.active {
                     *vis = Visibility::Visible;
                 }
                 else {
-                    ps.active = false;
                     *vis = Visibility::Hidden;
                 }
             }
@@ -591,15 +1025,42 @@ pub fn shield_draw(
     }
 }
 
+/// shields that are down get their durability back, on the host
+pub fn shield_simulate(
+    classes: Res<Classes>,
+    mut players: Query<(&mut PlayerShield, &PlayerClass)>,
+) {
+    for (mut shield, class) in &mut players {
+        if shield.active || shield.durability == SHIELD_MAX { continue }
+        let durability = shield.durability.saturating_add(classes.get(class.0).shield_regen).min(SHIELD_MAX);
+        shield.set_durability(durability);
+    }
+}
+
 pub fn spawn_simulate(
     tick: Res<TickNum>,
     mut spawn_reader: EventReader<SpawnEvent>,
-    mut players: Query<(&Player, &mut HpBuffer)>
+    rules: Res<SpawnRules>,
+    classes: Res<Classes>,
+    mut players: Query<(&Player, &mut HpBuffer, &StoredPowerUps, &PlayerClass, &mut Respawn)>,
+    others: Query<(&Player, &Transform, &Health)>,
+    enemies: Query<(&Transform, &Health), With<Enemy>>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ) {
     for ev in &mut spawn_reader {
-        for (pl, mut hb) in &mut players {
+        // nobody gets to appear on top of someone, least of all whoever just killed them
+        let living_players = others.iter().filter(|(pl, _, hp)| pl.0 != ev.id && !hp.dead).map(|(_, tf, _)| tf.translation.truncate());
+        let living_enemies = enemies.iter().filter(|(_, hp)| hp.current > 0).map(|(tf, _)| tf.translation.truncate());
+        if !spawn_clear(ev.pos, living_players.chain(living_enemies), rules.clearance) {
+            println!("player {} tried to spawn too close to someone", ev.id);
+            gameplay_writer.send(GameplayEvent(GameEvent::SpawnRejected { player: ev.id }));
+            continue;
+        }
+        for (pl, mut hb, spu, class, mut respawn) in &mut players {
             if pl.0 != ev.id { continue }
-            hb.0.set(tick.0, Some(PLAYER_DEFAULT_HP));
+            if hb.0.get(tick.0).unwrap_or(0) > 0 || respawn.waiting(tick.0) > 0 { continue }
+            hb.0.set(tick.0, Some(spu.max_hp(classes.get(class.0).hp)));
+            respawn.spawn(tick.0, rules.protection);
         }
     }
     spawn_reader.clear();
@@ -607,17 +1068,26 @@ pub fn spawn_simulate(
 
 pub fn health_simulate(
     tick: Res<TickNum>,
-    mut players: Query<(&HpBuffer, &mut Health, &mut Visibility, &mut Stats, Option<&LocalPlayer>)>,
+    is_host: Res<IsHost>,
+    rules: Res<SpawnRules>,
+    mut players: Query<(&Player, &HpBuffer, &mut StoredPowerUps, &mut Health, &mut Visibility, &mut Stats, &mut Respawn, Option<&LocalPlayer>, &Transform, &Weapon, &mut Cooldown, &mut DamageLedger, &PlayerClass)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    powerup_atlas: Res<PowerupAtlas>,
+    mut next_id: ResMut<NextNetworkId>,
     mut death_writer: EventWriter<LocalPlayerDeathEvent>,
     mut spawn_writer: EventWriter<LocalPlayerSpawnEvent>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ) {
-    for (hb, mut hp, mut vis, mut stats, lp) in &mut players {
+    let mut kills: Vec<(Attacker, u16)> = Vec::new();  // (killer, assists)
+    for (pl, hb, mut spu, mut hp, mut vis, mut stats, mut respawn, lp, tf, weapon, mut cooldown, mut ledger, class) in &mut players {
         let next_hp = hb.0.get(tick.0);
         if next_hp.is_none() { continue }
+        let class = classes.get(class.0);
         hp.current = next_hp.unwrap();
-        hp.max = PLAYER_DEFAULT_HP;
+        hp.max = spu.max_hp(class.hp);
         if hp.current > 0 && hp.dead {
             hp.dead = false;
             if lp.is_some() {
@@ -632,11 +1102,64 @@ pub fn health_simulate(
             });
             hp.dead = true;
             *vis = Visibility::Hidden;
+            // clients hear how long they have to wait, and who gets the credit, from the host
+            if is_host.0 {
+                respawn.die(tick.0, rules.delay);
+                drop_loot(&mut commands, &powerup_atlas, &mut next_id, &mut spu, tf.translation.truncate(), rules.loot_drop);
+                cooldown.0.set_duration(weapons.get(weapon.0).cooldown(&spu, class));
+                stats.deaths = stats.deaths.saturating_add(1);
+                stats.update_kd();
+                if let Some(killer) = ledger.killer(tick.0) {
+                    let assists = ledger.assists(tick.0);
+                    gameplay_writer.send(GameplayEvent(GameEvent::PlayerKilled { victim: pl.0, attacker: killer, assists }));
+                    kills.push((killer, assists));
+                }
+                ledger.0.clear();
+            }
             if lp.is_some() {
                 death_writer.send(LocalPlayerDeathEvent);
             }
         }
     }
+    // the killers are in the same query as their victims, so their credit waits until everyone's been looked at
+    for (killer, assists) in kills {
+        for (pl, _, _, _, _, mut stats, _, _, _, _, _, _, _) in &mut players {
+            if killer == Attacker::Player(pl.0) {
+                stats.players_killed = stats.players_killed.saturating_add(1);
+                stats.score = stats.score.saturating_add(KILL_SCORE);
+            }
+            else if assists & (1 << pl.0) != 0 {
+                stats.assists = stats.assists.saturating_add(1);
+                stats.score = stats.score.saturating_add(ASSIST_SCORE);
+            }
+            else {
+                continue;
+            }
+            stats.update_kd();
+        }
+    }
+}
+
+/// spreads a share of a dead player's powerups in a ring around where they died for anyone to pick up.
+/// The host sends them out like any other powerup
+fn drop_loot(
+    commands: &mut Commands,
+    powerup_atlas: &PowerupAtlas,
+    next_id: &mut NextNetworkId,
+    spu: &mut StoredPowerUps,
+    pos: Vec2,
+    percent: u8,
+) {
+    let taken = spu.take_share(percent);
+    let total: usize = taken.iter().map(|&count| count as usize).sum();
+    let mut n = 0;
+    for (index, &count) in taken.iter().enumerate() {
+        for _ in 0..count {
+            let angle = std::f32::consts::TAU * n as f32 / total as f32;
+            powerup::spawn_powerup(commands, powerup_atlas, next_id.next(), POWERUP_TYPES[index], pos + Vec2::from_angle(angle) * LOOT_SCATTER);
+            n += 1;
+        }
+    }
 }
 
 pub fn health_draw(
@@ -657,30 +1180,36 @@ pub fn health_draw(
 
 pub fn handle_player_ticks(
     tick: Res<TickNum>,
+    time: Res<Time>,
     mut player_reader: EventReader<PlayerTickEvent>,
-    mut player_query: Query<(&Player, &mut PosBuffer, &mut HpBuffer, &mut DirBuffer, &mut EventBuffer, &mut PlayerShield, &mut Stats, &mut StoredPowerUps, &mut Cooldown, Option<&LocalPlayer>)>,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    mut player_query: Query<(&Player, &mut PosBuffer, &mut HpBuffer, &mut DirBuffer, &mut EventBuffer, &mut PlayerShield, &mut Stats, &mut StoredPowerUps, &mut Cooldown, &mut Weapon, (&mut Stagger, &mut StatusEffects, &mut Hotbar, &mut Respawn, &PlayerClass, &mut Team), &mut JitterBuffer, Option<&LocalPlayer>)>,
 ) {
     for ev in player_reader.iter() {
-        for (pl, mut pb, mut hb, mut db, mut eb, mut shield, mut stats, mut spu, mut cooldown, local) in &mut player_query {
+        for (pl, mut pb, mut hb, mut db, mut eb, mut shield, mut stats, mut spu, mut cooldown, mut weapon, (mut stagger, mut effects, mut hotbar, mut respawn, class, mut team), mut jb, local) in &mut player_query {
             if pl.0 == ev.tick.id {
                 *stats = ev.tick.stats.clone();
+                effects.0 = ev.tick.effects.clone();
+                *hotbar = ev.tick.hotbar.clone();
+                *respawn = ev.tick.respawn;
+                team.0 = ev.tick.team;
+                shield.set_durability(ev.tick.shield);
+                // the host keeps sending the last hit, but we only get pushed by it once.
+                // Everyone else's position already has their push in it
+                if stagger.since != ev.tick.stagger.since || stagger.ticks != ev.tick.stagger.ticks || local.is_none() {
+                    *stagger = ev.tick.stagger;
+                }
 
                 let prev = spu.clone();
                 *spu = ev.tick.powerups.clone();
-                if prev != *spu {
-                    if prev.power_ups[PowerUpType::AttackSpeedUp as usize] !=
-                        spu.power_ups[PowerUpType::AttackSpeedUp as usize] {
-                        let updated_duration = DEFAULT_COOLDOWN * (1. / ATTACK_SPEED_UP).powi(spu.power_ups[PowerUpType::AttackSpeedUp as usize] as i32);
-                        cooldown.0.set_duration(Duration::from_secs_f32(updated_duration));
-                    }
-                    commands.spawn(AudioBundle {
-                        source: asset_server.load("powerup.ogg"),
-                        ..default()
-                    });
+                if prev.power_ups[PowerUpType::AttackSpeedUp as usize] != spu.power_ups[PowerUpType::AttackSpeedUp as usize]
+                    || weapon.0 != ev.tick.weapon {
+                    weapon.0 = ev.tick.weapon;
+                    cooldown.0.set_duration(weapons.get(weapon.0).cooldown(&spu, classes.get(class.0)));
                 }
-                pb.0.set(ev.seq_num, Some(ev.tick.pos));
+                pb.0.set_with_time(ev.seq_num, Some(ev.tick.pos), ev.seq_num);
+                jb.observe(ev.seq_num, time.elapsed_seconds());
                 hb.0.set(tick.0, Some(ev.tick.hp));
                 db.0.set(ev.seq_num, Some(ev.tick.dir));
                 if local.is_none() {
@@ -708,25 +1237,70 @@ pub fn handle_id_events(
 }
 
 pub fn handle_usercmd_events(
+    time: Res<Time>,
+    map: Res<WorldMap>,
     mut usercmd_reader: EventReader<UserCmdEvent>,
-    mut player_query: Query<(&Player, &mut PosBuffer, &mut DirBuffer, &mut EventBuffer, &mut PlayerShield)>,
+    mut player_query: Query<(&Player, &mut PosBuffer, &mut DirBuffer, &mut EventBuffer, &mut PlayerShield, &mut JitterBuffer, &mut Dash)>,
     mut attack_writer: EventWriter<AttackEvent>,
+    mut fire_writer: EventWriter<FireEvent>,
+    mut interact_writer: EventWriter<InteractEvent>,
+    mut use_item_writer: EventWriter<UseItemEvent>,
     mut spawn_writer: EventWriter<SpawnEvent>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
 ) {
     for ev in usercmd_reader.iter() {
-        for (pl, mut pb, mut db, mut eb, mut shield) in &mut player_query {
+        for (pl, mut pb, mut db, mut eb, mut shield, mut jb, mut dash) in &mut player_query {
             if pl.0 == ev.id {
-                pb.0.set_with_time(ev.seq_num, Some(ev.tick.pos), ev.seq_num);
+                let mut pos = ev.tick.pos;
+                if let Some(held) = dash.held {
+                    // until they've heard they were put back, everything they say about where they are is off by the dash
+                    if pos.distance(held) > DASH_HOLD_SLACK {
+                        pos = held;
+                    }
+                    else {
+                        dash.held = None;
+                    }
+                }
+                if ev.tick.events & DASH_BITFLAG != 0 {
+                    // they've already moved themselves, this just keeps the dash honest about walls and the cooldown
+                    let from = pb.0.get(ev.seq_num.wrapping_sub(1)).unwrap_or(pos);
+                    if dash.ready(ev.seq_num) {
+                        pos = dash_destination(from, (pos - from).normalize_or_zero(), &map.biome_map);
+                        dash.last = Some(ev.seq_num);
+                    }
+                    else {
+                        pos = from;
+                        dash.held = Some(from);
+                        gameplay_writer.send(GameplayEvent(GameEvent::DashRejected { player: ev.id, pos: from }));
+                    }
+                }
+                pb.0.set_with_time(ev.seq_num, Some(pos), ev.seq_num);
+                jb.observe(ev.seq_num, time.elapsed_seconds());
                 db.0.set(ev.seq_num, Some(ev.tick.dir));
                 eb.0.set(ev.seq_num, Some(ev.tick.events));
                 if ev.tick.events & ATTACK_BITFLAG != 0 {
                     attack_writer.send(AttackEvent { seq_num: ev.seq_num, id: ev.id });
                 }
+                if ev.tick.events & FIRE_BITFLAG != 0 {
+                    fire_writer.send(FireEvent { seq_num: ev.seq_num, id: ev.id });
+                }
+                if ev.tick.events & INTERACT_BITFLAG != 0 {
+                    interact_writer.send(InteractEvent { seq_num: ev.seq_num, id: ev.id });
+                }
+                if let Some(slot) = item::used_slot(ev.tick.events) {
+                    use_item_writer.send(UseItemEvent { seq_num: ev.seq_num, id: ev.id, slot });
+                }
                 if ev.tick.events & SPAWN_BITFLAG != 0 {
-                    spawn_writer.send(SpawnEvent { id: ev.id });
+                    spawn_writer.send(SpawnEvent { id: ev.id, pos });
                 }
+                // the parry window starts on the tick they pressed it, as we play it back DELAY ticks later,
+                // not whenever their packet happened to get here
+                let played = ev.seq_num.wrapping_add(net::DELAY);
                 if ev.tick.events & SHIELD_BITFLAG != 0 {
-                    shield.active = true;
+                    shield.raise(played);
+                }
+                else if shield.raised.map_or(true, |raised| net::newer(played, raised)) {
+                    shield.active = false;
                 }
             }
         }
@@ -740,4 +1314,65 @@ pub fn local_player_dead(health: Query<&Health, With<LocalPlayer>>) -> bool {
     if health.is_err() { return false; }
     let health = health.unwrap();
     return health.dead;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respawn_waits_then_protects_until_attacking() {
+        let mut respawn = Respawn::default();
+        assert_eq!(respawn.waiting(0), 0);
+        assert!(!respawn.protected(0));
+        respawn.die(65530, 20);
+        assert_eq!(respawn.waiting(65535), 15);
+        assert_eq!(respawn.waiting(14), 0);
+        respawn.spawn(14, 30);
+        assert!(respawn.protected(43));
+        assert!(!respawn.protected(44));
+        respawn.unprotect();
+        assert!(!respawn.protected(15));
+    }
+
+    #[test]
+    fn spawns_need_room() {
+        let others = [Vec2::new(100., 0.), Vec2::new(0., 300.)];
+        assert!(spawn_clear(Vec2::ZERO, others.iter().copied(), 100.));
+        assert!(!spawn_clear(Vec2::ZERO, others.iter().copied(), 101.));
+        assert!(spawn_clear(Vec2::ZERO, std::iter::empty(), SPAWN_CLEARANCE));
+    }
+
+    #[test]
+    fn parry_only_right_after_raising() {
+        let mut shield = PlayerShield::new();
+        shield.raise(100);
+        assert_eq!(shield.block(100 + PARRY_TICKS - 1, 40), Block::Parried);
+        assert_eq!(shield.durability, SHIELD_MAX);
+        assert_eq!(shield.block(100 + PARRY_TICKS, 40), Block::Blocked);
+        assert_eq!(shield.durability, SHIELD_MAX - 40);
+    }
+
+    #[test]
+    fn lowered_shield_doesnt_block() {
+        let mut shield = PlayerShield::new();
+        assert_eq!(shield.block(5, 40), Block::Hit);
+        assert_eq!(shield.durability, SHIELD_MAX);
+    }
+
+    #[test]
+    fn breaks_and_recovers() {
+        let mut shield = PlayerShield::new();
+        shield.raise(0);
+        shield.block(10, 255);
+        assert!(shield.broken && !shield.active);
+        shield.raise(20);
+        assert!(!shield.active);
+        shield.set_durability(SHIELD_RECOVER - 1);
+        assert!(shield.broken);
+        shield.set_durability(SHIELD_RECOVER);
+        assert!(!shield.broken);
+        shield.raise(30);
+        assert!(shield.active);
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::camera::SpatialCameraBundle;
+use crate::game::components::{NetworkId, Player, PowerUp, PowerUpType};
+use crate::game::player::LocalPlayer;
+use crate::game::PowerupAtlas;
+
+pub const NO_GRABBER: u8 = 0xFF;  // the powerup went away without anyone picking it up
+const HEARING_DISTANCE: f32 = 640.;
+const OTHER_GRAB_VOLUME: f32 = 0.3;
+
+/// Hands out network ids on the host. Clients remember every id they've seen go, so this assumes a match
+/// never makes more than u16::MAX powerups. After a wrap a reused id would be taken for the old one and ignored
+#[derive(Resource)]
+pub struct NextNetworkId(pub u16);
+
+impl NextNetworkId {
+    pub fn next(&mut self) -> NetworkId {
+        let id = NetworkId(self.0);
+        self.0 = self.0.wrapping_add(1);
+        return id;
+    }
+}
+
+/// Client side map of network id -> (entity, type, position) we spawned for it, or None once it's gone.
+/// Gone ids are kept so a late or duplicated spawn can't bring a powerup back
+#[derive(Resource, Default)]
+pub struct NetworkedPowerups(pub HashMap<u16, Option<(Entity, PowerUpType, Vec2)>>);
+
+/// Sent on the host and on clients whenever somebody picks a powerup up
+#[derive(Event)]
+pub struct PowerupGrabEvent {
+    pub id: u16,
+    pub player: u8,
+    pub pos: Vec2,
+}
+
+pub struct PowerupPlugin;
+
+impl Plugin for PowerupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, startup)
+            .add_systems(Update, grab_effects.run_if(in_state(AppState::Game)))
+            .add_systems(OnExit(AppState::Game), remove_powerups)
+            .add_event::<PowerupGrabEvent>();
+    }
+}
+
+fn startup(mut commands: Commands) {
+    commands.insert_resource(NextNetworkId(0));
+    commands.insert_resource(NetworkedPowerups::default());
+}
+
+pub fn spawn_powerup(
+    commands: &mut Commands,
+    powerup_atlas: &PowerupAtlas,
+    id: NetworkId,
+    ptype: PowerUpType,
+    pos: Vec2,
+) -> Entity {
+    return commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: powerup_atlas.handle.clone(),
+            sprite: TextureAtlasSprite {
+                index: powerup_atlas.coord_to_index(0, ptype as i32),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: Vec3::new(pos.x, pos.y, 1.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        PowerUp(ptype),
+        id,
+    )).id();
+}
+
+/// plays the pickup sound, loud for our own grabs and quieter for anyone else's we're close enough to hear
+pub fn grab_effects(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut grab_reader: EventReader<PowerupGrabEvent>,
+    local_player: Query<&Player, With<LocalPlayer>>,
+    cameras: Query<&Transform, With<SpatialCameraBundle>>,
+) {
+    let local_id = local_player.get_single().ok().map(|pl| pl.0);
+    let listener = cameras.get_single().ok().map(|tf| tf.translation.truncate());
+    for ev in grab_reader.iter() {
+        let volume;
+        if local_id == Some(ev.player) {
+            volume = 1.;
+        }
+        else if listener.is_some_and(|listener| listener.distance(ev.pos) < HEARING_DISTANCE) {
+            volume = OTHER_GRAB_VOLUME;
+        }
+        else {
+            continue;
+        }
+        commands.spawn(AudioBundle {
+            source: asset_server.load("powerup.ogg"),
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(volume)),
+        });
+    }
+}
+
+pub fn remove_powerups(
+    mut commands: Commands,
+    mut networked: ResMut<NetworkedPowerups>,
+    powerups: Query<Entity, With<PowerUp>>,
+) {
+    for e in &powerups {
+        commands.entity(e).despawn();
+    }
+    networked.0.clear();
+}
@@ -0,0 +1,341 @@
+//! Things that fly. The host moves them and decides what they hit,
+//! clients only draw them where the host says they are
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::{CircularBuffer, DirBuffer, HpBuffer, PosBuffer};
+use crate::game::components::*;
+use crate::game::{enemy, player};
+use crate::game::events::{Attacker, GameEvent, GameplayEvent};
+use crate::game::map::{self, Biome, WorldMap};
+use crate::game::player::{Block, Dash, PlayerShield, Respawn, PLAYER_SIZE};
+use crate::net::{self, is_client, is_host, TICKLEN_S, TickNum};
+use crate::net::lerp::JitterBuffer;
+use crate::net::packets::ProjectileTick;
+use crate::game::status::StatusEffects;
+use crate::game::team::{Team, NO_TEAM};
+
+const PROJECTILE_Z: f32 = 3.;
+
+/// Everything about a kind of projectile. Adding one means adding a variant here and a tag in packets.rs
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProjectileKind {
+    Arrow,
+}
+
+impl ProjectileKind {
+    /// pixels per second
+    pub fn speed(&self) -> f32 {
+        return match self {
+            ProjectileKind::Arrow => 600.,
+        };
+    }
+
+    /// ticks before it falls out of the air
+    pub fn lifetime(&self) -> u16 {
+        return match self {
+            ProjectileKind::Arrow => 8,
+        };
+    }
+
+    pub fn damage(&self) -> u8 {
+        return match self {
+            ProjectileKind::Arrow => 25,
+        };
+    }
+
+    /// for hit tests, it's treated as a circle
+    pub fn radius(&self) -> f32 {
+        return match self {
+            ProjectileKind::Arrow => 4.,
+        };
+    }
+
+    fn sprite(&self) -> Sprite {
+        return match self {
+            ProjectileKind::Arrow => Sprite {
+                color: Color::rgb(0.9, 0.8, 0.55),
+                custom_size: Some(Vec2::new(14., 3.)),
+                ..default()
+            },
+        };
+    }
+}
+
+/// Host side state of something in flight
+#[derive(Component)]
+pub struct Projectile {
+    pub id: u16,
+    pub kind: ProjectileKind,
+    pub owner: Attacker,
+    pub pos: Vec2,
+    pub dir: f32,
+    pub expires: u16,  // tick it disappears on if it hasn't hit anything
+}
+
+/// Hands out projectile ids on the host. They only get sent while the projectile is alive,
+/// so wrapping around is fine as long as nothing lives for 65536 projectiles
+#[derive(Resource)]
+pub struct NextProjectileId(pub u16);
+
+/// Client side map of projectile id -> the entity we're drawing for it
+#[derive(Resource, Default)]
+pub struct NetworkedProjectiles {
+    pub entities: HashMap<u16, Entity>,
+    newest: Option<u16>,  // seq_num of the newest HostTick we've applied, older ones can't despawn anything
+}
+
+/// someone wants to shoot, seq_num is the tick they pressed the button on
+#[derive(Event)]
+pub struct FireEvent {
+    pub seq_num: u16,
+    pub id: u8,
+}
+
+/// every projectile the host told us about in one HostTick
+#[derive(Event)]
+pub struct ProjectileTickEvent {
+    pub seq_num: u16,
+    pub projectiles: Vec<ProjectileTick>,
+}
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, startup)
+            .add_systems(FixedUpdate, (
+                fire_simulate,
+                move_projectiles.after(fire_simulate),
+            ).run_if(in_state(AppState::Game)).run_if(is_host).after(enemy::fixed_move).after(player::attack_host).before(crate::net::host::fixed))
+            .add_systems(Update, handle_projectile_ticks.run_if(is_client).run_if(in_state(AppState::Game)))
+            .add_systems(OnExit(AppState::Game), remove_projectiles)
+            // read in FixedUpdate, so they're cleared by hand like AttackEvent
+            .init_resource::<Events<FireEvent>>()
+            .add_event::<ProjectileTickEvent>();
+    }
+}
+
+fn startup(mut commands: Commands) {
+    commands.insert_resource(NextProjectileId(0));
+    commands.insert_resource(NetworkedProjectiles::default());
+}
+
+fn projectile_bundle(kind: ProjectileKind, pos: Vec2, dir: f32) -> SpriteBundle {
+    return SpriteBundle {
+        sprite: kind.sprite(),
+        transform: Transform {
+            translation: pos.extend(PROJECTILE_Z),
+            rotation: Quat::from_rotation_z(dir),
+            ..default()
+        },
+        ..default()
+    };
+}
+
+/// Starts something flying on the host. Anything can use this, enemies included, the owner just can't hit itself
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    next_id: &mut NextProjectileId,
+    tick: u16,
+    kind: ProjectileKind,
+    owner: Attacker,
+    pos: Vec2,
+    dir: f32,
+) -> Entity {
+    let id = next_id.0;
+    next_id.0 = next_id.0.wrapping_add(1);
+    let mut pb = PosBuffer(CircularBuffer::new());
+    pb.0.set(tick, Some(pos));
+    return commands.spawn((
+        Projectile {
+            id,
+            kind,
+            owner,
+            pos,
+            dir,
+            expires: tick.wrapping_add(kind.lifetime()),
+        },
+        pb,
+        projectile_bundle(kind, pos, dir),
+    )).id();
+}
+
+/// players shooting, on the host
+pub fn fire_simulate(
+    mut commands: Commands,
+    tick: Res<TickNum>,
+    mut next_id: ResMut<NextProjectileId>,
+    mut fire_reader: EventReader<FireEvent>,
+    mut players: Query<(&Player, &PosBuffer, &DirBuffer, &Health, &Stagger, &mut Respawn)>,
+) {
+    for ev in fire_reader.iter() {
+        for (pl, pb, db, hp, stagger, mut respawn) in &mut players {
+            if pl.0 != ev.id || hp.dead || stagger.active(tick.0) { continue }
+            respawn.unprotect();
+            let pos = pb.0.get(ev.seq_num);
+            if pos.is_none() { continue }
+            let dir = db.0.get(ev.seq_num).unwrap_or(0.);
+            // start at the edge of the player so it doesn't look like it came out of their face
+            let pos = pos.unwrap() + Vec2::from_angle(dir) * PLAYER_SIZE.x / 2.;
+            spawn_projectile(&mut commands, &mut next_id, tick.0, ProjectileKind::Arrow, Attacker::Player(pl.0), pos, dir);
+        }
+    }
+}
+
+/// Moves everything in flight on the host and works out what it hit.
+/// Each tick's movement is checked in steps no longer than the projectile is wide, so fast ones can't skip past things
+pub fn move_projectiles(
+    mut commands: Commands,
+    tick: Res<TickNum>,
+    map: Res<WorldMap>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut PosBuffer, &mut Transform)>,
+    mut players: Query<(&Player, &PosBuffer, &mut HpBuffer, &mut PlayerShield, &Dash, &mut DamageLedger, &Collider, &StatusEffects, &Respawn, &Team), (Without<Projectile>, Without<Enemy>)>,
+    mut enemies: Query<(&PosBuffer, &mut HpBuffer, &mut DamageLedger, &Collider, &StatusEffects), (With<Enemy>, Without<Projectile>, Without<Player>)>,
+    mut chests: Query<(&ItemChest, &Transform, &mut Health, &Collider), (Without<Player>, Without<Enemy>, Without<Projectile>)>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
+) {
+    for (e, mut proj, mut pb, mut tf) in &mut projectiles {
+        if tick.0.wrapping_sub(proj.expires) < u16::MAX / 2 {
+            commands.entity(e).despawn();
+            continue;
+        }
+        let radius = proj.kind.radius();
+        let travel = proj.kind.speed() * TICKLEN_S;
+        let steps = (travel / (radius * 2.)).ceil().max(1.) as usize;
+        let step = Vec2::from_angle(proj.dir) * travel / steps as f32;
+        let mut hit = false;
+        let mut parried_by = None;
+        // arrows fly past the shooter's teammates
+        let owner_team = players.iter()
+            .find(|(pl, ..)| proj.owner == Attacker::Player(pl.0))
+            .map_or(Team(NO_TEAM), |(.., team)| *team);
+        for _ in 0..steps {
+            proj.pos += step;
+            if map::get_tile_at_pos(&proj.pos.extend(0.), &map.biome_map) == Biome::Wall {
+                hit = true;
+                break;
+            }
+            for (pl, player_pb, mut player_hb, mut shield, dash, mut ledger, collider, effects, respawn, team) in &mut players {
+                if proj.owner == Attacker::Player(pl.0) || owner_team.allied(*team) { continue }
+                let pos = player_pb.0.get(tick.0);
+                if pos.is_none() || pos.unwrap().distance(proj.pos) > radius + collider.0.x / 2. { continue }
+                let prev_hp = player_hb.0.get(tick.0).unwrap_or(0);
+                if prev_hp == 0 { continue }  // dead players don't stop arrows
+                if dash.invulnerable(tick.0) || respawn.protected(tick.0) {
+                    hit = true;
+                    break;
+                }
+                match shield.block(tick.0, proj.kind.damage()) {
+                    Block::Parried => {
+                        parried_by = Some(pl.0);
+                        break;
+                    },
+                    Block::Blocked => {
+                        hit = true;
+                        break;
+                    },
+                    Block::Hit => hit = true,
+                }
+                let hp = prev_hp.saturating_sub(effects.damage_taken(proj.kind.damage(), tick.0));
+                player_hb.0.set(tick.0, Some(hp));
+                ledger.record(proj.owner, prev_hp - hp, tick.0);
+                break;
+            }
+            if hit || parried_by.is_some() { break }
+            if let Attacker::Player(shooter) = proj.owner {
+                for (enemy_pb, mut enemy_hb, mut ledger, collider, effects) in &mut enemies {
+                    let pos = enemy_pb.0.get(tick.0);
+                    if pos.is_none() || pos.unwrap().distance(proj.pos) > radius + collider.0.x / 2. { continue }
+                    let prev_hp = enemy_hb.0.get(tick.0).unwrap_or(0);
+                    if prev_hp == 0 { continue }
+                    let hp = prev_hp.saturating_sub(effects.damage_taken(proj.kind.damage(), tick.0));
+                    enemy_hb.0.set(tick.0, Some(hp));
+                    ledger.record(proj.owner, prev_hp - hp, tick.0);
+                    hit = true;
+                    break;
+                }
+                if hit { break }
+                for (ic, chest_tf, mut chest_hp, collider) in &mut chests {
+                    if chest_hp.current == 0 { continue }
+                    if chest_tf.translation.truncate().distance(proj.pos) > radius + collider.0.x / 2. { continue }
+                    chest_hp.current = 0;
+                    gameplay_writer.send(GameplayEvent(GameEvent::ChestOpened { chest: ic.id, opener: shooter }));
+                    hit = true;
+                    break;
+                }
+                if hit { break }
+            }
+        }
+        if hit {
+            commands.entity(e).despawn();
+            continue;
+        }
+        if let Some(parrier) = parried_by {
+            // sent straight back, and it's the parrier's now
+            let back = proj.dir + std::f32::consts::PI;
+            proj.dir = back.sin().atan2(back.cos());
+            proj.owner = Attacker::Player(parrier);
+            proj.expires = tick.0.wrapping_add(proj.kind.lifetime());
+            tf.rotation = Quat::from_rotation_z(proj.dir);
+        }
+        pb.0.set(tick.0, Some(proj.pos));
+    }
+}
+
+/// Matches what we're drawing to what the host sent.
+/// Anything missing from the newest HostTick has hit something, run out, or gone out of view
+pub fn handle_projectile_ticks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut networked: ResMut<NetworkedProjectiles>,
+    mut projectile_reader: EventReader<ProjectileTickEvent>,
+    mut buffers: Query<(&mut PosBuffer, &mut JitterBuffer, &mut Transform)>,
+) {
+    for ev in projectile_reader.iter() {
+        for proj in &ev.projectiles {
+            let e = networked.entities.get(&proj.id).copied();
+            let e = match e {
+                Some(e) => e,
+                None => {
+                    let e = commands.spawn((
+                        PosBuffer(CircularBuffer::new()),
+                        JitterBuffer::new(),
+                        projectile_bundle(proj.kind, proj.pos, proj.dir),
+                    )).id();
+                    networked.entities.insert(proj.id, e);
+                    e
+                }
+            };
+            // newly spawned ones get their first position when they're spawned
+            if let Ok((mut pb, mut jb, mut tf)) = buffers.get_mut(e) {
+                pb.0.set_with_time(ev.seq_num, Some(proj.pos), ev.seq_num);
+                jb.observe(ev.seq_num, time.elapsed_seconds());
+                tf.rotation = Quat::from_rotation_z(proj.dir);
+            }
+        }
+        // seq_nums wrap, so a tick from before the wrap still counts as older
+        if networked.newest.is_some_and(|newest| net::newer(newest, ev.seq_num)) { continue }
+        networked.newest = Some(ev.seq_num);
+        let alive: HashSet<u16> = ev.projectiles.iter().map(|proj| proj.id).collect();
+        networked.entities.retain(|id, e| {
+            if alive.contains(id) { return true }
+            commands.entity(*e).despawn();
+            return false;
+        });
+    }
+}
+
+pub fn remove_projectiles(
+    mut commands: Commands,
+    mut networked: ResMut<NetworkedProjectiles>,
+    projectiles: Query<Entity, With<Projectile>>,
+) {
+    for e in &projectiles {
+        commands.entity(e).despawn();
+    }
+    for e in networked.entities.values() {
+        commands.entity(*e).despawn();
+    }
+    *networked = NetworkedProjectiles::default();
+}
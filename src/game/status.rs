@@ -0,0 +1,364 @@
+//! Timed buffs and debuffs. Powerups are forever, these wear off. The host adds them, ticks them and takes them
+//! away, clients just get everyone's list in their PlayerTicks and EnemyTicks
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::HpBuffer;
+use crate::game::components::*;
+use crate::game::{enemy, player, projectile};
+use crate::game::events::{Attacker, GameEvent, GameplayEvent};
+use crate::game::player::LocalPlayer;
+use crate::net::{self, is_host, TICKLEN_S, TickNum};
+
+pub const MAX_EFFECTS: usize = 8;  // per player or enemy, whatever's closest to running out goes first
+const PULSE_TICKS: u16 = net::TICKRATE as u16;  // poison, burn and regeneration happen once a second
+const MIN_SPEED: f32 = 0.25;  // slows can't take anyone below this fraction of their usual speed
+const CHEST_BUFFS: [(StatusType, u8); 2] = [(StatusType::Haste, 30), (StatusType::Regeneration, 5)];
+const CHEST_BUFF_SECONDS: f32 = 8.;
+const ICON_SIZE: f32 = 5.;
+const ICON_Y: f32 = 31.;  // just above the health bar
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StatusType {
+    Poison,  // hurts every second, but never kills
+    Burn,  // hurts every second
+    Slow,
+    Haste,
+    Vulnerability,  // takes more damage from everything
+    Regeneration,  // heals every second
+}
+
+pub const STATUS_TYPES: [StatusType; 6] = [
+    StatusType::Poison,
+    StatusType::Burn,
+    StatusType::Slow,
+    StatusType::Haste,
+    StatusType::Vulnerability,
+    StatusType::Regeneration,
+];
+
+impl StatusType {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            StatusType::Poison => "Poison",
+            StatusType::Burn => "Burn",
+            StatusType::Slow => "Slow",
+            StatusType::Haste => "Haste",
+            StatusType::Vulnerability => "Vulnerable",
+            StatusType::Regeneration => "Regen",
+        };
+    }
+
+    /// what the icon and the HUD text are drawn in
+    pub fn color(&self) -> Color {
+        return match self {
+            StatusType::Poison => Color::rgb(0.4, 0.9, 0.2),
+            StatusType::Burn => Color::rgb(1., 0.5, 0.1),
+            StatusType::Slow => Color::rgb(0.4, 0.6, 1.),
+            StatusType::Haste => Color::rgb(1., 1., 0.3),
+            StatusType::Vulnerability => Color::rgb(0.8, 0.3, 0.9),
+            StatusType::Regeneration => Color::rgb(1., 0.5, 0.7),
+        };
+    }
+
+    /// damage and healing over time add up across sources, everything else just goes by the strongest
+    fn stacks(&self) -> bool {
+        return matches!(self, StatusType::Poison | StatusType::Burn | StatusType::Regeneration);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusType,
+    pub magnitude: u8,  // hp a second for poison, burn and regeneration, percent for the rest
+    pub expires: u16,  // tick it wears off on
+    pub source: Attacker,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusType, magnitude: u8, tick: u16, seconds: f32, source: Attacker) -> StatusEffect {
+        return StatusEffect {
+            kind,
+            magnitude,
+            expires: tick.wrapping_add((seconds / TICKLEN_S) as u16),
+            source,
+        };
+    }
+
+    pub fn expired(&self, tick: u16) -> bool {
+        return tick.wrapping_sub(self.expires) < u16::MAX / 2;
+    }
+
+    /// ticks left, 0 once it's run out
+    pub fn remaining(&self, tick: u16) -> u16 {
+        if self.expired(tick) { return 0 }
+        return self.expires.wrapping_sub(tick);
+    }
+}
+
+/// Everything currently affecting a player or enemy
+#[derive(Component, Default, Clone, Debug, PartialEq)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    /// the same effect from the same source refreshes instead of stacking with itself
+    pub fn add(&mut self, tick: u16, effect: StatusEffect) {
+        let same = self.0.iter_mut().find(|e| e.kind == effect.kind && e.source == effect.source);
+        if let Some(same) = same {
+            same.magnitude = same.magnitude.max(effect.magnitude);
+            same.expires = effect.expires;
+            return;
+        }
+        if self.0.len() >= MAX_EFFECTS {
+            let soonest = self.0.iter().enumerate().min_by_key(|(_, e)| e.remaining(tick)).map(|(i, _)| i);
+            self.0.remove(soonest.unwrap());
+        }
+        self.0.push(effect);
+    }
+
+    pub fn expire(&mut self, tick: u16) {
+        self.0.retain(|e| !e.expired(tick));
+    }
+
+    pub fn magnitude(&self, kind: StatusType, tick: u16) -> u8 {
+        let active = self.0.iter().filter(|e| e.kind == kind && !e.expired(tick)).map(|e| e.magnitude);
+        if kind.stacks() {
+            return active.fold(0, u8::saturating_add);
+        }
+        return active.max().unwrap_or(0);
+    }
+
+    /// whose effect of this kind is strongest, for working out who gets the credit
+    pub fn strongest_source(&self, kind: StatusType, tick: u16) -> Option<Attacker> {
+        return self.0.iter()
+            .filter(|e| e.kind == kind && !e.expired(tick))
+            .max_by_key(|e| e.magnitude)
+            .map(|e| e.source);
+    }
+
+    /// what a hit for damage actually does to them
+    pub fn damage_taken(&self, damage: u8, tick: u16) -> u8 {
+        let vulnerability = self.magnitude(StatusType::Vulnerability, tick) as f32 / 100.;
+        return (damage as f32 * (1. + vulnerability)).min(u8::MAX as f32) as u8;
+    }
+
+    /// how much faster than usual they move and swing. Haste and slow cancel out
+    pub fn speed(&self, tick: u16) -> f32 {
+        let haste = self.magnitude(StatusType::Haste, tick) as f32 / 100.;
+        let slow = self.magnitude(StatusType::Slow, tick) as f32 / 100.;
+        return (1. + haste - slow).max(MIN_SPEED);
+    }
+
+    /// the kinds that are on right now, in the order the icons go
+    pub fn active(&self, tick: u16) -> Vec<StatusType> {
+        return STATUS_TYPES.iter().copied()
+            .filter(|kind| self.0.iter().any(|e| e.kind == *kind && !e.expired(tick)))
+            .collect();
+    }
+
+    /// hp after one pulse of poison, burn and regeneration, and whoever's burn did it if it killed them
+    pub fn pulse(&self, hp: u8, max: u8, tick: u16) -> (u8, Option<Attacker>) {
+        let healed = hp.saturating_add(self.magnitude(StatusType::Regeneration, tick)).min(max);
+        let burnt = healed.saturating_sub(self.damage_taken(self.magnitude(StatusType::Burn, tick), tick));
+        if burnt == 0 {
+            return (0, self.strongest_source(StatusType::Burn, tick));
+        }
+        let poisoned = burnt.saturating_sub(self.damage_taken(self.magnitude(StatusType::Poison, tick), tick)).max(1);
+        return (poisoned, None);
+    }
+}
+
+/// One of the little squares over someone's health bar
+#[derive(Component)]
+pub struct StatusIcon(pub usize);
+
+/// The list of what's affecting the local player, on the HUD
+#[derive(Component)]
+pub struct StatusDisplay;
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (
+                status_simulate.after(player::attack_simulate).after(enemy::attack_simulate).after(projectile::move_projectiles),
+                chest_buff_simulate.after(player::attack_simulate).after(projectile::move_projectiles),
+            ).run_if(in_state(AppState::Game)).run_if(is_host).before(net::host::fixed))
+            .add_systems(Update, (
+                update_status_icons,
+                update_status_display,
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+/// gives an entity its (hidden) status icons, one slot for each kind of effect
+pub fn spawn_status_icons(commands: &mut Commands, entity: Entity) {
+    for i in 0..STATUS_TYPES.len() {
+        let x = (i as f32 - (STATUS_TYPES.len() - 1) as f32 / 2.) * (ICON_SIZE + 1.);
+        let icon = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(ICON_SIZE)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                transform: Transform::from_xyz(x, ICON_Y, 2.),
+                ..default()
+            },
+            StatusIcon(i),
+        )).id();
+        commands.entity(entity).add_child(icon);
+    }
+}
+
+/// wears effects off, and does the damage and healing for the ones that do that. Dying clears everything
+pub fn status_simulate(
+    tick: Res<TickNum>,
+    mut players: Query<(&mut StatusEffects, &mut HpBuffer, &Health, &mut DamageLedger), (With<Player>, Without<Enemy>)>,
+    mut enemies: Query<(&mut StatusEffects, &mut HpBuffer, &Health, &mut DamageLedger), With<Enemy>>,
+) {
+    let pulse = tick.0 % PULSE_TICKS == 0;
+    for (mut effects, mut hb, health, mut ledger) in players.iter_mut().chain(enemies.iter_mut()) {
+        let hp = hb.0.get(tick.0).unwrap_or(0);
+        if hp == 0 {
+            effects.0.clear();
+            continue;
+        }
+        effects.expire(tick.0);
+        if !pulse || effects.0.is_empty() { continue }
+        let (next, killer) = effects.pulse(hp, health.max, tick.0);
+        hb.0.set(tick.0, Some(next));
+        if next < hp {
+            // whoever's burn or poison did it gets it on the ledger
+            let source = killer
+                .or(effects.strongest_source(StatusType::Burn, tick.0))
+                .or(effects.strongest_source(StatusType::Poison, tick.0));
+            if let Some(source) = source {
+                ledger.record(source, hp - next, tick.0);
+            }
+        }
+    }
+}
+
+/// opening a chest gives a short buff as well as the powerups, which one depends on the chest
+pub fn chest_buff_simulate(
+    tick: Res<TickNum>,
+    mut gameplay_reader: EventReader<GameplayEvent>,
+    mut players: Query<(&Player, &mut StatusEffects)>,
+) {
+    for ev in gameplay_reader.iter() {
+        if let GameEvent::ChestOpened { chest, opener } = ev.0 {
+            for (pl, mut effects) in &mut players {
+                if pl.0 != opener { continue }
+                let (kind, magnitude) = CHEST_BUFFS[chest as usize % CHEST_BUFFS.len()];
+                effects.add(tick.0, StatusEffect::new(kind, magnitude, tick.0, CHEST_BUFF_SECONDS, Attacker::Player(opener)));
+            }
+        }
+    }
+}
+
+pub fn update_status_icons(
+    tick: Res<TickNum>,
+    entities: Query<(&StatusEffects, &Children)>,
+    mut icons: Query<(&StatusIcon, &mut Sprite, &mut Visibility)>,
+) {
+    for (effects, children) in &entities {
+        let active = effects.active(tick.0);
+        for child in children.iter() {
+            let icon = icons.get_mut(*child);
+            if icon.is_err() { continue }
+            let (icon, mut sprite, mut vis) = icon.unwrap();
+            if icon.0 < active.len() {
+                sprite.color = active[icon.0].color();
+                *vis = Visibility::Inherited;
+            }
+            else {
+                *vis = Visibility::Hidden;
+            }
+        }
+    }
+}
+
+/// lists what's on the local player and how long it's got left
+pub fn update_status_display(
+    tick: Res<TickNum>,
+    players: Query<&StatusEffects, With<LocalPlayer>>,
+    mut displays: Query<&mut Text, With<StatusDisplay>>,
+) {
+    let effects = players.get_single();
+    if effects.is_err() { return }
+    let effects = effects.unwrap();
+    for mut text in &mut displays {
+        let style = text.sections[0].style.clone();
+        let mut sections = Vec::new();
+        for kind in effects.active(tick.0) {
+            let left = effects.0.iter()
+                .filter(|e| e.kind == kind)
+                .map(|e| e.remaining(tick.0))
+                .max()
+                .unwrap_or(0);
+            sections.push(TextSection::new(
+                format!("{} {}s  ", kind.name(), (left as f32 * TICKLEN_S).ceil() as u32),
+                TextStyle { color: kind.color(), ..style.clone() },
+            ));
+        }
+        if sections.is_empty() {
+            // keep one around so there's still a style to copy next time
+            sections.push(TextSection::new("", style));
+        }
+        text.sections = sections;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOMEONE: Attacker = Attacker::Enemy(4);
+
+    #[test]
+    fn same_source_refreshes() {
+        let mut effects = StatusEffects::default();
+        effects.add(0, StatusEffect::new(StatusType::Poison, 3, 0, 2., SOMEONE));
+        effects.add(10, StatusEffect::new(StatusType::Poison, 2, 10, 2., SOMEONE));
+        assert_eq!(effects.0.len(), 1);
+        assert_eq!(effects.magnitude(StatusType::Poison, 10), 3);
+        assert!(!effects.0[0].expired(25));
+    }
+
+    #[test]
+    fn damage_over_time_stacks_and_buffs_dont() {
+        let mut effects = StatusEffects::default();
+        effects.add(0, StatusEffect::new(StatusType::Poison, 3, 0, 2., SOMEONE));
+        effects.add(0, StatusEffect::new(StatusType::Poison, 4, 0, 2., Attacker::Player(1)));
+        effects.add(0, StatusEffect::new(StatusType::Haste, 20, 0, 2., SOMEONE));
+        effects.add(0, StatusEffect::new(StatusType::Haste, 30, 0, 2., Attacker::Player(1)));
+        assert_eq!(effects.magnitude(StatusType::Poison, 0), 7);
+        assert!((effects.speed(0) - 1.3).abs() < 0.001);
+    }
+
+    #[test]
+    fn effects_wear_off() {
+        let mut effects = StatusEffects::default();
+        effects.add(65530, StatusEffect::new(StatusType::Vulnerability, 50, 65530, 1., SOMEONE));
+        assert_eq!(effects.damage_taken(10, 3), 15);
+        effects.expire(4);
+        assert!(effects.0.is_empty());
+        assert_eq!(effects.damage_taken(10, 4), 10);
+    }
+
+    #[test]
+    fn poison_never_kills_but_burn_does() {
+        let mut effects = StatusEffects::default();
+        effects.add(0, StatusEffect::new(StatusType::Poison, 50, 0, 5., SOMEONE));
+        assert_eq!(effects.pulse(20, 100, 0), (1, None));
+        effects.add(0, StatusEffect::new(StatusType::Burn, 30, 0, 5., Attacker::Player(2)));
+        assert_eq!(effects.pulse(20, 100, 0), (0, Some(Attacker::Player(2))));
+    }
+
+    #[test]
+    fn slows_have_a_floor() {
+        let mut effects = StatusEffects::default();
+        effects.add(0, StatusEffect::new(StatusType::Slow, 200, 0, 5., SOMEONE));
+        assert_eq!(effects.speed(0), MIN_SPEED);
+    }
+}
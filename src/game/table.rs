@@ -0,0 +1,97 @@
+//! The csv tables in assets/ that say what weapons and classes do. A row's index is its id on the wire,
+//! so every machine has to have the same files. Rows are checked as they're read, a bad number here
+//! would otherwise only show up as a panic the first time something used it
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use bevy::asset::FileAssetIo;
+
+/// One row of a table, with the header so errors can say which column was wrong
+pub struct Row<'a> {
+    headers: &'a csv::StringRecord,
+    record: &'a csv::StringRecord,
+}
+
+impl<'a> Row<'a> {
+    fn name(&self, i: usize) -> &str {
+        return self.headers.get(i).unwrap_or("?");
+    }
+
+    pub fn field<F: FromStr>(&self, i: usize) -> Result<F, Box<dyn Error>> where F::Err: Display {
+        let text = self.record.get(i).ok_or_else(|| format!("missing {}", self.name(i)))?;
+        return text.parse().map_err(|e| format!("{} {:?}: {}", self.name(i), text, e).into());
+    }
+
+    /// for numbers that end up as times or divisors, where 0, negatives and NaN all blow up later
+    pub fn positive(&self, i: usize) -> Result<f32, Box<dyn Error>> {
+        let value: f32 = self.field(i)?;
+        if !value.is_finite() || value <= 0. {
+            return Err(format!("{} has to be more than 0, not {}", self.name(i), value).into());
+        }
+        return Ok(value);
+    }
+
+    /// for numbers where 0 just means none, like knockback
+    pub fn at_least_zero(&self, i: usize) -> Result<f32, Box<dyn Error>> {
+        let value: f32 = self.field(i)?;
+        if !value.is_finite() || value < 0. {
+            return Err(format!("{} can't be less than 0, not {}", self.name(i), value).into());
+        }
+        return Ok(value);
+    }
+}
+
+/// parses a header row then one `what` per row. There has to be at least one, and few enough for a u8 id
+pub fn read<T, R: Read>(reader: R, what: &str, row: impl Fn(&Row) -> Result<T, Box<dyn Error>>) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        rows.push(row(&Row { headers: &headers, record: &record })?);
+    }
+    if rows.is_empty() || rows.len() > u8::MAX as usize {
+        return Err(format!("need between 1 and 255 {}", what).into());
+    }
+    return Ok(rows);
+}
+
+/// reads file out of the assets folder. If that goes wrong it says so, and what happens `instead`
+pub fn load<T>(file: &str, read: impl FnOnce(File) -> Result<Vec<T>, Box<dyn Error>>, fallback: T, instead: &str) -> Vec<T> {
+    let path = FileAssetIo::get_base_path().join("assets").join(file);
+    let rows = File::open(&path)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(read);
+    match rows {
+        Ok(rows) => return rows,
+        Err(e) => {
+            println!("couldn't read {}: {}, {}", path.display(), e, instead);
+            return vec![fallback];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(csv: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+        return read(csv.as_bytes(), "numbers", |row| row.positive(0));
+    }
+
+    #[test]
+    fn says_which_column_was_wrong() {
+        let e = numbers("cooldown\n-1\n").unwrap_err();
+        assert_eq!(e.to_string(), "cooldown has to be more than 0, not -1");
+        let e = numbers("cooldown\nsoon\n").unwrap_err();
+        assert!(e.to_string().starts_with("cooldown \"soon\""), "{}", e);
+    }
+
+    #[test]
+    fn needs_a_row() {
+        assert!(numbers("cooldown\n").is_err());
+        assert_eq!(numbers("cooldown\n0.5\n2\n").unwrap(), vec![0.5, 2.]);
+    }
+}
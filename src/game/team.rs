@@ -0,0 +1,149 @@
+//! Team deathmatch. The host puts players on teams as they join and everyone else hears which team
+//! each player is on in their PlayerTicks. With no teams it's free-for-all, like it always was
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::components::Player;
+use crate::net::is_host;
+
+pub const NO_TEAM: u8 = u8::MAX;  // what everyone is in free-for-all
+pub const MAX_TEAMS: u8 = 4;
+const TEAM_NAMES: [&str; MAX_TEAMS as usize] = ["Red", "Blue", "Green", "Gold"];
+// sprites are tinted like the extra players are, the minimap gets the full colour
+const TEAM_TINTS: [Color; MAX_TEAMS as usize] = [
+    Color::rgb(1., 0.55, 0.55),
+    Color::rgb(0.55, 0.7, 1.),
+    Color::rgb(0.6, 1., 0.55),
+    Color::rgb(1., 0.9, 0.45),
+];
+const TEAM_COLORS: [Color; MAX_TEAMS as usize] = [
+    Color::rgb(0.9, 0.15, 0.15),
+    Color::rgb(0.2, 0.4, 1.),
+    Color::rgb(0.2, 0.8, 0.2),
+    Color::rgb(0.95, 0.8, 0.1),
+];
+
+/// Which team a player is on
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Team(pub u8);
+
+impl Team {
+    /// teammates can't hurt each other. In free-for-all nobody's on anyone's side
+    pub fn allied(&self, other: Team) -> bool {
+        return self.0 != NO_TEAM && self.0 == other.0;
+    }
+
+    pub fn name(&self) -> &'static str {
+        return TEAM_NAMES.get(self.0 as usize).copied().unwrap_or("No");
+    }
+
+    pub fn tint(&self) -> Option<Color> {
+        return TEAM_TINTS.get(self.0 as usize).copied();
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        return TEAM_COLORS.get(self.0 as usize).copied();
+    }
+}
+
+/// How many teams the host splits everyone into. Fewer than 2 is free-for-all
+#[derive(Resource, Default)]
+pub struct TeamRules {
+    pub teams: u8,
+}
+
+/// the team with the fewest players on it, the lowest one if that's a tie
+pub fn smallest_team(teams: u8, members: impl Iterator<Item = Team>) -> u8 {
+    let mut counts = vec![0; teams as usize];
+    for team in members {
+        if let Some(count) = counts.get_mut(team.0 as usize) {
+            *count += 1;
+        }
+    }
+    return (0..teams).min_by_key(|&team| counts[team as usize]).unwrap_or(0);
+}
+
+/// (team, total score of everyone on it) for each team that has anyone on it, in team order
+pub fn team_scores(members: impl Iterator<Item = (Team, u16)>) -> Vec<(Team, u32)> {
+    let mut scores: Vec<(Team, u32)> = Vec::new();
+    for (team, score) in members {
+        if team.0 == NO_TEAM { continue }
+        match scores.iter_mut().find(|(t, _)| *t == team) {
+            Some((_, total)) => *total += score as u32,
+            None => scores.push((team, score as u32)),
+        }
+    }
+    scores.sort_by_key(|(team, _)| team.0);
+    return scores;
+}
+
+/// the team with the most points, if one of them has more than all the others
+pub fn winner(scores: &[(Team, u32)]) -> Option<Team> {
+    let best = scores.iter().map(|(_, total)| *total).max()?;
+    let mut leaders = scores.iter().filter(|(_, total)| *total == best);
+    let leader = leaders.next().map(|(team, _)| *team);
+    if leaders.next().is_some() { return None }
+    return leader;
+}
+
+pub struct TeamPlugin;
+
+impl Plugin for TeamPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, assign_teams.run_if(in_state(AppState::Game)).run_if(is_host).before(crate::net::host::fixed))
+            .init_resource::<TeamRules>();
+    }
+}
+
+/// puts anyone who isn't on a team yet on whichever has the fewest players, on the host
+pub fn assign_teams(
+    rules: Res<TeamRules>,
+    mut players: Query<(&Player, &mut Team)>,
+) {
+    if rules.teams < 2 { return }
+    let mut waiting: Vec<u8> = players.iter().filter(|(_, team)| team.0 == NO_TEAM).map(|(pl, _)| pl.0).collect();
+    waiting.sort();
+    for id in waiting {
+        let team = smallest_team(rules.teams, players.iter().map(|(_, team)| *team));
+        for (pl, mut player_team) in &mut players {
+            if pl.0 == id {
+                player_team.0 = team;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nobody_is_allied_in_free_for_all() {
+        assert!(!Team(NO_TEAM).allied(Team(NO_TEAM)));
+        assert!(Team(1).allied(Team(1)));
+        assert!(!Team(0).allied(Team(1)));
+    }
+
+    #[test]
+    fn joiners_fill_the_smallest_team() {
+        let members = [Team(0), Team(1), Team(0), Team(NO_TEAM)];
+        assert_eq!(smallest_team(2, members.iter().copied()), 1);
+        assert_eq!(smallest_team(3, members.iter().copied()), 2);
+        assert_eq!(smallest_team(2, std::iter::empty()), 0);
+    }
+
+    #[test]
+    fn most_points_wins_and_ties_dont() {
+        let scores = team_scores([(Team(1), 30), (Team(0), 20), (Team(1), 5), (Team(NO_TEAM), 200)].into_iter());
+        assert_eq!(scores, vec![(Team(0), 20), (Team(1), 35)]);
+        assert_eq!(winner(&scores), Some(Team(1)));
+        assert_eq!(winner(&[(Team(0), 20), (Team(1), 20)]), None);
+        assert_eq!(winner(&[]), None);
+    }
+
+    #[test]
+    fn a_round_on_the_hill_outscores_a_byte() {
+        // five minutes at a point a second
+        let scores = team_scores([(Team(0), 300), (Team(1), 255)].into_iter());
+        assert_eq!(winner(&scores), Some(Team(0)));
+    }
+}
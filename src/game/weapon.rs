@@ -0,0 +1,277 @@
+//! Melee weapons, one per row of assets/weapons.csv, so new ones don't need code.
+//! They're found in chests and swapped on the ground, the sword is what everyone starts with
+use std::error::Error;
+use std::io::Read;
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::PosBuffer;
+use crate::game::class::{ClassDef, Classes, PlayerClass};
+use crate::game::components::*;
+use crate::game::events::{GameEvent, GameplayEvent};
+use crate::game::player::{self, Cooldown, InteractEvent};
+use crate::game::table;
+use crate::net::{is_client, is_host};
+
+pub const WEAPONS_FILE: &str = "weapons.csv";
+pub const SWORD: u8 = 0;  // the first weapon in the file is what everyone starts with
+pub const WEAPON_DROP_CHANCE: u32 = 35;  // percent of chests that have a weapon in them
+const GRAB_DISTANCE: f32 = 48.;
+const DROP_Z: f32 = 1.5;
+
+/// One row of weapons.csv
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeaponDef {
+    pub name: String,
+    pub damage: u8,
+    pub reach: f32,  // pixels
+    pub arc: f32,  // degrees either side of where the player is facing
+    pub cooldown: f32,  // seconds between swings
+    pub knockback: f32,  // pixels a hit pushes the target
+    pub sprite: String,
+}
+
+impl WeaponDef {
+    /// the weapon everyone gets if weapons.csv can't be read
+    fn fallback() -> WeaponDef {
+        return WeaponDef {
+            name: "Sword".to_string(),
+            damage: 40,
+            reach: 90.,
+            arc: 70.,
+            cooldown: 0.8,
+            knockback: 20.,
+            sprite: "sword01.png".to_string(),
+        };
+    }
+
+    /// The one hit test for a swing: is target within reach and inside the arc of a swing from `from` facing `dir`
+    pub fn hits(&self, from: Vec2, dir: f32, target: Vec2) -> bool {
+        if from.distance(target) > self.reach { return false }
+        let to_target = target - from;
+        let angle_diff = dir - to_target.y.atan2(to_target.x);
+        let angle_diff = angle_diff.sin().atan2(angle_diff.cos());
+        return angle_diff.abs() <= self.arc.to_radians();
+    }
+
+    /// what a swing does in the hands of class, before crits
+    pub fn damage(&self, spu: &StoredPowerUps, class: &ClassDef) -> u8 {
+        let base = (self.damage as f32 * class.damage).round().min(u8::MAX as f32) as u8;
+        return base.saturating_add(spu.power_ups[PowerUpType::DamageDealtUp as usize].saturating_mul(DAMAGE_DEALT_UP));
+    }
+
+    /// how far a hit pushes, damage dealt ups hit harder
+    pub fn knockback(&self, spu: &StoredPowerUps) -> f32 {
+        return self.knockback * KNOCKBACK_UP.powi(spu.power_ups[PowerUpType::DamageDealtUp as usize] as i32);
+    }
+
+    pub fn cooldown(&self, spu: &StoredPowerUps, class: &ClassDef) -> Duration {
+        return Duration::from_secs_f32(self.cooldown * class.cooldown * (1. / ATTACK_SPEED_UP).powi(spu.power_ups[PowerUpType::AttackSpeedUp as usize] as i32));
+    }
+}
+
+/// Every weapon there is, in file order. A weapon's id is its index
+#[derive(Resource)]
+pub struct Weapons(pub Vec<WeaponDef>);
+
+impl Weapons {
+    /// reads weapons.csv out of the assets folder, or makes do with just a sword
+    pub fn load() -> Weapons {
+        return Weapons(table::load(WEAPONS_FILE, read_weapons, WeaponDef::fallback(), "everyone gets a sword"));
+    }
+
+    /// a weapon from someone else's weapons.csv that ours doesn't have swings like a sword
+    pub fn get(&self, id: u8) -> &WeaponDef {
+        return self.0.get(id as usize).unwrap_or(&self.0[SWORD as usize]);
+    }
+}
+
+/// parses weapons.csv: a header row, then name, damage, reach, arc, cooldown, knockback, sprite
+pub fn read_weapons<R: Read>(reader: R) -> Result<Vec<WeaponDef>, Box<dyn Error>> {
+    return table::read(reader, "weapons", |row| {
+        return Ok(WeaponDef {
+            name: row.field(0)?,
+            damage: row.field(1)?,
+            reach: row.positive(2)?,
+            arc: row.positive(3)?,
+            // turned into a Duration every swing
+            cooldown: row.positive(4)?,
+            knockback: row.at_least_zero(5)?,
+            sprite: row.field(6)?,
+        });
+    });
+}
+
+/// The melee weapon a player has out
+#[derive(Component)]
+pub struct Weapon(pub u8);
+
+/// A weapon lying where its chest was opened. Chests only ever hold one, so the chest id is enough to name it
+#[derive(Component)]
+pub struct WeaponDrop {
+    pub chest: u16,
+    pub weapon: u8,
+}
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, startup)
+            .add_systems(FixedUpdate, weapon_grab_simulate.after(player::attack_host)
+                .run_if(in_state(AppState::Game)).run_if(is_host).before(crate::net::host::fixed))
+            .add_systems(Update, handle_weapon_events.run_if(is_client).run_if(in_state(AppState::Game)))
+            .add_systems(OnExit(AppState::Game), remove_weapon_drops);
+    }
+}
+
+fn startup(mut commands: Commands) {
+    commands.insert_resource(Weapons::load());
+}
+
+pub fn spawn_weapon_drop(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    weapons: &Weapons,
+    chest: u16,
+    weapon: u8,
+    pos: Vec2,
+) {
+    commands.spawn((
+        WeaponDrop { chest, weapon },
+        SpriteBundle {
+            texture: asset_server.load(&weapons.get(weapon).sprite),
+            transform: Transform {
+                translation: pos.extend(DROP_Z),
+                rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+/// players pressing interact next to a weapon, on the host. The new one replaces whatever they had
+pub fn weapon_grab_simulate(
+    mut commands: Commands,
+    weapons: Res<Weapons>,
+    classes: Res<Classes>,
+    mut interact_reader: EventReader<InteractEvent>,
+    mut players: Query<(&Player, &PosBuffer, &Health, &StoredPowerUps, &PlayerClass, &mut Weapon, &mut Cooldown)>,
+    drops: Query<(Entity, &WeaponDrop, &Transform)>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
+) {
+    // despawns don't happen until later, so keep track of what's been taken this tick
+    let mut taken: Vec<Entity> = Vec::new();
+    for ev in interact_reader.iter() {
+        for (pl, pb, hp, spu, class, mut weapon, mut cooldown) in &mut players {
+            if pl.0 != ev.id || hp.dead { continue }
+            let pos = pb.0.get(ev.seq_num);
+            if pos.is_none() { continue }
+            let pos = pos.unwrap();
+            let nearest = drops.iter()
+                .filter(|(e, _, tf)| !taken.contains(e) && tf.translation.truncate().distance(pos) < GRAB_DISTANCE)
+                .min_by(|(_, _, a), (_, _, b)| a.translation.truncate().distance(pos).total_cmp(&b.translation.truncate().distance(pos)));
+            if nearest.is_none() { continue }
+            let (e, drop, _) = nearest.unwrap();
+            weapon.0 = drop.weapon;
+            cooldown.0.set_duration(weapons.get(drop.weapon).cooldown(spu, classes.get(class.0)));
+            commands.entity(e).despawn();
+            taken.push(e);
+            gameplay_writer.send(GameplayEvent(GameEvent::WeaponTaken { chest: drop.chest, player: pl.0 }));
+        }
+    }
+}
+
+/// clients take the weapon off the ground when the host says someone picked it up,
+/// the player's new weapon comes in their PlayerTicks
+pub fn handle_weapon_events(
+    mut commands: Commands,
+    mut gameplay_reader: EventReader<GameplayEvent>,
+    drops: Query<(Entity, &WeaponDrop)>,
+) {
+    for ev in gameplay_reader.iter() {
+        if let GameEvent::WeaponTaken { chest, .. } = ev.0 {
+            for (e, drop) in &drops {
+                if drop.chest == chest {
+                    commands.entity(e).despawn();
+                }
+            }
+        }
+    }
+}
+
+pub fn remove_weapon_drops(
+    mut commands: Commands,
+    drops: Query<Entity, With<WeaponDrop>>,
+) {
+    for e in &drops {
+        commands.entity(e).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sword() -> WeaponDef {
+        return WeaponDef::fallback();
+    }
+
+    #[test]
+    fn hits_inside_the_arc() {
+        let from = Vec2::ZERO;
+        assert!(sword().hits(from, 0., Vec2::new(50., 0.)));
+        assert!(sword().hits(from, 0., Vec2::new(30., 50.)));  // about 59 degrees off
+    }
+
+    #[test]
+    fn misses_outside_reach_or_arc() {
+        let from = Vec2::ZERO;
+        assert!(!sword().hits(from, 0., Vec2::new(100., 0.)));
+        assert!(!sword().hits(from, 0., Vec2::new(-50., 0.)));
+        assert!(!sword().hits(from, 0., Vec2::new(10., 50.)));  // about 79 degrees off
+    }
+
+    #[test]
+    fn hits_across_the_wraparound() {
+        // facing just under pi, target just past -pi
+        let from = Vec2::ZERO;
+        assert!(sword().hits(from, 3.1, Vec2::new(-50., -1.)));
+    }
+
+    #[test]
+    fn reads_weapons_csv() {
+        let csv = "name, damage, reach, arc, cooldown, knockback, sprite\n\
+            Sword, 40, 90, 70, 0.8, 20, sword01.png\n\
+            Dagger, 22, 60, 45, 0.35, 5, sword01.png\n";
+        let weapons = read_weapons(csv.as_bytes()).unwrap();
+        assert_eq!(weapons.len(), 2);
+        assert_eq!(weapons[0], sword());
+        assert_eq!(weapons[1].name, "Dagger");
+        assert_eq!(weapons[1].cooldown, 0.35);
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        assert!(read_weapons("name, damage, reach, arc, cooldown, knockback, sprite\n".as_bytes()).is_err());
+        assert!(read_weapons("name, damage, reach, arc, cooldown, knockback, sprite\nSword, lots, 90, 70, 0.8, 20, sword01.png\n".as_bytes()).is_err());
+        assert!(read_weapons("name, damage, reach, arc, cooldown, knockback, sprite\nSword, 40, 90\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_numbers_a_duration_cant_take() {
+        for cooldown in ["0", "-0.8", "NaN", "inf"] {
+            let csv = format!("name, damage, reach, arc, cooldown, knockback, sprite\nSword, 40, 90, 70, {}, 20, sword01.png\n", cooldown);
+            assert!(read_weapons(csv.as_bytes()).is_err(), "took a cooldown of {}", cooldown);
+        }
+        assert!(read_weapons("name, damage, reach, arc, cooldown, knockback, sprite\nSword, 40, -90, 70, 0.8, 20, sword01.png\n".as_bytes()).is_err());
+        assert!(read_weapons("name, damage, reach, arc, cooldown, knockback, sprite\nSword, 40, 90, 70, 0.8, NaN, sword01.png\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn unknown_ids_get_the_sword() {
+        let weapons = Weapons(vec![sword()]);
+        assert_eq!(weapons.get(200).name, "Sword");
+    }
+}
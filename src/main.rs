@@ -21,10 +21,28 @@ pub enum AppState {
     Credits,
     Connecting,
     Quitting,
+    Replay,
 }
 
 fn main() {
+    // jordquest --bot <host ip:port> [count] runs headless bots instead of the game
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "--bot") {
+        net::bot::run(&args[2..]);
+        return;
+    }
     App::new()
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: TITLE.into(),
+                resolution: (WIN_W, WIN_H).into(),
+                present_mode: bevy::window::PresentMode::Fifo,
+                ..default()
+            }),
+            ..default()
+        })
+            .set(ImagePlugin::default_nearest())
+        )
         .add_state::<AppState>()
         .add_plugins((
             GamePlugin,
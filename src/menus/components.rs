@@ -37,6 +37,11 @@ pub struct Switch{
     pub enemy_per_camp: bool,
     pub map_seed: bool,
     pub eid_percentage: bool,
+    pub max_players: bool,
+    pub respawn_delay: bool,
+    pub loot_drop: bool,
+    pub teams: bool,
+    pub hill: bool,
 }
 
 pub trait InputType: Component {
@@ -179,6 +184,116 @@ impl InputType for EidPercentageInput {
     }
 }
 
+impl InputType for MaxPlayersInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.max_players
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for RespawnDelayInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.respawn_delay
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for TeamsInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.teams
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for HillInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.hill
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for LootDropInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.loot_drop
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
 impl InputType for JoinHostPortInput {
     fn push_char(&mut self, ch: char) {
         self.port.push(ch);
@@ -290,6 +405,14 @@ impl ButtonTypeTrait for CreditsButtonType {
     }
 }
 
+pub struct ReplayButtonType;
+impl ButtonTypeTrait for ReplayButtonType {
+    type Marker = ReplayButton;
+    fn app_state() -> AppState {
+        AppState::Replay
+    }
+}
+
 pub struct QuitButtonType;
 impl ButtonTypeTrait for QuitButtonType {
     type Marker = QuitButton;
@@ -310,6 +433,9 @@ pub struct ControlsButton;
 #[derive(Component)]
 pub struct CreditsButton;
 
+#[derive(Component)]
+pub struct ReplayButton;
+
 #[derive(Component)]
 pub struct QuitButton;
 
@@ -322,12 +448,19 @@ pub struct LeaderboardUi;
 #[derive(Component)]
 pub struct LeaderboardUiTitle;
 
+/// each team's total under the leaderboard title, blank in free-for-all
+#[derive(Component)]
+pub struct LeaderboardTeams;
+
 #[derive(Component)]
 pub struct PlayerStatDisplay{
     pub player_id: u8,
     pub stat_id: u8,
 }
 
+#[derive(Component)]
+pub struct LeaderboardRow(pub u8);
+
 #[derive(Component)]
 pub struct HostPortInput {
     pub port: String,
@@ -354,6 +487,25 @@ pub struct MapSeedButton;
 #[derive(Component)]
 pub struct EidPercentageButton;
 
+#[derive(Component)]
+pub struct MaxPlayersButton;
+
+#[derive(Component)]
+pub struct RespawnDelayButton;
+
+#[derive(Component)]
+pub struct LootDropButton;
+
+#[derive(Component)]
+pub struct TeamsButton;
+
+#[derive(Component)]
+pub struct HillButton;
+
+/// cycles through the classes on the host and join pages
+#[derive(Component)]
+pub struct ClassButton;
+
 #[derive(Component)]
 pub struct JoinHostPortButton;
 
@@ -382,6 +534,31 @@ pub struct EidPercentageInput {
     pub value: String,
 }
 
+#[derive(Component)]
+pub struct MaxPlayersInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct RespawnDelayInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct LootDropInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct TeamsInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct HillInput {
+    pub value: String,
+}
+
 #[derive(Component)]
 pub struct JoinHostPortInput {
     pub port: String,
@@ -3,6 +3,8 @@ use bevy::prelude::*;
 use crate::menus::components::*;
 use crate::AppState;
 use crate::game::PlayerId;
+use crate::game::class::{ChosenClass, Classes};
+use crate::game::player::{DEFAULT_LOOT_DROP, DEFAULT_PLAYER_CAP, DEFAULT_RESPAWN_DELAY};
 use crate::menus::NetworkAddresses;
 use crate::game::MapConfig;
 use rand::Rng;
@@ -116,6 +118,46 @@ pub fn update_eid_percentage_input(
     update_input::<EidPercentageInput>(char_events, query, Some(switch_query));
 }
 
+pub fn update_max_players_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut MaxPlayersInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<MaxPlayersInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_respawn_delay_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut RespawnDelayInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<RespawnDelayInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_loot_drop_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut LootDropInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<LootDropInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_teams_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut TeamsInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<TeamsInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_hill_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut HillInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<HillInput>(char_events, query, Some(switch_query));
+}
+
 pub fn update_join_port_input(
     char_events: EventReader<ReceivedCharacter>,
     query: Query<(&mut Text, &mut JoinPortInput)>,
@@ -151,6 +193,11 @@ pub fn save_host_input(
     enemy_per_camp_query: Query<&EnemiesPerCampInput>,
     map_seed_query: Query<&MapSeedInput>,
     eid_percentage_query: Query<&EidPercentageInput>,
+    max_players_query: Query<&MaxPlayersInput>,
+    respawn_delay_query: Query<&RespawnDelayInput>,
+    loot_drop_query: Query<&LootDropInput>,
+    // (teams, hill), to stay under the system parameter limit
+    mode_queries: (Query<&TeamsInput>, Query<&HillInput>),
     mut button_query: Query<
         (&Interaction, &mut BackgroundColor),
         (Changed<Interaction>, With<HostPortSaveButton>),
@@ -181,6 +228,21 @@ pub fn save_host_input(
                     map_config.eid_percentage = input.value.clone();
                     //println!("eid percentage to {:?}", map_config.eid_percentage);
                 }
+                for input in max_players_query.iter() {
+                    map_config.max_players = input.value.clone();
+                }
+                for input in respawn_delay_query.iter() {
+                    map_config.respawn_delay = input.value.clone();
+                }
+                for input in loot_drop_query.iter() {
+                    map_config.loot_drop = input.value.clone();
+                }
+                for input in mode_queries.0.iter() {
+                    map_config.teams = input.value.clone();
+                }
+                for input in mode_queries.1.iter() {
+                    map_config.hill = input.value.clone();
+                }
                 app_state_next_state.set(AppState::Game);
             }
             Interaction::Hovered => {
@@ -210,6 +272,11 @@ pub fn host_port_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
                 }
             }
             Interaction::Hovered => {
@@ -239,6 +306,11 @@ pub fn num_camps_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
                 }
             }
             Interaction::Hovered => {
@@ -267,6 +339,11 @@ pub fn num_chests_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
                 }
             }
             Interaction::Hovered => {
@@ -296,6 +373,11 @@ pub fn enemy_per_camp_but(
                     switch.enemy_per_camp = true;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
                 }
             }
             Interaction::Hovered => {
@@ -325,6 +407,11 @@ pub fn map_seed_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = true;
                     switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
                 }
             }
             Interaction::Hovered => {
@@ -353,6 +440,182 @@ pub fn eid_percentage_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = true;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+pub fn max_players_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MaxPlayersButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.max_players = true;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn respawn_delay_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<RespawnDelayButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = true;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn loot_drop_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<LootDropButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = true;
+                    switch.teams = false;
+                    switch.hill = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+/// selects the teams box for typing, how many teams to split players into. Under 2 is free for all
+pub fn teams_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<TeamsButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = true;
+                    switch.hill = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+/// selects the hill box for typing, how many seconds the hill stays at each camp. 0 turns it off
+pub fn hill_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<HillButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.max_players = false;
+                    switch.respawn_delay = false;
+                    switch.loot_drop = false;
+                    switch.teams = false;
+                    switch.hill = true;
                 }
             }
             Interaction::Hovered => {
@@ -365,6 +628,50 @@ pub fn eid_percentage_but(
     }
 }
 
+/// flips to the next class, our own player is whichever one is showing when we host or join
+pub fn class_but(
+    classes: Res<Classes>,
+    mut chosen: ResMut<ChosenClass>,
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ClassButton>),
+    >,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                chosen.0 = classes.next(chosen.0);
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+/// keeps the class button showing what's picked
+pub fn update_class_button(
+    classes: Res<Classes>,
+    chosen: Res<ChosenClass>,
+    button_query: Query<&Children, With<ClassButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    let class = classes.get(chosen.0);
+    let label = format!("Class: {} ({} HP)", class.name, class.hp);
+    for children in button_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
 pub fn join_port_but(
     mut button_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -562,6 +869,41 @@ pub fn init_eid_percentage_input_system(
     init_input_system_with_default::<EidPercentageInput>("WIP", commands, eid_percentage_query);
 }
 
+pub fn init_max_players_input_system(
+    commands: Commands,
+    max_players_query: Query<(Entity, &mut Text, &mut MaxPlayersInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<MaxPlayersInput>(&DEFAULT_PLAYER_CAP.to_string(), commands, max_players_query);
+}
+
+pub fn init_respawn_delay_input_system(
+    commands: Commands,
+    respawn_delay_query: Query<(Entity, &mut Text, &mut RespawnDelayInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<RespawnDelayInput>(&DEFAULT_RESPAWN_DELAY.to_string(), commands, respawn_delay_query);
+}
+
+pub fn init_loot_drop_input_system(
+    commands: Commands,
+    loot_drop_query: Query<(Entity, &mut Text, &mut LootDropInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<LootDropInput>(&DEFAULT_LOOT_DROP.to_string(), commands, loot_drop_query);
+}
+
+pub fn init_teams_input_system(
+    commands: Commands,
+    teams_query: Query<(Entity, &mut Text, &mut TeamsInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<TeamsInput>("0", commands, teams_query);
+}
+
+pub fn init_hill_input_system(
+    commands: Commands,
+    hill_query: Query<(Entity, &mut Text, &mut HillInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<HillInput>("0", commands, hill_query);
+}
+
 pub fn exit_system(mut exit: EventWriter<AppExit>) {
     exit.send(AppExit);
 }
\ No newline at end of file
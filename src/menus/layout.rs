@@ -5,8 +5,13 @@ use bevy::prelude::DerefMut;
 use bevy::prelude::Timer;
 use bevy::prelude::*;
 use crate::game::camera::SpatialCameraBundle;
+use crate::game::player;
 use crate::game::components::*;
 use crate::game::ROUND_TIME;
+use crate::game::item::HotbarDisplay;
+use crate::game::status::StatusDisplay;
+use crate::game::team::{self, Team};
+use crate::game::hill::HillDisplay;
 use crate::AppState;
 use crate::net::{TICKLEN_S, TickNum};
 
@@ -198,6 +203,11 @@ fn spawn_input<T: Bundle, U: Bundle>(
             enemy_per_camp: false,
             map_seed: false,
             eid_percentage: false,
+            max_players: false,
+            respawn_delay: false,
+            loot_drop: false,
+            teams: false,
+            hill: false,
         },
         button,
     )).id();
@@ -286,6 +296,7 @@ pub fn spawn_main_menu(
     spawn_button(&mut main_menu, &font, HostButton, "Host");
     spawn_button(&mut main_menu, &font, JoinButton, "Join");
     spawn_button(&mut main_menu, &font, ControlsButton, "Controls");
+    spawn_button(&mut main_menu, &font, ReplayButton, "Replay");
     spawn_button(&mut main_menu, &font, CreditsButton, "Credits");
 }
 
@@ -387,11 +398,17 @@ pub fn spawn_host_page(
     spawn_input(&mut host_page_left, &font, NumCampsButton, NumCampsInput { value: String::new() }, "Number of Camps: ");
     spawn_input(&mut host_page_left, &font, NumChestsButton, NumChestsInput { value: String::new() }, "Number of Chests: ");
     spawn_input(&mut host_page_left, &font, EnemiesPerCampButton, EnemiesPerCampInput { value: String::new() }, "Number of Enemies Per Camp: ");
+    spawn_input(&mut host_page_left, &font, LootDropButton, LootDropInput { value: String::new() }, "Loot Drop (%): ");
+    spawn_button(&mut host_page_left, &font, ClassButton, "Class: ");
     let host_page_right_id = spawn_flex_column(&mut commands, ());
     commands.entity(host_page_row_id).add_child(host_page_right_id);
     let mut host_page_right = commands.entity(host_page_right_id);
     spawn_input(&mut host_page_right, &font, MapSeedButton, MapSeedInput { value: String::new() }, "Map Seed: ");
     spawn_input(&mut host_page_right, &font, EidPercentageButton, EidPercentageInput { value: String::new() }, "EID Percentage: ");
+    spawn_input(&mut host_page_right, &font, MaxPlayersButton, MaxPlayersInput { value: String::new() }, "Max Players: ");
+    spawn_input(&mut host_page_right, &font, RespawnDelayButton, RespawnDelayInput { value: String::new() }, "Respawn Delay (s): ");
+    spawn_input(&mut host_page_right, &font, TeamsButton, TeamsInput { value: String::new() }, "Teams (0 = free-for-all): ");
+    spawn_input(&mut host_page_right, &font, HillButton, HillInput { value: String::new() }, "Hill Rotation (s, 0 = off): ");
     spawn_button(&mut host_page_right, &font, HostPortSaveButton, "Host Now");
     spawn_button(&mut host_page_right, &font, BackToMainMenu, "Back");
 }
@@ -417,6 +434,7 @@ pub fn spawn_join_page(
     spawn_input(&mut join_page, &font, JoinPortButton, JoinPortInput { port: String::new() }, "Your Port: ");
     spawn_input(&mut join_page, &font, JoinHostPortButton, JoinHostPortInput { port: String::new() }, "Host Port: ");
     spawn_input(&mut join_page, &font, JoinIpButton, JoinIPInput { ip: String::new() }, "Host IP: ");
+    spawn_button(&mut join_page, &font, ClassButton, "Class: ");
     spawn_button(&mut join_page, &font, JoinSaveButton, "Join Now");
     spawn_button(&mut join_page, &font, BackToMainMenu, "Back");
 }
@@ -438,7 +456,12 @@ pub fn spawn_controls_page(
     let text = commands.spawn(TextBundle::from_section(
         "Movement - WASD\n\
         Attack - Left Click\n\
+        Shield - Right Click\n\
+        Dash - Space\n\
+        Switch Weapon - Q\n\
         Interact - E\n\
+        Use Item - 1 2 3\n\
+        Record Replay - F9\n\
         Quit Game - Esc",
         TextStyle {
             font: font.clone(),
@@ -465,7 +488,8 @@ pub fn despawn_in_game_ui(
 
 pub fn spawn_in_game_ui(
     mut commands: Commands,
-    asset_server: Res<AssetServer>
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     commands.spawn((TextBundle {
@@ -487,6 +511,148 @@ pub fn spawn_in_game_ui(
         ScoreDisplay,
         InGameUi));
 
+    // Dash cooldown, just above the score
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(SCREEN_HEIGHT - PADDING - 64.0 - 40.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "Dash: ready",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        DashDisplay,
+        InGameUi));
+
+    // Which weapon is out, above the dash cooldown
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(SCREEN_HEIGHT - PADDING - 64.0 - 80.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "Weapon: Sword",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        WeaponDisplay,
+        InGameUi));
+
+    // Shield durability, above the weapon
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(SCREEN_HEIGHT - PADDING - 64.0 - 120.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "Shield: 100",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        ShieldDisplay,
+        InGameUi));
+
+    // Buffs and debuffs, above the shield. Sections get filled in as effects come and go
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(SCREEN_HEIGHT - PADDING - 64.0 - 160.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        StatusDisplay,
+        InGameUi));
+
+    // Hotbar, above the buffs
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(SCREEN_HEIGHT - PADDING - 64.0 - 200.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "1: -   2: -   3: -",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        HotbarDisplay,
+        InGameUi));
+
+    // Respawn countdown, under the timer and only while dead
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(SCREEN_WIDTH / 2.0 - 160.0),
+            top: Val::Px(PADDING + 72.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Center),
+        visibility: Visibility::Hidden,
+        ..Default::default()},
+        RespawnDisplay,
+        InGameUi));
+
+    // King of the Hill, under the respawn countdown and only when there's a hill
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(SCREEN_WIDTH / 2.0 - 200.0),
+            top: Val::Px(PADDING + 112.0),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            }
+        ).with_alignment(TextAlignment::Center),
+        visibility: Visibility::Hidden,
+        ..Default::default()},
+        HillDisplay,
+        InGameUi));
+
     // Timer Display
     commands.spawn((TextBundle {
         style: Style {
@@ -509,24 +675,21 @@ pub fn spawn_in_game_ui(
         },
         InGameUi));
 
-    // Powerup Display
-     commands.spawn((ImageBundle {
-        style: Style {
-            position_type: PositionType::Absolute,
-            left: Val::Px(PADDING),
-            top: Val::Px(SCREEN_HEIGHT / 2. - 176.),
-            ..default()
-        },
-        image: asset_server.load("powerup_icons.png").into(),
-        ..default()
-    }, InGameUi));
-
+    // Powerup Display, one row per type down the top left with its icon off the HUD sheet
+    let powerup_icons = texture_atlases.add(TextureAtlas::from_grid(
+        asset_server.load("powerup_icons.png"),
+        Vec2::splat(64.),
+        1,
+        NUM_POWERUPS,
+        Some(Vec2::new(0., 8.)),
+        None
+    ));
     commands.spawn((NodeBundle {
         style: Style {
             position_type: PositionType::Absolute,
-            left: Val::Px(PADDING + 72.),
-            top: Val::Px(SCREEN_HEIGHT / 2. - 137.),
-            row_gap: Val::Px(40.),
+            left: Val::Px(PADDING),
+            top: Val::Px(PADDING),
+            row_gap: Val::Px(4.),
             flex_direction: FlexDirection::Column,
             align_items: AlignItems::Start,
             ..default()
@@ -534,17 +697,40 @@ pub fn spawn_in_game_ui(
         ..default()
     }, InGameUi)).with_children(|parent| {
         for i in 0..NUM_POWERUPS {
-            parent.spawn((
-                TextBundle::from_section(
-                    "1.00x",
-                    TextStyle {
-                        font: font.clone(),
-                        font_size: 32.0,
-                        color: Color::WHITE,
+            parent.spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(8.),
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            }).with_children(|row| {
+                row.spawn(AtlasImageBundle {
+                    style: Style {
+                        width: Val::Px(32.),
+                        height: Val::Px(32.),
+                        ..default()
                     },
-                ),
-                PowerupDisplayText(i as u8), // Dunno whether to attach this to NodeBundle or the individual TextBundles
-            ));
+                    texture_atlas: powerup_icons.clone(),
+                    texture_atlas_image: UiTextureAtlasImage {
+                        index: i,
+                        ..default()
+                    },
+                    ..default()
+                });
+                row.spawn((
+                    TextBundle::from_section(
+                        "1.00x",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 28.0,
+                            color: Color::WHITE,
+                        },
+                    ),
+                    PowerupDisplayText(i as u8),
+                ));
+            });
         }
     });
 }
@@ -591,7 +777,7 @@ pub fn spawn_leaderboard_ui(
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-    let measure_names = ["Player", "Score", "Enemy Kills", "Player Kills", "Camps Captured", "Deaths", "KD"];
+    let measure_names = ["Player", "Score", "Enemy Kills", "Player Kills", "Assists", "Camps Captured", "Deaths", "KD"];
     let leaderboard_entity = commands
         .spawn((NodeBundle {
             style: Style {
@@ -622,7 +808,16 @@ pub fn spawn_leaderboard_ui(
                 color: Color::RED,
             },
         ), LeaderboardUiTitle)).id();
-    commands.entity(leaderboard_entity).push_children(&[title_entity]);
+    let teams_entity = commands
+        .spawn((TextBundle::from_section(
+            String::new(),
+            TextStyle {
+                font: font.clone(),
+                font_size: 36.0,
+                color: Color::WHITE,
+            },
+        ), LeaderboardTeams)).id();
+    commands.entity(leaderboard_entity).push_children(&[title_entity, teams_entity]);
     // field names
     let measures_entity = commands
         .spawn(NodeBundle {
@@ -660,10 +855,10 @@ pub fn spawn_leaderboard_ui(
                     },
                 )
                 .with_style(Style {
-                    width: Val::Percent(100.0 / 21.0),
+                    width: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                     margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
+                        left: Val::Percent(100.0 / LEADERBOARD_SLOTS),
+                        right: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                         top: Val::Px(0.),
                         bottom: Val::Px(0.),
                     },
@@ -672,37 +867,65 @@ pub fn spawn_leaderboard_ui(
             }
         }).id();
     commands.entity(leaderboard_entity).push_children(&[measures_entity]);
-    // player stats
-    let player_icons = vec!["jordan_icon.png", "ian_icon.png", "sam_icon.png", "kevin_icon.png"];
-    for i in 0..4 {
-        let player_stats_entity = commands
-        .spawn(NodeBundle {
+    // the player rows get filled in by update_leaderboard_rows as people join
+}
+
+const PLAYER_ICONS: [&str; 4] = ["jordan_icon.png", "ian_icon.png", "sam_icon.png", "kevin_icon.png"];
+const LEADERBOARD_ROWS_HEIGHT: f32 = 60.0;  // percent of the leaderboard all the rows get to share
+const LEADERBOARD_COLUMNS: u8 = 8;
+const LEADERBOARD_SLOTS: f32 = LEADERBOARD_COLUMNS as f32 * 3.0;  // each column is a slot wide with a slot of margin either side
+
+fn stat_value(stats: &Stats, stat_id: u8) -> String {
+    return match stat_id {
+        1 => stats.score.to_string(),
+        2 => stats.enemies_killed.to_string(),
+        3 => stats.players_killed.to_string(),
+        4 => stats.assists.to_string(),
+        5 => stats.camps_captured.to_string(),
+        6 => stats.deaths.to_string(),
+        7 => stats.kd_ratio.to_string(),
+        _ => String::new(),
+    };
+}
+
+/// scale is 1 for up to four players, and shrinks the rows to fit any more than that
+fn spawn_leaderboard_row(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    font: &Handle<Font>,
+    player_id: u8,
+    stats: &Stats,
+    scale: f32,
+) -> Entity {
+    return commands
+        .spawn((NodeBundle {
             style: Style {
                 width: Val::Percent(70.0),
-                height: Val::Percent(15.0),
+                height: Val::Percent(LEADERBOARD_ROWS_HEIGHT / 4.0 * scale),
                 flex_direction: FlexDirection::Row,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::SpaceEvenly,
                 padding: UiRect {
                     left: Val::Px(20.),
                     right: Val::Px(20.),
-                    top: Val::Px(20.),
-                    bottom: Val::Px(20.),
+                    top: Val::Px(20. * scale),
+                    bottom: Val::Px(20. * scale),
                 },
                 ..default()
             },
             background_color: BackgroundColor(Color::rgba(0.5, 0.5, 0.5, 0.5)),
             ..default()
-        })
+        }, LeaderboardRow(player_id)))
         .with_children(|parent| {
             parent.spawn((ImageBundle {
-                image: asset_server.load(player_icons[i]).into(),
+                image: asset_server.load(PLAYER_ICONS[player_id as usize % PLAYER_ICONS.len()]).into(),
+                background_color: BackgroundColor(player::player_color(player_id)),
                 style: Style {
-                    width: Val::Percent(100.0 / 21.0),
+                    width: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                     max_height: Val::Percent(100.0),
                     margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
+                        left: Val::Percent(100.0 / LEADERBOARD_SLOTS),
+                        right: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                         top: Val::Px(0.),
                         bottom: Val::Px(0.),
                     },
@@ -711,35 +934,73 @@ pub fn spawn_leaderboard_ui(
                 ..default()
             },
             PlayerStatDisplay {
-                player_id: i as u8,
+                player_id,
                 stat_id: 0,
             }));
-            for j in 1..7 {
+            for j in 1..LEADERBOARD_COLUMNS {
                 parent.spawn((TextBundle::from_section(
-                    j.to_string(),
+                    stat_value(stats, j),
                     TextStyle {
                         font: font.clone(),
-                        font_size: 32.0,
+                        font_size: (32.0 * scale).max(14.0),
                         color: Color::WHITE,
                     },
                 )
                 .with_style(Style {
-                    width: Val::Percent(100.0 / 21.0),
+                    width: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                     margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
+                        left: Val::Percent(100.0 / LEADERBOARD_SLOTS),
+                        right: Val::Percent(100.0 / LEADERBOARD_SLOTS),
                         top: Val::Px(0.),
                         bottom: Val::Px(0.),
                     },
                     ..default()
                 }),
                 PlayerStatDisplay {
-                    player_id: i as u8,
-                    stat_id: j as u8,
+                    player_id,
+                    stat_id: j,
                 }));
             }
         }).id();
-        commands.entity(leaderboard_entity).push_children(&[player_stats_entity]);
+}
+
+/// rebuilds the player rows whenever someone joins or leaves, sized to fit everyone
+pub fn update_leaderboard_rows(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    leaderboard: Query<Entity, With<LeaderboardUi>>,
+    rows: Query<(Entity, &LeaderboardRow)>,
+    players: Query<(&Player, &Stats)>,
+) {
+    let leaderboard = leaderboard.get_single();
+    if leaderboard.is_err() { return }
+    let leaderboard = leaderboard.unwrap();
+    let mut roster: Vec<(u8, &Stats)> = players.iter().map(|(pl, stats)| (pl.0, stats)).collect();
+    roster.sort_by_key(|(id, _)| *id);
+    let mut shown: Vec<u8> = rows.iter().map(|(_, row)| row.0).collect();
+    shown.sort();
+    if shown.len() == roster.len() && shown.iter().zip(roster.iter()).all(|(a, (b, _))| a == b) { return }
+
+    for (e, _) in &rows {
+        commands.entity(e).despawn_recursive();
+    }
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let scale = (4.0 / roster.len().max(1) as f32).min(1.0);
+    for (id, stats) in roster {
+        let row = spawn_leaderboard_row(&mut commands, &asset_server, &font, id, stats, scale);
+        commands.entity(leaderboard).add_child(row);
+    }
+}
+
+/// adds up everyone's score by team
+pub fn update_team_totals(
+    mut totals_query: Query<&mut Text, With<LeaderboardTeams>>,
+    players: Query<(&Team, &Stats)>,
+) {
+    let scores = team::team_scores(players.iter().map(|(team, stats)| (*team, stats.score)));
+    let totals: Vec<String> = scores.iter().map(|(team, total)| format!("{}: {}", team.name(), total)).collect();
+    for mut text in &mut totals_query {
+        text.sections[0].value = totals.join("   ");
     }
 }
 
@@ -760,6 +1021,7 @@ pub fn toggle_leaderboard(
     mut minimap_query: Query<&mut Visibility, With<SpatialCameraBundle>>,
     mut leaderboard_query: Query<(Entity, &mut Style), (With<LeaderboardUi>, Without<InGameUi>)>,
     mut leaderboard_title_query: Query<&mut Text, With<LeaderboardUiTitle>>,
+    players: Query<(&Team, &Stats)>,
     app_state_current_state: ResMut<State<AppState>>,
 ) {
     if input.just_pressed(KeyCode::Tab) || *app_state_current_state.get() == AppState::GameOver {
@@ -774,8 +1036,15 @@ pub fn toggle_leaderboard(
         }
         if *app_state_current_state.get() == AppState::GameOver 
         {
+            // in team deathmatch the team with the most points wins
+            let scores = team::team_scores(players.iter().map(|(team, stats)| (*team, stats.score)));
+            let title = match team::winner(&scores) {
+                Some(winner) => format!("Game Over - {} Team Wins", winner.name()),
+                None if scores.is_empty() => "Game Over".to_string(),
+                None => "Game Over - Draw".to_string(),
+            };
             for mut text in &mut leaderboard_title_query.iter_mut() {
-                text.sections[0].value = "Game Over".to_string();
+                text.sections[0].value = title.clone();
             }
             for (leaderboard_id, _) in &mut leaderboard_query.iter_mut() {
                 let font = asset_server.load("fonts/FiraSans-Bold.ttf");
@@ -805,16 +1074,8 @@ pub fn update_leaderboard(
     for (player, stats) in &stats_query {
         for (mut text, identifier) in &mut leaderboard_query {
             if identifier.player_id == player.0 {
-                match identifier.stat_id {
-                    1 => text.sections[0].value = stats.score.to_string(),
-                    2 => text.sections[0].value = stats.enemies_killed.to_string(),
-                    3 => text.sections[0].value = stats.players_killed.to_string(),
-                    4 => text.sections[0].value = stats.camps_captured.to_string(),
-                    5 => text.sections[0].value = stats.deaths.to_string(),
-                    6 => text.sections[0].value = stats.kd_ratio.to_string(),
-                    _ => {}
-                }
+                text.sections[0].value = stat_value(stats, identifier.stat_id);
             }
         }
     }
-}
\ No newline at end of file
+}
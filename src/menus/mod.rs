@@ -52,7 +52,10 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, interact_with_button::<JoinButtonType>.run_if(in_state(AppState::MainMenu)))
         .add_systems(Update, interact_with_button::<ControlsButtonType>.run_if(in_state(AppState::MainMenu)))
         .add_systems(Update, interact_with_button::<CreditsButtonType>.run_if(in_state(AppState::MainMenu)))
+        .add_systems(Update, interact_with_button::<ReplayButtonType>.run_if(in_state(AppState::MainMenu)))
         .add_systems(Update, toggle_leaderboard.run_if(in_state(AppState::Game)))
+        .add_systems(Update, update_leaderboard_rows.run_if(in_state(AppState::Game)))
+        .add_systems(Update, update_team_totals.run_if(in_state(AppState::Game)))
         .add_systems(Update, update_leaderboard.run_if(in_state(AppState::Game)))
         .add_systems(Update, interact_with_button::<CreditsButtonType>.run_if(in_state(AppState::GameOver)))
         .add_systems(Update, interact_with_button::<BackButtonType>)
@@ -63,6 +66,11 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, update_enemies_per_camp_input)
         .add_systems(Update, update_map_seed_input)
         .add_systems(Update, update_eid_percentage_input)
+        .add_systems(Update, update_max_players_input)
+        .add_systems(Update, update_respawn_delay_input)
+        .add_systems(Update, update_loot_drop_input)
+        .add_systems(Update, update_teams_input)
+        .add_systems(Update, update_hill_input)
         .add_systems(Update, update_time_remaining_system.run_if(in_state(AppState::Game)))
         .add_systems(Update, save_host_input)
         .add_systems(Update, update_join_port_input)
@@ -73,6 +81,13 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, enemy_per_camp_but)
         .add_systems(Update, map_seed_but)
         .add_systems(Update, eid_percentage_but)
+        .add_systems(Update, max_players_but)
+        .add_systems(Update, respawn_delay_but)
+        .add_systems(Update, loot_drop_but)
+        .add_systems(Update, teams_but)
+        .add_systems(Update, hill_but)
+        .add_systems(Update, class_but)
+        .add_systems(Update, update_class_button)
         .add_systems(Update, update_join_ip_input)
         .add_systems(Update, join_port_but)
         .add_systems(Update, host_port_but)
@@ -87,6 +102,11 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, init_enemies_per_camp_input_system)
         .add_systems(Update, init_map_seed_input_system)
         .add_systems(Update, init_eid_percentage_input_system)
+        .add_systems(Update, init_max_players_input_system)
+        .add_systems(Update, init_respawn_delay_input_system)
+        .add_systems(Update, init_loot_drop_input_system)
+        .add_systems(Update, init_teams_input_system)
+        .add_systems(Update, init_hill_input_system)
         .add_systems(Update, bevy::window::close_on_esc)
         .add_systems(Update, animate.run_if(in_state(AppState::MainMenu)))
         .add_systems(Startup, startup);
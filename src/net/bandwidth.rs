@@ -0,0 +1,187 @@
+use std::collections::{HashMap, VecDeque};
+use bevy::prelude::*;
+use crate::net::{MAX_DATAGRAM_SIZE, TICKRATE, TickNum};
+use crate::net::host::Connections;
+
+pub const DEFAULT_BUDGET: u32 = 16_000;  // bytes per second per connection
+const MIN_BUDGET: u32 = 2_000;
+const BUDGET_STEP: u32 = 2_000;
+const MIN_RATE: f32 = 2_000.;  // loss never pushes a connection's rate below this
+const LOSS_THRESHOLD: f32 = 0.1;  // more loss than this and we back off
+const BACKOFF: f32 = 0.75;  // rate multiplier when we're over the loss threshold
+const BACKOFF_TICKS: u16 = TICKRATE as u16;  // loss is measured over a few seconds, so only back off once in a while
+const RECOVERY: f32 = 200.;  // bytes per second the rate climbs back each tick without loss
+const HISTORY_TICKS: u16 = 32;  // as far back as a client's ack bitfield goes
+pub const STATS_KEY: KeyCode = KeyCode::F3;
+
+/// What we know about the link to one client
+pub struct LinkStats {
+    pub rate: f32,  // bytes per second we're currently letting through, at most the budget
+    tokens: f32,  // bytes we can send right now
+    sent: VecDeque<(u16, usize)>,  // (tick, bytes) of every HostTick sent in the last HISTORY_TICKS
+    pub loss: f32,  // fraction of recent HostTicks the client says it never got
+    pub throttled: bool,  // whether the last tick had to be trimmed or skipped
+    pub skipped: u32,  // ticks skipped since the last report
+    last_backoff: u16,
+}
+
+impl LinkStats {
+    pub fn new(budget: u32) -> LinkStats {
+        return LinkStats {
+            rate: budget as f32,
+            tokens: MAX_DATAGRAM_SIZE as f32,
+            sent: VecDeque::new(),
+            loss: 0.,
+            throttled: false,
+            skipped: 0,
+            last_backoff: 0,
+        };
+    }
+
+    /// bytes sent over the last second
+    pub fn throughput(&self, tick: u16) -> usize {
+        return self.sent.iter()
+            .filter(|(sent, _)| tick.wrapping_sub(*sent) < TICKRATE as u16)
+            .map(|(_, bytes)| *bytes)
+            .sum();
+    }
+
+    /// Called once a tick before sending. Works out loss from the client's latest ack,
+    /// backs off or recovers the rate, and tops up the allowance
+    pub fn update(&mut self, tick: u16, budget: u32, host_ack: u16, ack_bits: u32) {
+        while self.sent.front().is_some_and(|(sent, _)| tick.wrapping_sub(*sent) > HISTORY_TICKS) {
+            self.sent.pop_front();
+        }
+        let mut total = 0;
+        let mut missed = 0;
+        for (sent, _) in &self.sent {
+            // anything newer than their ack might still be on its way
+            let age = host_ack.wrapping_sub(*sent);
+            if age > HISTORY_TICKS { continue }
+            total += 1;
+            if age != 0 && ack_bits & (1 << (age - 1)) == 0 {
+                missed += 1;
+            }
+        }
+        self.loss = if total == 0 { 0. } else { missed as f32 / total as f32 };
+        if self.loss > LOSS_THRESHOLD {
+            if tick.wrapping_sub(self.last_backoff) >= BACKOFF_TICKS {
+                self.rate = (self.rate * BACKOFF).max(MIN_RATE);
+                self.last_backoff = tick;
+            }
+        }
+        else {
+            self.rate += RECOVERY;
+        }
+        self.rate = self.rate.min(budget as f32);
+        // let it save up one datagram so a full packet doesn't get stuck on a slow link, and no more than that.
+        // Bigger payloads have to be split by whoever's sending them
+        self.tokens = (self.tokens + self.rate / TICKRATE as f32).min(MAX_DATAGRAM_SIZE as f32);
+    }
+
+    /// whether a packet this big fits in what's left this tick, and in one datagram
+    pub fn fits(&self, bytes: usize) -> bool {
+        return bytes <= MAX_DATAGRAM_SIZE && bytes as f32 <= self.tokens;
+    }
+
+    pub fn spend(&mut self, tick: u16, bytes: usize) {
+        self.tokens -= bytes as f32;
+        self.sent.push_back((tick, bytes));
+    }
+
+    /// returns true when this connection just started or stopped being throttled, so it can be reported
+    pub fn set_throttled(&mut self, throttled: bool) -> bool {
+        let changed = throttled != self.throttled;
+        self.throttled = throttled;
+        return changed;
+    }
+}
+
+/// The host's per connection bandwidth bookkeeping, by player id
+#[derive(Resource)]
+pub struct Bandwidth {
+    pub budget: u32,  // bytes per second each connection is allowed
+    pub links: HashMap<u8, LinkStats>,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Bandwidth {
+        return Bandwidth { budget: DEFAULT_BUDGET, links: HashMap::new() };
+    }
+}
+
+#[derive(Component)]
+pub struct NetStatsHud;
+
+/// F3 shows the host's bandwidth numbers, and [ and ] change the budget while it's up
+pub fn toggle_net_stats(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    input: Res<Input<KeyCode>>,
+    mut bandwidth: ResMut<Bandwidth>,
+    huds: Query<Entity, With<NetStatsHud>>,
+) {
+    if input.just_pressed(STATS_KEY) {
+        if huds.is_empty() {
+            commands.spawn((TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(20.),
+                    top: Val::Px(80.),
+                    ..Default::default()
+                },
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    }
+                ),
+                ..Default::default()},
+                NetStatsHud));
+        }
+        for e in &huds {
+            commands.entity(e).despawn_recursive();
+        }
+    }
+    if huds.is_empty() { return }
+    if input.just_pressed(KeyCode::BracketRight) {
+        bandwidth.budget += BUDGET_STEP;
+    }
+    if input.just_pressed(KeyCode::BracketLeft) {
+        bandwidth.budget = bandwidth.budget.saturating_sub(BUDGET_STEP).max(MIN_BUDGET);
+    }
+}
+
+pub fn update_net_stats(
+    bandwidth: Res<Bandwidth>,
+    conns: Res<Connections>,
+    tick: Res<TickNum>,
+    mut huds: Query<&mut Text, With<NetStatsHud>>,
+) {
+    let mut lines = format!("budget {} B/s per player  [ ] to change\n", bandwidth.budget);
+    for conn in conns.0.iter().flatten() {
+        let link = bandwidth.links.get(&conn.player_id);
+        if link.is_none() { continue }
+        let link = link.unwrap();
+        lines.push_str(&format!("P{}  {} B/s  limit {:.0}  loss {:.0}%{}\n",
+            conn.player_id + 1,
+            link.throughput(tick.0),
+            link.rate,
+            link.loss * 100.,
+            if link.throttled { "  THROTTLED" } else { "" }));
+    }
+    for mut text in &mut huds {
+        text.sections[0].value = lines.clone();
+    }
+}
+
+pub fn despawn_net_stats(
+    mut commands: Commands,
+    huds: Query<Entity, With<NetStatsHud>>,
+) {
+    for e in &huds {
+        commands.entity(e).despawn_recursive();
+    }
+}
@@ -0,0 +1,279 @@
+//! Headless bots for load testing a host: `jordquest --bot <host ip:port> [count]`.
+//! Each bot is a thread speaking the real protocol, so it looks like any other client to the host.
+//! They wander, swing at whatever's close, and respawn somewhere random when the host lets them
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+use crate::game::class::Classes;
+use crate::game::map::{self, Biome, WorldMap, MAPSIZE, TILESIZE};
+use crate::game::movement::correct_wall_collisions;
+use crate::game::player::{self, Respawn, ATTACK_BITFLAG, PLAYER_SIZE, SPAWN_BITFLAG, SPAWN_CLEARANCE};
+use crate::game::weapon::{self, Weapons};
+use crate::net::{event_newer, Ack, MAGIC_NUMBER, MAX_DATAGRAM_SIZE, TICKLEN_S};
+use crate::net::packets::*;
+
+const RETRY_TICKS: u32 = 10;  // how long to wait for a ConnectionResponse before asking again
+const SPAWN_TRIES: usize = 10;  // random tiles to try before spawning somewhere crowded anyway
+const CHASE_DISTANCE: f32 = 200.;  // enemies closer than this get chased instead of wandering
+const WANDER_DISTANCE: usize = 20;  // in tiles
+const STUCK_TICKS: u32 = 20;  // give up on a wander target after this long
+const REPORT_TICKS: u32 = 100;
+
+/// reads the bot arguments and runs that many bots until the process is killed
+pub fn run(args: &[String]) {
+    let host = args.get(0).and_then(|arg| SocketAddr::from_str(arg).ok());
+    if host.is_none() {
+        println!("usage: jordquest --bot <host ip:port> [count]");
+        return;
+    }
+    let host = host.unwrap();
+    let count = args.get(1).and_then(|arg| usize::from_str(arg).ok()).unwrap_or(1);
+    println!("starting {} bots against {}", count, host);
+    let mut handles = Vec::new();
+    for n in 0..count {
+        handles.push(thread::spawn(move || Bot::connect(n, host).run()));
+        // don't all show up on the same tick
+        thread::sleep(Duration::from_millis(50));
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+struct Bot {
+    n: usize,  // which bot this is in this process, for logging
+    sock: UdpSocket,
+    host: SocketAddr,
+    player_id: u8,
+    map: WorldMap,
+    weapons: Weapons,
+    weapon: u8,
+    classes: Classes,
+    class: u8,
+    rng: ChaChaRng,
+    tick: u16,
+    ack: Ack,
+    event_ack: u16,
+    pos: Vec2,
+    dir: f32,
+    hp: u8,
+    target: Vec2,
+    target_ticks: u32,
+    respawn: Respawn,
+    cooldown: u32,
+    enemies: Vec<Vec2>,
+    // stats for the periodic report
+    ticks_received: u32,
+    bytes_received: usize,
+    malformed: u32,
+}
+
+impl Bot {
+    /// blocks until the host lets this bot in
+    fn connect(n: usize, host: SocketAddr) -> Bot {
+        let sock = UdpSocket::bind("0.0.0.0:0").expect("bot can't bind a socket");
+        sock.connect(host).expect("bot can't connect to host");
+        sock.set_read_timeout(Some(Duration::from_secs_f32(TICKLEN_S * RETRY_TICKS as f32))).expect("can't set read timeout");
+        // any class will do, so long as they aren't all the same
+        let classes = Classes::load();
+        let class = rand::thread_rng().gen_range(0..classes.0.len()) as u8;
+        let mut request: Vec<u8> = Vec::new();
+        ConnectionRequest { class }.to_buf(&mut request);
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        let response = loop {
+            send_buf(request.as_slice(), &sock, &host).expect("bot failed to request connection");
+            let len = sock.recv(&mut buf);
+            if len.is_err() { continue }
+            let len = len.unwrap();
+            if len < 3 || u16::from_be_bytes(buf[0..2].try_into().unwrap()) != MAGIC_NUMBER { continue }
+            if buf[2] == PacketType::ServerFull as u8 {
+                println!("bot {}: server is full, trying again", n);
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            if buf[2] != PacketType::ConnectionResponse as u8 { continue }
+            if let Ok(response) = ConnectionResponse::from_buf(&buf[3..len]) {
+                break response;
+            }
+        };
+        println!("bot {} connected as player {}, a {}", n, response.player_id, classes.get(class).name);
+        sock.set_nonblocking(true).expect("can't set nonblocking");
+        // the same map everyone else generated, so the bot knows where the walls are
+        let mut world_map = WorldMap {
+            map_size: MAPSIZE,
+            tile_size: TILESIZE,
+            biome_map: [[Biome::Free; MAPSIZE]; MAPSIZE],
+        };
+        let mut map_rng = ChaChaRng::seed_from_u64(response.seed);
+        let _ = map::read_map(&mut world_map, &mut Vec::new(), response.num_camps, &mut map_rng, &mut Vec::new());
+        return Bot {
+            n,
+            sock,
+            host,
+            player_id: response.player_id,
+            map: world_map,
+            weapons: Weapons::load(),
+            weapon: weapon::SWORD,
+            classes,
+            class,
+            rng: ChaChaRng::seed_from_u64(response.seed ^ (response.player_id as u64 + 1)),
+            tick: 0,
+            ack: Ack { rmt_num: 0, bitfield: 0 },
+            event_ack: 0,
+            pos: Vec2::ZERO,
+            dir: 0.,
+            hp: 0,
+            target: Vec2::ZERO,
+            target_ticks: 0,
+            respawn: Respawn::default(),
+            cooldown: 0,
+            enemies: Vec::new(),
+            ticks_received: 0,
+            bytes_received: 0,
+            malformed: 0,
+        };
+    }
+
+    fn run(mut self) {
+        let start = Instant::now();
+        let mut ticks_run: u32 = 0;
+        loop {
+            self.receive();
+            let events = self.think();
+            self.send(events);
+            ticks_run += 1;
+            if ticks_run % REPORT_TICKS == 0 {
+                let secs = REPORT_TICKS as f32 * TICKLEN_S;
+                println!("bot {} (player {}): {} HostTicks, {:.0} B/s, {} malformed, hp {}",
+                    self.n, self.player_id, self.ticks_received, self.bytes_received as f32 / secs, self.malformed, self.hp);
+                self.ticks_received = 0;
+                self.bytes_received = 0;
+                self.malformed = 0;
+            }
+            self.tick = self.tick.wrapping_add(1);
+            // sleep until the next tick, without drifting
+            let next = Duration::from_secs_f32(TICKLEN_S * ticks_run as f32);
+            thread::sleep(next.saturating_sub(start.elapsed()));
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        while let Ok(len) = self.sock.recv(&mut buf) {
+            if len < 3 || u16::from_be_bytes(buf[0..2].try_into().unwrap()) != MAGIC_NUMBER { continue }
+            if buf[2] != PacketType::HostTick as u8 { continue }
+            self.bytes_received += len;
+            let packet = HostTick::from_buf(&buf[3..len]);
+            if packet.is_err() {
+                self.malformed += 1;
+                continue;
+            }
+            let packet = packet.unwrap();
+            self.ticks_received += 1;
+            self.ack.receive(packet.seq_num);
+            if packet.seq_num > self.tick {
+                self.tick = packet.seq_num;
+            }
+            for (id, _) in &packet.events {
+                if event_newer(*id, self.event_ack) {
+                    self.event_ack = *id;
+                }
+            }
+            if let Some(me) = packet.players.iter().find(|pl| pl.id == self.player_id) {
+                self.hp = me.hp;
+                self.weapon = me.weapon;
+                self.respawn = me.respawn;
+            }
+            self.enemies = packet.enemies.iter().filter(|en| en.hp > 0).map(|en| en.pos).collect();
+        }
+    }
+
+    /// decides what to do this tick and returns the event bits for it
+    fn think(&mut self) -> u8 {
+        if self.hp == 0 {
+            if self.respawn.waiting(self.tick) > 0 { return 0 }
+            self.pos = self.random_tile(None);
+            for _ in 0..SPAWN_TRIES {
+                if player::spawn_clear(self.pos, self.enemies.iter().copied(), SPAWN_CLEARANCE) { break }
+                self.pos = self.random_tile(None);
+            }
+            self.hp = 1;  // until the host says otherwise, so we don't spawn again next tick
+            self.target_ticks = STUCK_TICKS;
+            return SPAWN_BITFLAG;
+        }
+        let mut events = 0;
+        self.cooldown = self.cooldown.saturating_sub(1);
+        let nearest = self.enemies.iter()
+            .min_by(|a, b| a.distance(self.pos).total_cmp(&b.distance(self.pos)))
+            .copied()
+            .filter(|en| en.distance(self.pos) < CHASE_DISTANCE);
+        if let Some(enemy) = nearest {
+            self.target = enemy;
+            let weapon = self.weapons.get(self.weapon);
+            if enemy.distance(self.pos) < weapon.reach && self.cooldown == 0 {
+                events |= ATTACK_BITFLAG;
+                self.cooldown = (weapon.cooldown * self.classes.get(self.class).cooldown / TICKLEN_S).ceil() as u32;
+            }
+        }
+        else {
+            self.target_ticks += 1;
+            if self.target_ticks >= STUCK_TICKS || self.target.distance(self.pos) < TILESIZE as f32 {
+                self.target = self.random_tile(Some(self.pos));
+                self.target_ticks = 0;
+            }
+        }
+        let to_target = self.target - self.pos;
+        if to_target.length() > 1. {
+            self.dir = to_target.y.atan2(to_target.x);
+            let step = to_target.clamp_length_max(self.classes.get(self.class).speed * TICKLEN_S);
+            let moved = correct_wall_collisions(&(self.pos + step).extend(0.), &PLAYER_SIZE, &self.map.biome_map);
+            self.pos = moved.truncate();
+        }
+        return events;
+    }
+
+    fn send(&mut self, events: u8) {
+        let packet = ClientTick {
+            seq_num: self.tick,
+            rmt_num: self.ack.rmt_num,
+            ack: self.ack.bitfield,
+            client_time: self.tick as f32,
+            event_ack: self.event_ack,
+            tick: UserCmd {
+                pos: self.pos,
+                dir: self.dir,
+                events,
+            },
+        };
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        if send_buf(bytes.as_slice(), &self.sock, &self.host).is_err() {
+            println!("bot {}: ClientTick send failed", self.n);
+        }
+    }
+
+    /// a random tile that isn't a wall, anywhere or within WANDER_DISTANCE tiles of near
+    fn random_tile(&mut self, near: Option<Vec2>) -> Vec2 {
+        loop {
+            let (row, col) = match near {
+                Some(near) => {
+                    let col = (near.x / TILESIZE as f32 + (MAPSIZE / 2) as f32) as isize;
+                    let row = (-near.y / TILESIZE as f32 + (MAPSIZE / 2) as f32) as isize;
+                    let spread = WANDER_DISTANCE as isize;
+                    (row + self.rng.gen_range(-spread..=spread), col + self.rng.gen_range(-spread..=spread))
+                },
+                None => (self.rng.gen_range(0..MAPSIZE) as isize, self.rng.gen_range(0..MAPSIZE) as isize),
+            };
+            if map::get_biome_from_map(row, col, &self.map.biome_map) == Biome::Wall { continue }
+            // same conversion as picking a spawn on the minimap
+            return Vec2::new(
+                (col as f32 - (MAPSIZE / 2) as f32) * TILESIZE as f32,
+                -(row as f32 - (MAPSIZE / 2) as f32) * TILESIZE as f32,
+            );
+        }
+    }
+}
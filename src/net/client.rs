@@ -1,17 +1,24 @@
 use std::net::*;
 use std::str::FromStr;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use crate::{menus, net};
 use crate::game::buffers::{DirBuffer, EventBuffer, PosBuffer};
-use crate::game::components::{Camp, CampEnemies, CampStatus, Health, ItemChest, PowerUp};
-use crate::game::map::MapSeed;
+use crate::game::class::ChosenClass;
+use crate::game::components::{Camp, CampEnemies, CampStatus, Health, ItemChest, NetworkId};
+use crate::game::events::{GameEvent, GameplayEvent};
+use crate::game::hill::Hill;
+use crate::game::map::{MapSeed, NumCamps};
 use crate::game::player::{LocalPlayer, SetIdEvent};
+use crate::game::powerup::{self, NetworkedPowerups, NO_GRABBER, PowerupGrabEvent};
+use crate::game::projectile::{NetworkedProjectiles, ProjectileTickEvent};
 use crate::game::PowerupAtlas;
-use crate::net::{MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
+use crate::net::{clock, replay, MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
 use crate::net::packets::*;
 
 pub fn connect(
     addresses: Res<menus::NetworkAddresses>,
+    chosen: Res<ChosenClass>,
     mut sock: ResMut<net::Socket>
 ) {
     // I think if you communicate over LAN, you have to use local ip rather than loopback ip
@@ -25,18 +32,33 @@ pub fn connect(
     let host_addr = SocketAddr::new(IpAddr::from(host_ip), host_port);
     let host = sock.0.as_mut().unwrap();
     host.connect(host_addr).expect("can't connect to host");
-    send_empty_packet(PacketType::ConnectionRequest, host, &host_addr).expect("failed to request connection");
+    let mut bytes: Vec<u8> = Vec::new();
+    ConnectionRequest { class: chosen.0 }.to_buf(&mut bytes);
+    send_buf(bytes.as_slice(), host, &host_addr).expect("failed to request connection");
 }
 
-pub fn disconnect(mut sock: ResMut<net::Socket>) {
-    sock.0.take();
+pub fn disconnect(
+    mut sock: ResMut<net::Socket>,
+    mut ack: ResMut<net::Ack>,
+    mut event_ack: ResMut<net::EventAck>,
+) {
+    if let Some(host) = sock.0.take() {
+        // let the host know we're gone instead of making it wait for us to time out
+        if let Ok(host_addr) = host.peer_addr() {
+            let _ = send_empty_packet(PacketType::Disconnect, &host, &host_addr);
+        }
+    }
+    *ack = net::Ack { rmt_num: 0, bitfield: 0 };
+    event_ack.0 = 0;
 }
 
 pub fn fixed(
     mut sock: ResMut<net::Socket>,
     tick: Res<net::TickNum>,
+    fixed_time: Res<FixedTime>,
     players: Query<(&PosBuffer, &EventBuffer, &DirBuffer), With<LocalPlayer>>,
     ack: Res<net::Ack>,
+    event_ack: Res<net::EventAck>,
 ) {
     if sock.0.is_none() { return }
     let sock = sock.0.as_mut().unwrap();
@@ -53,6 +75,8 @@ pub fn fixed(
         seq_num: tick.0,
         rmt_num: ack.rmt_num,
         ack: ack.bitfield,
+        client_time: clock::now(&tick, &fixed_time),
+        event_ack: event_ack.0,
         tick: UserCmd {
             pos,
             dir,
@@ -64,32 +88,157 @@ pub fn fixed(
     send_buf(bytes.as_slice(), sock, &sock.peer_addr().expect("Sock not connected during fixed")).expect("ClientTick send failed");
 }
 
+/// Everything needed to apply a HostTick to the world, shared by the live client and the replay player
+#[derive(SystemParam)]
+pub struct HostTickApplier<'w, 's> {
+    commands: Commands<'w, 's>,
+    player_writer: EventWriter<'w, PlayerTickEvent>,
+    enemy_writer: EventWriter<'w, EnemyTickEvent>,
+    grab_writer: EventWriter<'w, PowerupGrabEvent>,
+    gameplay_writer: EventWriter<'w, GameplayEvent>,
+    projectile_writer: EventWriter<'w, ProjectileTickEvent>,
+    event_ack: ResMut<'w, net::EventAck>,
+    powerup_atlas: Res<'w, PowerupAtlas>,
+    powerups: ResMut<'w, NetworkedPowerups>,
+    projectiles: ResMut<'w, NetworkedProjectiles>,
+    camps: Query<'w, 's, (&'static Camp, &'static mut CampStatus, &'static mut CampEnemies)>,
+    chests: Query<'w, 's, (&'static ItemChest, &'static mut Health)>,
+    hill: ResMut<'w, Hill>,
+}
+
+impl<'w, 's> HostTickApplier<'w, 's> {
+    /// spawns and despawns get resent until acked, so both have to be safe to see more than once
+    pub fn apply_powerups(&mut self, spawns: &[PowerupSpawn], despawns: &[PowerupDespawn], effects: bool) {
+        for spawn in spawns {
+            if self.powerups.0.contains_key(&spawn.id) { continue }  // already have it, or it's already gone
+            let e = powerup::spawn_powerup(&mut self.commands, &self.powerup_atlas, NetworkId(spawn.id), spawn.ptype, spawn.pos);
+            self.powerups.0.insert(spawn.id, Some((e, spawn.ptype, spawn.pos)));
+        }
+        for despawn in despawns {
+            // remember it's gone even if we never saw the spawn, so a late one doesn't bring it back
+            let prev = self.powerups.0.insert(despawn.id, None);
+            if let Some(Some((e, _, pos))) = prev {
+                self.commands.entity(e).despawn();
+                if effects && despawn.grabbed_by != NO_GRABBER {
+                    self.grab_writer.send(PowerupGrabEvent {
+                        id: despawn.id,
+                        player: despawn.grabbed_by,
+                        pos,
+                    });
+                }
+            }
+        }
+    }
+
+    /// adds what we know that the host only sends as changes to packet, for the first frame of a recording
+    pub fn add_known(&self, packet: &mut HostTick) {
+        for (id, powerup) in &self.powerups.0 {
+            if let Some((_, ptype, pos)) = powerup {
+                if packet.powerup_spawns.iter().any(|spawn| spawn.id == *id) { continue }
+                packet.powerup_spawns.push(PowerupSpawn { id: *id, ptype: *ptype, pos: *pos });
+            }
+        }
+        for (chest, hp) in &self.chests {
+            if packet.chests.iter().any(|(id, _)| *id == chest.id) { continue }
+            packet.chests.push((chest.id, hp.current));
+        }
+    }
+
+    /// events can show up in several HostTicks until we ack them, so only pass on ones newer than we've seen
+    pub fn apply_events(&mut self, events: &[(u16, GameEvent)]) {
+        for (id, event) in events {
+            if !net::event_newer(*id, self.event_ack.0) { continue }
+            self.gameplay_writer.send(GameplayEvent(*event));
+            self.event_ack.0 = *id;
+        }
+    }
+
+    /// start taking events from wherever the replay jumped to
+    pub fn reset_events(&mut self) {
+        self.event_ack.0 = 0;
+    }
+
+    /// forget every powerup, for when the replay jumps around
+    pub fn clear_powerups(&mut self) {
+        for (e, _, _) in self.powerups.0.values().flatten() {
+            self.commands.entity(*e).despawn();
+        }
+        self.powerups.0.clear();
+    }
+
+    /// forget every projectile, for when the replay jumps around
+    pub fn clear_projectiles(&mut self) {
+        for e in self.projectiles.entities.values() {
+            self.commands.entity(*e).despawn();
+        }
+        *self.projectiles = NetworkedProjectiles::default();
+    }
+
+    pub fn apply(&mut self, packet: HostTick) {
+        for tick in packet.players {
+            self.player_writer.send(PlayerTickEvent {
+                seq_num: packet.seq_num,
+                tick
+            })
+        }
+        for tick in packet.enemies {
+            self.enemy_writer.send(EnemyTickEvent {
+                seq_num: packet.seq_num,
+                tick
+            })
+        }
+        self.projectile_writer.send(ProjectileTickEvent {
+            seq_num: packet.seq_num,
+            projectiles: packet.projectiles,
+        });
+        self.apply_powerups(&packet.powerup_spawns, &packet.powerup_despawns, true);
+        self.apply_events(&packet.events);
+        // only active camps get sent, so any camp that's missing has been cleared
+        for (camp, mut status, mut campcount) in self.camps.iter_mut() {
+            let active = packet.camps.iter().find(|(camp_id, _)| *camp_id == camp.0);
+            status.0 = active.is_some();
+            campcount.current_enemies = active.map_or(0, |(_, count)| *count);
+        }
+        self.apply_chests(&packet.chests);
+        *self.hill = packet.hill;
+    }
+
+    /// chests only get sent when they change
+    pub fn apply_chests(&mut self, chests: &[(u16, u8)]) {
+        for (net_ic, net_hp) in chests {
+            for (ic, mut hp) in &mut self.chests {
+                if ic.id == *net_ic {
+                    hp.current = *net_hp;
+                }
+            }
+        }
+    }
+}
+
 pub fn update(
-    mut commands: Commands,
     mut sock: ResMut<net::Socket>,
-    mut player_writer: EventWriter<PlayerTickEvent>,
-    mut enemy_writer: EventWriter<EnemyTickEvent>,
+    mut applier: HostTickApplier,
     mut id_writer: EventWriter<SetIdEvent>,
     mut tick_num: ResMut<net::TickNum>,
+    fixed_time: Res<FixedTime>,
+    mut clock_sync: ResMut<clock::ClockSync>,
+    mut recorder: ResMut<replay::Recorder>,
+    mut ack: ResMut<net::Ack>,
     mut seed: ResMut<MapSeed>,
-    powerup_atlas: Res<PowerupAtlas>,
-    mut powerups: Query<Entity, With<PowerUp>>,
-    mut camps: Query<(&Camp, &mut CampStatus, &mut CampEnemies)>,
-    mut chests: Query<(&ItemChest, &mut Health)>
-
+    mut num_camps: ResMut<NumCamps>,
 ) {
     if sock.0.is_none() { return }
     let sock = sock.0.as_mut().unwrap();
     loop {
         let mut buf = [0; MAX_DATAGRAM_SIZE];
         if sock.peek(&mut buf).is_err() { break }
-        sock.recv(&mut buf).unwrap();
+        let len = sock.recv(&mut buf).unwrap();
         let magic = u16::from_be_bytes(buf[0..2].try_into().unwrap());
         if magic != MAGIC_NUMBER { break; }
         let pt = u8::from_be_bytes(buf[2..3].try_into().unwrap());
         match pt {
             pt if pt == PacketType::ConnectionResponse as u8 => {
-                let packet = ConnectionResponse::from_buf(&buf[3..]);
+                let packet = ConnectionResponse::from_buf(&buf[3..len]);
                 if packet.is_err() {
                     println!("Malformed ConnectionResponse Received!");
                     continue;
@@ -97,66 +246,37 @@ pub fn update(
                 let packet = packet.unwrap();
                 println!("ConnectionResponse received");
                 seed.0 = packet.seed;
+                num_camps.0 = packet.num_camps;
                 id_writer.send(SetIdEvent(packet.player_id));
             },
             pt if pt == PacketType::HostTick as u8 => {
-                let packet = HostTick::from_buf(&buf[3..]);
+                let packet = HostTick::from_buf(&buf[3..len]);
                 if packet.is_err() {
                     println!("Malformed HostTick Received!");
                     continue;
                 }
                 let packet = packet.unwrap();
-                for tick in packet.players {
-                    player_writer.send(PlayerTickEvent {
-                        seq_num: packet.seq_num,
-                        tick
-                    })
-                }
-                for tick in packet.enemies {
-                    enemy_writer.send(EnemyTickEvent {
-                        seq_num: packet.seq_num,
-                        tick
-                    })
-                }
-                for e in &mut powerups {
-                    commands.entity(e).despawn();
-                }
-                for (ptype, pos) in packet.powerups {
-                    commands.spawn((
-                        SpriteSheetBundle{
-                            texture_atlas: powerup_atlas.handle.clone(),
-                            sprite: TextureAtlasSprite {
-                                index: powerup_atlas.coord_to_index(0, ptype as i32),
-                                ..Default::default()
-                            },
-                            transform: Transform {
-                                translation: Vec3 { x: pos.x, y: pos.y, z: 0.0 },
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        },
-                        PowerUp(ptype),
-                        ));
+                if recorder.needs_snapshot() {
+                    // powerups and chests only come as they change, so a recording started mid-match
+                    // has to begin with everything we already know about them
+                    let mut first = HostTick::from_buf(&buf[3..len]).unwrap();
+                    applier.add_known(&mut first);
+                    recorder.record(&first);
                 }
-                for (camp_id, count) in packet.camps {
-                    for (camp, mut status, mut campcount) in camps.iter_mut() {
-                        if camp.0 == camp_id {
-                            status.0 = true;
-                            campcount.current_enemies = count;
-                        }
-                    }
+                else {
+                    recorder.record_raw(&buf[3..len]);
                 }
-                for (net_ic, net_hp) in packet.chests {
-                    for (ic, mut hp) in &mut chests {
-                        if ic.id == net_ic {
-                            hp.current = net_hp;
-                        }
-                    }
+                ack.receive(packet.seq_num);
+                if packet.echo_time >= 0. {
+                    let now = clock::now(&tick_num, &fixed_time);
+                    clock_sync.add_sample(packet.echo_time, packet.recv_time, packet.host_time, now);
                 }
-                if packet.seq_num > tick_num.0 {
+                else if packet.seq_num > tick_num.0 {
+                    // the host hasn't heard from us yet so there's nothing to sync against, just catch up
                     println!("re-syncing: changing tick from {} to {}", tick_num.0, packet.seq_num);
                     tick_num.0 = packet.seq_num;
                 }
+                applier.apply(packet);
             },
             pt if pt == PacketType::ServerFull as u8 => {
                 println!("Server is full!");
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::net::{TICKLEN_S, TickNum};
+
+pub const SYNC_SAMPLES: usize = 8;
+const SNAP_TICKS: f32 = 5.;  // offsets bigger than this are jumped instead of dilated away
+const DILATION_GAIN: f32 = 0.05;  // fraction of the tick length changed per tick of offset
+const MAX_DILATION: f32 = 0.1;  // never run the clock more than 10% fast or slow
+
+/// NTP-style estimate of how far the host's tick clock is ahead of ours.
+/// All times are in fractional ticks (tick number + how far we are through it).
+#[derive(Resource)]
+pub struct ClockSync {
+    samples: VecDeque<(f32, f32)>,  // (round trip time, offset)
+}
+
+impl ClockSync {
+    pub fn new() -> ClockSync {
+        return ClockSync { samples: VecDeque::with_capacity(SYNC_SAMPLES) };
+    }
+
+    /// t0: client send, t1: host receive, t2: host send, t3: client receive
+    pub fn add_sample(&mut self, t0: f32, t1: f32, t2: f32, t3: f32) {
+        let rtt = (t3 - t0) - (t2 - t1);
+        let offset = ((t1 - t0) + (t2 - t3)) / 2.;
+        if rtt < 0. { return }  // clocks moved under us, this one is garbage
+        if self.samples.len() == SYNC_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((rtt, offset));
+    }
+
+    /// the offset from the sample with the lowest round trip, since that one had the least queueing in it
+    pub fn offset(&self) -> Option<f32> {
+        let mut best: Option<(f32, f32)> = None;
+        for (rtt, offset) in &self.samples {
+            if best.is_none() || *rtt < best.unwrap().0 {
+                best = Some((*rtt, *offset));
+            }
+        }
+        return best.map(|(_, offset)| offset);
+    }
+
+    /// whether we've heard from the host and are close enough to its clock to not need a jump
+    pub fn synced(&self) -> bool {
+        return self.offset().is_some_and(|offset| offset.abs() <= SNAP_TICKS);
+    }
+
+    /// after we jump our own clock by ticks, the stored offsets need to agree with it
+    pub fn shift(&mut self, ticks: f32) {
+        for (_, offset) in self.samples.iter_mut() {
+            *offset -= ticks;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// the current time in fractional ticks
+pub fn now(tick: &TickNum, fixed_time: &FixedTime) -> f32 {
+    return tick.0 as f32 + fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32();
+}
+
+/// Runs on the client. Small offsets are corrected by running FixedUpdate slightly fast or slow,
+/// big ones (e.g. right after connecting) are jumped.
+pub fn adjust_clock(
+    mut sync: ResMut<ClockSync>,
+    mut tick: ResMut<TickNum>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    let offset = sync.offset();
+    if offset.is_none() { return }
+    let offset = offset.unwrap();
+    if !sync.synced() {
+        let jump = offset.round();
+        let new_tick = (tick.0 as i32 + jump as i32).max(0) as u16;
+        println!("re-syncing: changing tick from {} to {}", tick.0, new_tick);
+        tick.0 = new_tick;
+        sync.shift(jump);
+        return;
+    }
+    // host ahead -> positive offset -> shorter ticks until we catch up
+    let dilation = (offset * DILATION_GAIN).clamp(-MAX_DILATION, MAX_DILATION);
+    fixed_time.period = Duration::from_secs_f32(TICKLEN_S * (1. - dilation));
+}
+
+pub fn reset_clock(
+    mut sync: ResMut<ClockSync>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    sync.clear();
+    fixed_time.period = Duration::from_secs_f32(TICKLEN_S);
+}
@@ -0,0 +1,665 @@
+//! Runs a host and several clients in one process over loopback, with no windows.
+//! Time is stepped by hand instead of following the wall clock, so every app runs
+//! exactly the same FixedUpdate ticks and the tests don't depend on how fast the machine is.
+use std::time::Duration;
+use bevy::input::{ButtonState, InputPlugin};
+use bevy::input::keyboard::KeyboardInput;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::ExitCondition;
+use crate::AppState;
+use crate::game::{GamePlugin, PlayerId, ROUND_TIME};
+use crate::game::map::{Biome, WorldMap, MAPSIZE, TILESIZE};
+use crate::game::buffers::{EventBuffer, HpBuffer, PosBuffer};
+use crate::game::class::{ChosenClass, Classes, PlayerClass, DEFAULT_CLASS};
+use crate::game::components::{Camp, DamageLedger, Enemy, Health, ItemChest, Player, PowerUp, PowerUpType, Stats, StoredPowerUps};
+use crate::game::events::{Announcement, Attacker};
+use crate::game::item::{Hotbar, ItemType, HOTBAR_SLOTS};
+use crate::game::movement::KeyBinds;
+use crate::game::player::{Bow, Dash, LocalPlayer, PlayerShield, LocalPlayerSpawnEvent, SpawnEvent, SpawnRules, INTERACT_BITFLAG, SPAWN_BITFLAG, SPAWN_CLEARANCE};
+use crate::game::status::{StatusEffects, StatusType};
+use crate::game::team::{Team, TeamRules};
+use crate::game::hill::{Hill, HillRules, HILL_CONTESTED};
+use crate::game::weapon::{self, Weapon, Weapons};
+use crate::menus::{MainMenuPlugin, NetworkAddresses};
+use crate::net::{self, NetPlugin, TICKLEN_S, TickNum};
+use crate::net::clock::ClockSync;
+use crate::net::host::Connections;
+
+const FRAMES_PER_TICK: u32 = 2;
+const CONNECT_TICKS: u32 = 50;  // how long everyone gets to connect before the test gives up
+
+/// everything the game needs from bevy, minus the window, rendering and audio
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            // the window is never opened, it's only here because some systems look for it
+            WindowPlugin {
+                primary_window: Some(Window::default()),
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            },
+            InputPlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+        ))
+        .add_asset::<Image>()
+        .add_asset::<TextureAtlas>()
+        .add_state::<AppState>()
+        .add_plugins((
+            GamePlugin,
+            NetPlugin,
+            MainMenuPlugin,
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(TICKLEN_S / FRAMES_PER_TICK as f32)));
+    app.update();  // startup
+    return app;
+}
+
+pub struct Harness {
+    pub host: App,
+    pub clients: Vec<App>,
+}
+
+impl Harness {
+    /// starts a host, connects num_clients clients to it, and waits until everyone's in the game
+    pub fn new(num_clients: usize) -> Harness {
+        return Harness::with_classes(&vec![DEFAULT_CLASS; num_clients]);
+    }
+
+    /// the same, with a client for each class in classes playing as that class
+    pub fn with_classes(classes: &[u8]) -> Harness {
+        let num_clients = classes.len();
+        let mut host = headless_app();
+        host.world.resource_mut::<net::IsHost>().0 = true;
+        host.world.resource_mut::<PlayerId>().0 = 0;  // what the host button does
+        host.world.resource_mut::<NetworkAddresses>().host_port = "0".to_string();  // any free port
+        host.world.resource_mut::<NextState<AppState>>().set(AppState::Game);
+        host.update();
+        let port = host.world.resource::<net::Socket>().0.as_ref().expect("host didn't bind").local_addr().unwrap().port();
+        let mut harness = Harness { host, clients: Vec::new() };
+        for &class in classes {
+            let mut client = headless_app();
+            client.world.resource_mut::<net::IsHost>().0 = false;
+            client.world.resource_mut::<ChosenClass>().0 = class;
+            let mut addresses = client.world.resource_mut::<NetworkAddresses>();
+            addresses.ip = "127.0.0.1".to_string();
+            addresses.host_port = port.to_string();
+            addresses.client_port = "0".to_string();
+            client.world.resource_mut::<NextState<AppState>>().set(AppState::Connecting);
+            harness.clients.push(client);
+        }
+        let connected = harness.run_until(CONNECT_TICKS, |h| {
+            h.host.world.resource::<Connections>().0.iter().flatten().count() == num_clients
+                && h.clients.iter().all(|client| *client.world.resource::<State<AppState>>().get() == AppState::Game)
+        });
+        assert!(connected, "clients didn't all connect");
+        // a client's clock jumps when it first hears from the host, and anything a test sets up on a tick that gets skipped is lost
+        let synced = harness.run_until(CONNECT_TICKS, |h| h.clients.iter().all(|client| client.world.resource::<ClockSync>().synced()));
+        assert!(synced, "clients didn't all sync their clocks");
+        // tests put players wherever they need them, enemies or not
+        harness.host.world.resource_mut::<SpawnRules>().clearance = 0.;
+        return harness;
+    }
+
+    pub fn apps(&mut self) -> impl Iterator<Item = &mut App> {
+        return std::iter::once(&mut self.host).chain(self.clients.iter_mut());
+    }
+
+    /// runs one tick on every app, a frame at a time so packets go back and forth in between
+    pub fn tick(&mut self) {
+        for _ in 0..FRAMES_PER_TICK {
+            for app in self.apps() {
+                app.update();
+            }
+        }
+    }
+
+    /// ticks until done says so, returning false if it took longer than max_ticks
+    pub fn run_until(&mut self, max_ticks: u32, done: impl Fn(&mut Harness) -> bool) -> bool {
+        for _ in 0..max_ticks {
+            if done(self) { return true }
+            self.tick();
+        }
+        return done(self);
+    }
+}
+
+/// does what clicking on the minimap does, putting the local player at pos
+pub fn spawn_local_player(app: &mut App, pos: Vec2) {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut players = app.world.query_filtered::<(&mut Transform, &mut EventBuffer), With<LocalPlayer>>();
+    let (mut tf, mut eb) = players.single_mut(&mut app.world);
+    tf.translation.x = pos.x;
+    tf.translation.y = pos.y;
+    let events = eb.0.get(tick).unwrap_or(0);
+    eb.0.set(tick, Some(events | SPAWN_BITFLAG));
+    if app.world.resource::<net::IsHost>().0 {
+        app.world.resource_mut::<Events<LocalPlayerSpawnEvent>>().send(LocalPlayerSpawnEvent);
+        app.world.resource_mut::<Events<SpawnEvent>>().send(SpawnEvent { id: 0, pos });
+    }
+}
+
+/// holds down (or lets go of) the attack button. With no cursor the sword points right
+pub fn hold_attack(app: &mut App, held: bool) {
+    let mut mouse = app.world.resource_mut::<Input<MouseButton>>();
+    if held {
+        mouse.press(MouseButton::Left);
+    }
+    else {
+        mouse.release(MouseButton::Left);
+    }
+}
+
+/// holds up (or lowers) the shield
+pub fn hold_shield(app: &mut App, held: bool) {
+    let mut mouse = app.world.resource_mut::<Input<MouseButton>>();
+    if held {
+        mouse.press(MouseButton::Right);
+    }
+    else {
+        mouse.release(MouseButton::Right);
+    }
+}
+
+/// what pressing the weapon switch key does, without needing a just_pressed
+pub fn equip_bow(app: &mut App) {
+    let mut players = app.world.query_filtered::<&mut Bow, With<LocalPlayer>>();
+    players.single_mut(&mut app.world).equipped = true;
+}
+
+/// taps the dash key, which has to come in as a real key press for just_pressed to see it
+pub fn dash(app: &mut App) {
+    let key = app.world.resource::<KeyBinds>().dash;
+    let mut keys = app.world.resource_mut::<Events<KeyboardInput>>();
+    keys.send(KeyboardInput { scan_code: 0, key_code: Some(key), state: ButtonState::Pressed, window: Entity::PLACEHOLDER });
+    keys.send(KeyboardInput { scan_code: 0, key_code: Some(key), state: ButtonState::Released, window: Entity::PLACEHOLDER });
+}
+
+/// what pressing interact does, for one tick
+pub fn interact(app: &mut App) {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut players = app.world.query_filtered::<&mut EventBuffer, With<LocalPlayer>>();
+    let mut eb = players.single_mut(&mut app.world);
+    let events = eb.0.get(tick).unwrap_or(0);
+    eb.0.set(tick, Some(events | INTERACT_BITFLAG));
+}
+
+/// swaps out whatever weapon the map rolled for chest id
+pub fn put_weapon_in_chest(app: &mut App, id: u16, weapon: u8) {
+    let mut chests = app.world.query::<&mut ItemChest>();
+    for mut ic in chests.iter_mut(&mut app.world) {
+        if ic.id == id {
+            ic.weapon = Some(weapon);
+        }
+    }
+}
+
+/// swaps out whatever item the map rolled for chest id, and takes out any weapon so interact goes for the item
+pub fn put_item_in_chest(app: &mut App, id: u16, item: ItemType) {
+    let mut chests = app.world.query::<&mut ItemChest>();
+    for mut ic in chests.iter_mut(&mut app.world) {
+        if ic.id == id {
+            ic.item = Some(item);
+            ic.weapon = None;
+        }
+    }
+}
+
+pub fn shield_up(app: &mut App, id: u8) -> bool {
+    let mut players = app.world.query::<(&Player, &PlayerShield)>();
+    return players.iter(&app.world).any(|(pl, shield)| pl.0 == id && shield.active);
+}
+
+pub fn player_weapon(app: &mut App, id: u8) -> Option<u8> {
+    let mut players = app.world.query::<(&Player, &Weapon)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, weapon)| weapon.0);
+}
+
+pub fn player_class(app: &mut App, id: u8) -> Option<u8> {
+    let mut players = app.world.query::<(&Player, &PlayerClass)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, class)| class.0);
+}
+
+pub fn player_hp(app: &mut App, id: u8) -> Option<u8> {
+    let mut players = app.world.query::<(&Player, &Health)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, hp)| hp.current);
+}
+
+pub fn player_team(app: &mut App, id: u8) -> Option<u8> {
+    let mut players = app.world.query::<(&Player, &Team)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, team)| team.0);
+}
+
+pub fn hill(app: &App) -> Hill {
+    return *app.world.resource::<Hill>();
+}
+
+pub fn player_hotbar(app: &mut App, id: u8) -> Option<Hotbar> {
+    let mut players = app.world.query::<(&Player, &Hotbar)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, hotbar)| hotbar.clone());
+}
+
+/// how far the starting weapon reaches
+pub fn sword_reach(app: &App) -> f32 {
+    return app.world.resource::<Weapons>().get(weapon::SWORD).reach;
+}
+
+pub fn local_player_alive(app: &mut App) -> bool {
+    let mut players = app.world.query_filtered::<&Health, With<LocalPlayer>>();
+    return players.get_single(&app.world).is_ok_and(|hp| hp.current > 0);
+}
+
+pub fn player_ids(app: &mut App) -> Vec<u8> {
+    let mut players = app.world.query::<&Player>();
+    let mut ids: Vec<u8> = players.iter(&app.world).map(|pl| pl.0).collect();
+    ids.sort();
+    return ids;
+}
+
+pub fn player_pos(app: &mut App, id: u8) -> Option<Vec2> {
+    let mut players = app.world.query::<(&Player, &Transform)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, tf)| tf.translation.truncate());
+}
+
+pub fn enemy_hp(app: &mut App, id: u16) -> Option<u8> {
+    let mut enemies = app.world.query::<(&Enemy, &Health)>();
+    return enemies.iter(&app.world).find(|(en, _)| en.0 == id).map(|(_, hp)| hp.current);
+}
+
+pub fn enemy_pos(app: &mut App, id: u16) -> Option<Vec2> {
+    let mut enemies = app.world.query::<(&Enemy, &Transform)>();
+    return enemies.iter(&app.world).find(|(en, _)| en.0 == id).map(|(_, tf)| tf.translation.truncate());
+}
+
+/// the kinds of status effect on a player
+pub fn player_effects(app: &mut App, id: u8) -> Vec<StatusType> {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut players = app.world.query::<(&Player, &StatusEffects)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, effects)| effects.active(tick)).unwrap_or_default();
+}
+
+pub fn chest_hp(app: &mut App, id: u16) -> Option<u8> {
+    let mut chests = app.world.query::<(&ItemChest, &Health)>();
+    return chests.iter(&app.world).find(|(ic, _)| ic.id == id).map(|(_, hp)| hp.current);
+}
+
+/// what the host does when someone's health runs out, without anyone having to land the hits
+pub fn kill_player(app: &mut App, id: u8) {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut players = app.world.query::<(&Player, &mut HpBuffer)>();
+    for (pl, mut hb) in players.iter_mut(&mut app.world) {
+        if pl.0 == id {
+            hb.0.set(tick, Some(0));
+        }
+    }
+}
+
+/// what a hit does to someone's damage ledger on the host, without having to line one up
+pub fn record_hit(app: &mut App, id: u8, attacker: Attacker, damage: u8) {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut players = app.world.query::<(&Player, &mut DamageLedger)>();
+    for (pl, mut ledger) in players.iter_mut(&mut app.world) {
+        if pl.0 == id {
+            ledger.record(attacker, damage, tick);
+        }
+    }
+}
+
+pub fn player_stats(app: &mut App, id: u8) -> Option<Stats> {
+    let mut players = app.world.query::<(&Player, &Stats)>();
+    return players.iter(&app.world).find(|(pl, _)| pl.0 == id).map(|(_, stats)| stats.clone());
+}
+
+pub fn give_powerups(app: &mut App, id: u8, ptype: PowerUpType, stacks: u8) {
+    let mut players = app.world.query::<(&Player, &mut StoredPowerUps)>();
+    for (pl, mut spu) in players.iter_mut(&mut app.world) {
+        if pl.0 == id {
+            spu.power_ups[ptype as usize] = stacks;
+        }
+    }
+}
+
+pub fn powerups_near(app: &mut App, pos: Vec2, ptype: PowerUpType) -> usize {
+    let mut powerups = app.world.query::<(&PowerUp, &Transform)>();
+    return powerups.iter(&app.world).filter(|(pu, tf)| pu.0 == ptype && tf.translation.truncate().distance(pos) < 64.).count();
+}
+
+/// the middle of the first tile with no walls anywhere near it
+pub fn open_ground(app: &App) -> Vec2 {
+    let map = &app.world.resource::<WorldMap>().biome_map;
+    let clear = 4;  // tiles
+    for row in clear..MAPSIZE - clear {
+        for col in clear..MAPSIZE - clear {
+            let walled = (row - clear..=row + clear).any(|r| (col - clear..=col + clear).any(|c| map[r][c] == Biome::Wall));
+            if walled { continue }
+            let half = (TILESIZE * MAPSIZE / 2) as f32;
+            return Vec2::new((col * TILESIZE) as f32 + TILESIZE as f32 / 2. - half, half - (row * TILESIZE) as f32 - TILESIZE as f32 / 2.);
+        }
+    }
+    panic!("there's nowhere without walls");
+}
+
+/// puts the local player gap pixels to the left of the first chest, which is out of the way of the camps.
+/// Returns the chest's id and where the player went
+pub fn spawn_next_to_chest(app: &mut App, gap: f32) -> (u16, Vec2) {
+    let id = first_chest(app);
+    let mut chests = app.world.query::<(&ItemChest, &Transform)>();
+    let chest = chests.iter(&app.world).find(|(ic, _)| ic.id == id).unwrap().1.translation.truncate();
+    let pos = chest - Vec2::new(gap, 0.);
+    spawn_local_player(app, pos);
+    return (id, pos);
+}
+
+/// the id, position this tick and hp of the first enemy the host has
+pub fn first_enemy(app: &mut App) -> (u16, Vec2, u8) {
+    let tick = app.world.resource::<TickNum>().0;
+    let mut enemies = app.world.query::<(&Enemy, &PosBuffer, &Health)>();
+    return enemies.iter(&app.world)
+        .find_map(|(en, pb, hp)| pb.0.get(tick).map(|pos| (en.0, pos, hp.current)))
+        .expect("host has no enemies");
+}
+
+/// ticks until a client's local player is alive, failing the test if it never is
+pub fn wait_for_spawn(h: &mut Harness, client: usize) {
+    assert!(h.run_until(20, |h| local_player_alive(&mut h.clients[client])), "client {} never spawned", client);
+}
+
+/// whatever the announcement banner says right now
+pub fn announcement(app: &mut App) -> Option<String> {
+    let mut announcements = app.world.query_filtered::<&Text, With<Announcement>>();
+    return announcements.iter(&app.world).next().map(|text| text.sections[0].value.clone());
+}
+
+/// the id of the chest spawn_next_to_chest goes to, so a test can fill it first
+pub fn first_chest(app: &mut App) -> u16 {
+    let mut chests = app.world.query::<&ItemChest>();
+    return chests.iter(&app.world).next().expect("no chests").id;
+}
+
+/// spawns a client's player by the first chest and hits it until it's open everywhere, then lets go of attack.
+/// Returns the chest's id
+pub fn open_chest_next_to(h: &mut Harness, client: usize) -> u16 {
+    let reach = sword_reach(&h.clients[client]);
+    let (id, _) = spawn_next_to_chest(&mut h.clients[client], reach / 3.);
+    wait_for_spawn(h, client);
+    hold_attack(&mut h.clients[client], true);
+    assert!(h.run_until(30, |h| h.apps().all(|app| chest_hp(app, id) == Some(0))), "chest {} didn't open everywhere", id);
+    hold_attack(&mut h.clients[client], false);
+    return id;
+}
+
+fn state(app: &App) -> AppState {
+    return *app.world.resource::<State<AppState>>().get();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everyone_sees_every_player() {
+        let mut h = Harness::new(2);
+        let all_there = h.run_until(20, |h| h.apps().all(|app| player_ids(app) == vec![0, 1, 2]));
+        assert!(all_there, "not every machine has players 0, 1 and 2");
+    }
+
+    #[test]
+    fn client_attack_damages_enemy() {
+        let mut h = Harness::new(1);
+        let (id, pos, start_hp) = first_enemy(&mut h.host);
+        // just to its left, facing it
+        let reach = sword_reach(&h.clients[0]);
+        spawn_local_player(&mut h.clients[0], pos - Vec2::new(reach / 3., 0.));
+        wait_for_spawn(&mut h, 0);
+        hold_attack(&mut h.clients[0], true);
+        let hit = h.run_until(30, |h| h.apps().all(|app| enemy_hp(app, id).is_some_and(|hp| hp < start_hp)));
+        assert!(hit, "enemy {} didn't take damage everywhere", id);
+    }
+
+    #[test]
+    fn client_attack_knocks_enemy_back() {
+        let mut h = Harness::new(1);
+        let (id, pos, _) = first_enemy(&mut h.host);
+        let reach = sword_reach(&h.clients[0]);
+        spawn_local_player(&mut h.clients[0], pos - Vec2::new(reach / 3., 0.));
+        wait_for_spawn(&mut h, 0);
+        hold_attack(&mut h.clients[0], true);
+        // swung at from the left, so it should end up further right everywhere
+        let pushed = h.run_until(40, |h| h.apps().all(|app| enemy_pos(app, id).is_some_and(|now| now.x > pos.x + 1.)));
+        assert!(pushed, "enemy {} wasn't knocked back everywhere", id);
+    }
+
+    #[test]
+    fn client_arrow_damages_enemy() {
+        let mut h = Harness::new(1);
+        let (id, pos, start_hp) = first_enemy(&mut h.host);
+        // out of sword reach, so only an arrow can do it
+        let reach = sword_reach(&h.clients[0]);
+        spawn_local_player(&mut h.clients[0], pos - Vec2::new(reach * 1.5, 0.));
+        wait_for_spawn(&mut h, 0);
+        equip_bow(&mut h.clients[0]);
+        hold_attack(&mut h.clients[0], true);
+        let hit = h.run_until(30, |h| h.apps().all(|app| enemy_hp(app, id).is_some_and(|hp| hp < start_hp)));
+        assert!(hit, "enemy {} didn't take an arrow everywhere", id);
+    }
+
+    #[test]
+    fn rejected_spawn_is_announced_to_the_client() {
+        let mut h = Harness::new(1);
+        h.host.world.resource_mut::<SpawnRules>().clearance = SPAWN_CLEARANCE;
+        // right on top of an enemy, which the client's own check would have stopped
+        let (_, pos, _) = first_enemy(&mut h.host);
+        spawn_local_player(&mut h.clients[0], pos);
+        let told = h.run_until(20, |h| announcement(&mut h.clients[0]).is_some_and(|text| text.starts_with("Too close")));
+        assert!(told, "client wasn't told its spawn was rejected");
+        assert!(!local_player_alive(&mut h.clients[0]), "client spawned on top of an enemy");
+    }
+
+    #[test]
+    fn rejected_dash_puts_the_client_back() {
+        let mut h = Harness::new(1);
+        let pos = open_ground(&h.host);
+        spawn_local_player(&mut h.clients[0], pos);
+        assert!(h.run_until(20, |h| player_pos(&mut h.host, 1).is_some_and(|now| now.distance(pos) < 1.)), "client never spawned on the host");
+        // as far as the host knows they only just dashed
+        let tick = h.host.world.resource::<TickNum>().0;
+        let mut dashes = h.host.world.query::<(&Player, &mut Dash)>();
+        for (pl, mut dash) in dashes.iter_mut(&mut h.host.world) {
+            if pl.0 != 1 { continue }
+            dash.last = Some(tick.wrapping_sub(100));
+            dash.cooldown = 1000;
+        }
+        dash(&mut h.clients[0]);
+        h.tick();
+        assert!(player_pos(&mut h.clients[0], 1).is_some_and(|now| now.distance(pos) > 60.), "client didn't dash");
+        for _ in 0..20 {
+            h.tick();
+            assert!(player_pos(&mut h.host, 1).is_some_and(|now| now.distance(pos) < 1.), "host let the dash through");
+        }
+        assert!(player_pos(&mut h.clients[0], 1).is_some_and(|now| now.distance(pos) < 1.), "client never went back");
+    }
+
+    #[test]
+    fn shield_stays_up_when_packets_go_missing() {
+        let mut h = Harness::new(1);
+        spawn_local_player(&mut h.clients[0], open_ground(&h.host));
+        wait_for_spawn(&mut h, 0);
+        hold_shield(&mut h.clients[0], true);
+        assert!(h.run_until(20, |h| shield_up(&mut h.host, 1)), "host never saw the shield go up");
+        // the client goes quiet, which looks the same as its packets getting lost
+        for _ in 0..10 * FRAMES_PER_TICK {
+            h.host.update();
+        }
+        assert!(shield_up(&mut h.host, 1), "host dropped the shield without being told to");
+        hold_shield(&mut h.clients[0], false);
+        assert!(h.run_until(20, |h| !shield_up(&mut h.host, 1)), "host never saw the shield go down");
+    }
+
+    #[test]
+    fn chest_opens_for_everyone() {
+        let mut h = Harness::new(2);
+        open_chest_next_to(&mut h, 0);
+    }
+
+    #[test]
+    fn chest_buffs_the_opener_everywhere() {
+        let mut h = Harness::new(1);
+        open_chest_next_to(&mut h, 0);
+        let player = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        let buffed = h.run_until(10, |h| h.apps().all(|app| {
+            let effects = player_effects(app, player);
+            effects.contains(&StatusType::Haste) || effects.contains(&StatusType::Regeneration)
+        }));
+        assert!(buffed, "player {} wasn't buffed everywhere", player);
+    }
+
+    #[test]
+    fn weapon_from_chest_changes_hands_everywhere() {
+        let mut h = Harness::new(1);
+        let id = first_chest(&mut h.clients[0]);
+        // whatever the map rolled, this chest has the last weapon in it
+        let w = (h.host.world.resource::<Weapons>().0.len() - 1) as u8;
+        assert!(w != weapon::SWORD, "only the sword loaded");
+        for app in h.apps() {
+            put_weapon_in_chest(app, id, w);
+        }
+        open_chest_next_to(&mut h, 0);
+        let player = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        interact(&mut h.clients[0]);
+        let taken = h.run_until(30, |h| h.apps().all(|app| player_weapon(app, player) == Some(w)));
+        assert!(taken, "player {} didn't end up with weapon {} everywhere", player, w);
+    }
+
+    #[test]
+    fn item_from_chest_lands_in_the_hotbar_everywhere() {
+        let mut h = Harness::new(1);
+        let id = first_chest(&mut h.clients[0]);
+        // whatever the map rolled, this chest has a bomb in it
+        let it = ItemType::Bomb;
+        for app in h.apps() {
+            put_item_in_chest(app, id, it);
+        }
+        open_chest_next_to(&mut h, 0);
+        let player = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        interact(&mut h.clients[0]);
+        // the chest's powerups can put meat in the hotbar first
+        let held = |app: &mut App| player_hotbar(app, player).is_some_and(|hotbar| (0..HOTBAR_SLOTS).any(|slot| hotbar.peek(slot) == Some(it)));
+        assert!(h.run_until(30, |h| h.apps().all(|app| held(app))), "player {} never had a {} in their hotbar everywhere", player, it.name());
+    }
+
+    #[test]
+    fn dead_player_drops_loot_for_everyone() {
+        let mut h = Harness::new(1);
+        let player = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        give_powerups(&mut h.host, player, PowerUpType::CritChance, 4);
+        let (_, died_at) = spawn_next_to_chest(&mut h.clients[0], 64.);
+        wait_for_spawn(&mut h, 0);
+        kill_player(&mut h.host, player);
+        let dropped = h.run_until(20, |h| h.apps().all(|app| powerups_near(app, died_at, PowerUpType::CritChance) == 2));
+        assert!(dropped, "player {}'s loot didn't show up everywhere", player);
+        let mut players = h.host.world.query::<(&Player, &StoredPowerUps)>();
+        let (_, spu) = players.iter(&h.host.world).find(|(pl, _)| pl.0 == player).unwrap();
+        assert_eq!(spu.power_ups[PowerUpType::CritChance as usize], 2);
+    }
+
+    #[test]
+    fn kill_and_assist_credit_reach_everyone() {
+        let mut h = Harness::new(2);
+        let helper = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        let victim = h.clients[1].world.resource::<crate::game::PlayerId>().0;
+        let killer = 0;  // the host's own player
+        spawn_next_to_chest(&mut h.clients[1], 64.);
+        wait_for_spawn(&mut h, 1);
+        record_hit(&mut h.host, victim, Attacker::Player(helper), 30);
+        record_hit(&mut h.host, victim, Attacker::Player(killer), 10);
+        kill_player(&mut h.host, victim);
+        let credited = h.run_until(20, |h| h.apps().all(|app| {
+            player_stats(app, killer).is_some_and(|stats| stats.players_killed == 1 && stats.assists == 0)
+                && player_stats(app, helper).is_some_and(|stats| stats.players_killed == 0 && stats.assists == 1)
+                && player_stats(app, victim).is_some_and(|stats| stats.deaths == 1)
+        }));
+        assert!(credited, "player {}'s kill and player {}'s assist on player {} didn't show up everywhere", killer, helper, victim);
+    }
+
+    #[test]
+    fn picked_classes_reach_everyone() {
+        let mut h = Harness::with_classes(&[2, 3]);
+        let rogue = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        let ranger = h.clients[1].world.resource::<crate::game::PlayerId>().0;
+        let known = h.run_until(20, |h| h.apps().all(|app| {
+            player_class(app, 0) == Some(DEFAULT_CLASS) && player_class(app, rogue) == Some(2) && player_class(app, ranger) == Some(3)
+        }));
+        assert!(known, "players {} and {} didn't show up everywhere as the classes they picked", rogue, ranger);
+        // they come in with their class's health
+        let hp = h.host.world.resource::<Classes>().get(2).hp;
+        spawn_next_to_chest(&mut h.clients[0], 64.);
+        let full = h.run_until(20, |h| h.apps().all(|app| player_hp(app, rogue) == Some(hp)));
+        assert!(full, "player {} didn't spawn with {} hp everywhere", rogue, hp);
+    }
+
+    #[test]
+    fn teammates_cant_hurt_each_other() {
+        let mut h = Harness::new(2);
+        h.host.world.resource_mut::<TeamRules>().teams = 2;
+        h.host.world.resource_mut::<SpawnRules>().protection = 0;
+        // joiners go on the smallest team in id order, so 0 and 2 end up together
+        let attacker = h.clients[1].world.resource::<crate::game::PlayerId>().0;
+        let enemy = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        let ally = 0;
+        let sorted = h.run_until(20, |h| h.apps().all(|app| {
+            player_team(app, ally) == Some(0) && player_team(app, enemy) == Some(1) && player_team(app, attacker) == Some(0)
+        }));
+        assert!(sorted, "teams didn't show up everywhere");
+        // nothing else gets to hurt anyone
+        let mut enemies = h.host.world.query_filtered::<Entity, With<Enemy>>();
+        let enemies: Vec<Entity> = enemies.iter(&h.host.world).collect();
+        for e in enemies {
+            h.host.world.entity_mut(e).despawn_recursive();
+        }
+        // one on each side of the attacker's swing, away from any walls
+        let pos = open_ground(&h.host);
+        let reach = sword_reach(&h.host);
+        spawn_local_player(&mut h.host, pos + Vec2::new(reach / 3., 10.));
+        spawn_local_player(&mut h.clients[0], pos + Vec2::new(reach / 3., -10.));
+        spawn_local_player(&mut h.clients[1], pos);
+        assert!(h.run_until(20, |h| h.apps().all(|app| local_player_alive(app))), "players never spawned");
+        let full = h.host.world.resource::<Classes>().get(DEFAULT_CLASS).hp;
+        hold_attack(&mut h.clients[1], true);
+        let hit = h.run_until(30, |h| h.apps().all(|app| player_hp(app, enemy).is_some_and(|hp| hp < full)));
+        assert!(hit, "player {} didn't hurt player {} on the other team", attacker, enemy);
+        for app in h.apps() {
+            assert_eq!(player_hp(app, ally), Some(full), "player {} hurt their teammate {}", attacker, ally);
+        }
+    }
+
+    #[test]
+    fn hill_pays_whoever_has_it_alone() {
+        let mut h = Harness::new(1);
+        *h.host.world.resource_mut::<HillRules>() = HillRules::with_period(60.);
+        let king = h.clients[0].world.resource::<crate::game::PlayerId>().0;
+        assert!(h.run_until(20, |h| h.apps().all(|app| hill(app).camp == 0)), "the hill didn't start at the first camp everywhere");
+        let mut camps = h.host.world.query::<(&Camp, &Transform)>();
+        let pos = camps.iter(&h.host.world).find(|(camp, _)| camp.0 == 0).map(|(_, tf)| tf.translation.truncate()).expect("no camps");
+        spawn_local_player(&mut h.clients[0], pos);
+        let held = h.run_until(40, |h| h.apps().all(|app| {
+            hill(app).holder == king && player_stats(app, king).is_some_and(|stats| stats.score > 0)
+        }));
+        assert!(held, "player {} didn't hold the hill and score for it everywhere", king);
+        // then the host's player walks on too
+        spawn_local_player(&mut h.host, pos + Vec2::new(32., 0.));
+        let contested = h.run_until(20, |h| h.apps().all(|app| hill(app).holder == HILL_CONTESTED));
+        assert!(contested, "the hill didn't show up as contested everywhere");
+    }
+
+    #[test]
+    fn round_ends_everywhere() {
+        let mut h = Harness::new(2);
+        // skip to just before the end, the clients have to follow the host's clock there
+        h.host.world.resource_mut::<TickNum>().0 = (ROUND_TIME / TICKLEN_S) as u16 - 10;
+        let over = h.run_until(60, |h| h.apps().all(|app| state(app) == AppState::GameOver));
+        assert!(over, "round didn't end on every machine");
+    }
+}
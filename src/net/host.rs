@@ -1,35 +1,213 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::*;
 use std::str::FromStr;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
-use crate::game::{Chests, player};
+use crate::game::{MapConfig, player};
+use crate::game::item::Hotbar;
+use crate::game::player::{PlayerShield, Respawn, SpawnRules};
 use crate::{menus, net};
 use crate::game::buffers::{DirBuffer, EventBuffer, HpBuffer, PosBuffer};
+use crate::game::class::PlayerClass;
+use crate::game::team::{self, Team, TeamRules};
+use crate::game::hill::{Hill, HillRules};
 use crate::components::*;
-use crate::game::map::MapSeed;
+use crate::game::events::{GameEvent, GameplayEvent};
+use crate::game::map::{MapSeed, NumCamps};
+use crate::game::powerup::{NO_GRABBER, PowerupGrabEvent};
+use crate::game::projectile::Projectile;
+use crate::game::status::StatusEffects;
+use crate::game::weapon::Weapon;
 use crate::net::packets::*;
-use crate::net::{MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
+use crate::net::{clock, replay, MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
+use crate::net::bandwidth::{Bandwidth, LinkStats};
 
 pub const RENDER_DISTANCE: f32 = 640.;
+const THROTTLED_DISTANCE: f32 = RENDER_DISTANCE / 2.;  // enemies and projectiles further than this are the first thing dropped when a connection is over budget
+pub const MAX_POWERUP_EVENTS: usize = 32;  // per HostTick, for spawns and despawns each
+pub const MAX_GAME_EVENTS: usize = 16;  // per HostTick
+pub const MAX_CHEST_UPDATES: usize = 32;  // per HostTick
+const EVENT_LOG_LEN: usize = 256;
+const TIMEOUT: u16 = 100;  // ticks without a ClientTick before we give up on someone
 
 #[derive(Copy, Clone)]
 pub struct Connection {
     pub addr: SocketAddr,
     pub player_id: u8,
-    pub rmt_num: u16,  // if the ack is older than TIMEOUT ticks ago, disconnect the player
-    pub ack: u32
+    pub rmt_num: u16,
+    pub ack: u32,
+    pub host_ack: u16,  // newest HostTick this client says it got
+    pub host_ack_bits: u32,  // which of the 32 HostTicks before host_ack it got, same layout as Ack
+    pub event_ack: u16,  // newest gameplay event this client says it handled
+    pub client_time: f32,  // clock reading from the last ClientTick to arrive, negative until the first one
+    pub recv_time: f32,  // our clock when that reading arrived
+    pub last_heard: u16,  // tick we last got anything from them, if it's older than TIMEOUT ticks ago they're gone
 }
 
 #[derive(Resource)]
 pub struct Connections(pub [Option<Connection>; player::MAX_PLAYERS-1]); // -1 because host not included
 
+/// how many players the host lets in, counting itself
+#[derive(Resource)]
+pub struct PlayerCap(pub usize);
+
+/// What one client has been told about the powerups.
+/// Spawns and despawns go out every tick from when they happen until the client acks a HostTick at least that new,
+/// so an ack of anything since then means it arrived
+#[derive(Default)]
+pub struct PowerupView {
+    spawns: HashMap<u16, u16>,  // id -> tick the spawn first went out
+    despawns: HashMap<u16, (u8, u16)>,  // id -> (who grabbed it, tick the despawn first went out)
+}
+
+impl PowerupView {
+    /// center limits new spawns to things near the player, but anything they've already heard about gets its despawn
+    pub fn update(
+        &mut self,
+        tick: u16,
+        host_ack: u16,
+        live: &[PowerupSpawn],
+        grabs: &HashMap<u16, u8>,
+        center: Option<Vec2>,
+    ) -> (Vec<PowerupSpawn>, Vec<PowerupDespawn>) {
+        let mut spawns: Vec<PowerupSpawn> = Vec::new();
+        let mut pending = self.spawns.values().filter(|sent| net::newer(**sent, host_ack)).count();
+        let mut alive: HashSet<u16> = HashSet::new();
+        for powerup in live {
+            alive.insert(powerup.id);
+            if let Some(sent) = self.spawns.get(&powerup.id) {
+                if net::newer(*sent, host_ack) {
+                    spawns.push(powerup.clone());
+                }
+                continue;
+            }
+            if center.is_some_and(|center| powerup.pos.distance(center) >= RENDER_DISTANCE) { continue }
+            if pending >= MAX_POWERUP_EVENTS { continue }  // the rest can wait a tick
+            self.spawns.insert(powerup.id, tick);
+            pending += 1;
+            spawns.push(powerup.clone());
+        }
+        self.despawns.retain(|_, (_, sent)| net::newer(*sent, host_ack));
+        let gone: Vec<u16> = self.spawns.keys().filter(|id| !alive.contains(id)).copied().collect();
+        for id in gone {
+            if self.despawns.len() >= MAX_POWERUP_EVENTS { break }
+            self.spawns.remove(&id);
+            self.despawns.insert(id, (*grabs.get(&id).unwrap_or(&NO_GRABBER), tick));
+        }
+        let despawns = self.despawns.iter()
+            .map(|(id, (grabbed_by, _))| PowerupDespawn { id: *id, grabbed_by: *grabbed_by })
+            .collect();
+        return (spawns, despawns);
+    }
+}
+
+/// What one client has been told about the chests. Like powerups, a chest goes out every tick from when its hp
+/// changes until the client acks a HostTick at least that new
+#[derive(Default)]
+pub struct ChestView {
+    sent: HashMap<u16, (u8, u16)>,  // id -> (hp we told them, tick that first went out)
+}
+
+impl ChestView {
+    pub fn update(&mut self, tick: u16, host_ack: u16, live: &[(u16, u8)]) -> Vec<(u16, u8)> {
+        let mut chests: Vec<(u16, u8)> = Vec::new();
+        let mut pending = self.sent.values().filter(|(_, sent)| net::newer(*sent, host_ack)).count();
+        for (id, hp) in live {
+            if let Some((sent_hp, sent)) = self.sent.get(id) {
+                if sent_hp == hp {
+                    if net::newer(*sent, host_ack) {
+                        chests.push((*id, *hp));
+                    }
+                    continue;
+                }
+            }
+            if pending >= MAX_CHEST_UPDATES { continue }  // the rest can wait a tick
+            self.sent.insert(*id, (*hp, tick));
+            pending += 1;
+            chests.push((*id, *hp));
+        }
+        return chests;
+    }
+}
+
+/// Everything the host keeps track of about what one client already knows
+#[derive(Default)]
+pub struct ClientView {
+    pub powerups: PowerupView,
+    pub chests: ChestView,
+    pub players: HashMap<u8, u16>,  // id -> tick they last went out, for when they don't all fit
+}
+
+/// The most recent gameplay events, numbered so clients can ack them.
+/// Each client gets everything newer than its ack until it catches up
+#[derive(Resource)]
+pub struct GameEventLog {
+    pub next_id: u16,
+    pub events: VecDeque<(u16, GameEvent)>,
+    pub recorded: u16,  // newest id that's gone into the replay recording
+}
+
+impl GameEventLog {
+    pub fn new() -> GameEventLog {
+        return GameEventLog { next_id: 1, events: VecDeque::new(), recorded: 0 };
+    }
+
+    pub fn push(&mut self, event: GameEvent) {
+        self.events.push_back((self.next_id, event));
+        self.next_id = net::next_event(self.next_id);
+        if self.events.len() > EVENT_LOG_LEN {
+            self.events.pop_front();
+        }
+    }
+
+    /// 0 if nothing has happened yet
+    pub fn newest(&self) -> u16 {
+        return self.events.back().map_or(0, |(id, _)| *id);
+    }
+
+    /// the oldest events after ack, at most MAX_GAME_EVENTS of them
+    pub fn since(&self, ack: u16) -> Vec<(u16, GameEvent)> {
+        return self.events.iter().filter(|(id, _)| net::event_newer(*id, ack)).take(MAX_GAME_EVENTS).copied().collect();
+    }
+
+    /// Some(id just before the oldest one we still have) if events after ack have already fallen out of the log
+    pub fn missed(&self, ack: u16) -> Option<u16> {
+        let oldest = self.events.front().map(|(id, _)| *id);
+        if oldest.is_none() { return None }
+        let oldest = oldest.unwrap();
+        if oldest == net::next_event(ack) || !net::event_newer(oldest, ack) { return None }
+        return Some(oldest.wrapping_sub(1));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ClientViews {
+    pub clients: HashMap<u8, ClientView>,  // by player id
+    pub recording: PowerupView,  // the recording always gets every chest
+}
+
 pub fn startup(mut commands: Commands) {
     commands.insert_resource(Connections { 0: [None; player::MAX_PLAYERS-1] });
+    commands.insert_resource(PlayerCap(player::DEFAULT_PLAYER_CAP));
+    commands.insert_resource(ClientViews::default());
+    commands.insert_resource(GameEventLog::new());
+    commands.insert_resource(Bandwidth::default());
 }
 
 pub fn connect(addresses: Res<menus::NetworkAddresses>,
+    map_config: Res<MapConfig>,
+    mut cap: ResMut<PlayerCap>,
+    mut spawn_rules: ResMut<SpawnRules>,
+    mut team_rules: ResMut<TeamRules>,
+    mut hill_rules: ResMut<HillRules>,
     mut sock: ResMut<net::Socket>
 ) {
+    cap.0 = usize::from_str(map_config.max_players.trim()).unwrap_or(player::DEFAULT_PLAYER_CAP).clamp(2, player::MAX_PLAYERS);
+    println!("hosting for up to {} players", cap.0);
+    *spawn_rules = SpawnRules::with_delay(f32::from_str(map_config.respawn_delay.trim()).unwrap_or(player::DEFAULT_RESPAWN_DELAY));
+    spawn_rules.loot_drop = u8::from_str(map_config.loot_drop.trim()).unwrap_or(player::DEFAULT_LOOT_DROP).min(100);
+    team_rules.teams = u8::from_str(map_config.teams.trim()).unwrap_or(0).min(team::MAX_TEAMS);
+    *hill_rules = HillRules::with_period(f32::from_str(map_config.hill.trim()).unwrap_or(0.));
     let host_ip = Ipv4Addr::new(0,0,0,0);
     let host_port = u16::from_str(&addresses.host_port).expect("bad host port");
     let host_addr = SocketAddr::new(IpAddr::from(host_ip), host_port);
@@ -39,97 +217,272 @@ pub fn connect(addresses: Res<menus::NetworkAddresses>,
 
 pub fn disconnect(
     mut sock: ResMut<net::Socket>,
-    mut conns: ResMut<Connections>
+    mut conns: ResMut<Connections>,
+    mut views: ResMut<ClientViews>,
+    mut bandwidth: ResMut<Bandwidth>,
+    mut log: ResMut<GameEventLog>
 ) {
     sock.0.take();
     for conn in conns.0.iter_mut() {
         conn.take();
     }
+    *views = ClientViews::default();
+    bandwidth.links.clear();
+    *log = GameEventLog::new();
+}
+
+/// keeps every gameplay event around until the clients have all had a chance to get it
+pub fn log_events(
+    mut log: ResMut<GameEventLog>,
+    mut event_reader: EventReader<GameplayEvent>,
+) {
+    for ev in event_reader.iter() {
+        log.push(ev.0);
+    }
+}
+
+/// the enemies within render distance of center, or every enemy if there's no center
+fn enemy_ticks(
+    tick: u16,
+    center: Option<Vec2>,
+    enemy_query: &Query<(&PosBuffer, &Health, &Enemy, &EventBuffer, &StatusEffects)>,
+) -> Vec<EnemyTick> {
+    let mut enemies: Vec<EnemyTick> = Vec::new();
+    for (pb, hp, en, eb, effects) in enemy_query {
+        let pos = pb.0.get(tick).unwrap();
+        if center.is_some_and(|center| pos.distance(center) >= RENDER_DISTANCE) { continue }
+        enemies.push(EnemyTick {
+            id: en.0,
+            pos,
+            hp: hp.current,
+            events: eb.0.get(tick).unwrap_or(0),
+            effects: effects.0.clone(),
+        });
+    }
+    return enemies;
+}
+
+/// the projectiles within render distance of center, or every projectile if there's no center
+fn projectile_ticks(
+    center: Option<Vec2>,
+    projectile_query: &Query<&Projectile>,
+) -> Vec<ProjectileTick> {
+    let mut projectiles: Vec<ProjectileTick> = Vec::new();
+    for proj in projectile_query {
+        if center.is_some_and(|center| proj.pos.distance(center) >= RENDER_DISTANCE) { continue }
+        projectiles.push(ProjectileTick {
+            id: proj.id,
+            kind: proj.kind,
+            pos: proj.pos,
+            dir: proj.dir,
+        });
+    }
+    return projectiles;
+}
+
+/// Trims a HostTick until it fits in one datagram and returns it encoded. Events get resent until they're acked, and
+/// everything else is sent fresh every tick, so whatever doesn't fit now goes out next time. Powerups and chests stay since
+/// they count as delivered once a newer tick is acked. Players are dropped last, us first then whoever's waited longest
+fn fit_datagram(packet: &mut HostTick, tick: u16, player_id: u8, center: Option<Vec2>, view: &ClientView) -> Vec<u8> {
+    if let Some(center) = center {
+        packet.enemies.sort_by(|a, b| a.pos.distance(center).total_cmp(&b.pos.distance(center)));
+        packet.projectiles.sort_by(|a, b| a.pos.distance(center).total_cmp(&b.pos.distance(center)));
+    }
+    // (not us, how recently they went out, how far away), so the ones that can wait longest sort last
+    let priority = |pl: &PlayerTick| (
+        pl.id != player_id,
+        view.players.get(&pl.id).map_or(0, |sent| u16::MAX - tick.wrapping_sub(*sent)),
+        center.map_or(0., |center| pl.pos.distance(center)) as u32,
+    );
+    packet.players.sort_by_key(priority);
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    while bytes.len() > MAX_DATAGRAM_SIZE {
+        let trimmed = packet.events.pop().is_some()
+            || packet.projectiles.pop().is_some()
+            || packet.enemies.pop().is_some()
+            || (packet.players.len() > 1 && packet.players.pop().is_some());
+        if !trimmed { break }
+        bytes.clear();
+        packet.to_buf(&mut bytes);
+    }
+    return bytes;
 }
 
 pub fn fixed(
     tick: Res<net::TickNum>,
-    conns: Res<Connections>,
+    fixed_time: Res<FixedTime>,
+    mut conns: ResMut<Connections>,
     sock: Res<net::Socket>,
-    player_query: Query<(&PosBuffer, &HpBuffer, &Player, &EventBuffer, &DirBuffer, &Stats, &StoredPowerUps)>,
-    enemy_query: Query<(&PosBuffer, &Health, &Enemy, &EventBuffer)>,
-    powerups_query: Query<(&PowerUp, &Transform)>,
+    mut recorder: ResMut<replay::Recorder>,
+    mut views: ResMut<ClientViews>,
+    mut log: ResMut<GameEventLog>,
+    mut bandwidth: ResMut<Bandwidth>,
+    mut grab_reader: EventReader<PowerupGrabEvent>,
+    player_query: Query<(&PosBuffer, &HpBuffer, &Player, &EventBuffer, &DirBuffer, &Stats, &StoredPowerUps, &Weapon, (&PlayerClass, &Team), &PlayerShield, &Stagger, &StatusEffects, &Hotbar, &Respawn)>,
+    enemy_query: Query<(&PosBuffer, &Health, &Enemy, &EventBuffer, &StatusEffects)>,
+    powerups_query: Query<(&NetworkId, &PowerUp, &Transform)>,
     camp_query: Query<(&Camp, &CampStatus, &CampEnemies)>,
-    chests_query: Query<(&ItemChest, &Health)>
+    chests_query: Query<(&ItemChest, &Health)>,
+    projectile_query: Query<&Projectile>,
+    hill: Res<Hill>,
 ) {
     if sock.0.is_none() { return }
     let sock = sock.0.as_ref().unwrap();
-    for conn in conns.0.iter() {
+    // everything but the enemies is the same for every connection, so only build it once
+    let mut players: Vec<PlayerTick> = Vec::new();
+    for (pb, hb, pl, eb, db, stats, powerups, weapon, (class, team), shield, stagger, effects, hotbar, respawn) in &player_query {
+        let pos = pb.0.get(tick.0);
+        // no hp yet means they haven't spawned, but everyone still has to hear they're here
+        let hp = hb.0.get(tick.0).unwrap_or(0);
+        let dir = db.0.get(tick.0);
+        let events = eb.0.get(tick.0);
+        if pos.is_none() || dir.is_none() || events.is_none() { continue }
+        let pos = pos.unwrap();
+        let dir = dir.unwrap();
+        let events = events.unwrap();
+        players.push(PlayerTick {
+            id: pl.0,
+            pos,
+            dir,
+            hp,
+            events,
+            stats: stats.clone(),
+            powerups: powerups.clone(),
+            weapon: weapon.0,
+            class: class.0,
+            team: team.0,
+            shield: shield.durability,
+            stagger: *stagger,
+            effects: effects.0.clone(),
+            hotbar: hotbar.clone(),
+            respawn: *respawn,
+        });
+    }
+    let grabs: HashMap<u16, u8> = grab_reader.iter().map(|ev| (ev.id, ev.player)).collect();
+    let mut powerups: Vec<PowerupSpawn> = Vec::new();
+    for (id, pu, pos) in &powerups_query {
+        // grabbed ones might not be despawned yet
+        if grabs.contains_key(&id.0) { continue }
+        powerups.push(PowerupSpawn { id: id.0, ptype: pu.0, pos: pos.translation.xy() });
+    }
+    let mut camps = Vec::new();
+    for (camp, status, enemies) in &camp_query {
+        if status.0 {
+            camps.push((camp.0, enemies.current_enemies));
+        }
+    }
+    let mut chests: Vec<(u16, u8)> = Vec::new();
+    for (id, hp) in &chests_query {
+        chests.push((id.id, hp.current));
+    }
+    if !recorder.is_recording() {
+        // a new recording has to start out with every powerup, but only new events
+        views.recording = PowerupView::default();
+        log.recorded = log.newest();
+    }
+    else {
+        // the recording isn't anyone's point of view, so it gets every enemy and powerup,
+        // and it never drops a packet so everything before this tick counts as acked
+        let (powerup_spawns, powerup_despawns) = views.recording.update(tick.0, tick.0.wrapping_sub(1), &powerups, &grabs, None);
+        let events = log.since(log.recorded);
+        log.recorded = events.last().map_or(log.recorded, |(id, _)| *id);
+        recorder.record(&HostTick {
+            seq_num: tick.0,
+            rmt_num: 0,
+            ack: 0,
+            echo_time: -1.,
+            recv_time: 0.,
+            host_time: clock::now(&tick, &fixed_time),
+            enemies: enemy_ticks(tick.0, None, &enemy_query),
+            players: players.clone(),
+            powerup_spawns,
+            powerup_despawns,
+            events,
+            camps: camps.clone(),
+            chests: chests.clone(),
+            projectiles: projectile_ticks(None, &projectile_query),
+            hill: *hill,
+        });
+    }
+    for conn in conns.0.iter_mut() {
         if conn.is_none() { continue; }
-        let conn = conn.unwrap();
-        for (lp_pb, _, lp_pl, _, _, _, _) in &player_query {
+        let conn = conn.as_mut().unwrap();
+        if let Some(skip_to) = log.missed(conn.event_ack) {
+            // they've been gone too long for the log to hold everything they missed, so they never get those
+            println!("player {} missed events {} to {}, they're gone from the log", conn.player_id, net::next_event(conn.event_ack), skip_to);
+            conn.event_ack = skip_to;
+        }
+        let conn = *conn;
+        for (lp_pb, _, lp_pl, _, _, _, _, _, _, _, _, _, _, _) in &player_query {
             if conn.player_id == lp_pl.0 {
-                // for "this" player, add self, then calculate who is close and add them.
+                // for "this" player, calculate which enemies are close and add them.
                 let lp_pos = lp_pb.0.get(tick.0);
-                let mut players: Vec<PlayerTick> = Vec::new();
-                for (pb, hb, pl, eb, db, stats, powerups) in &player_query {
-                    let pos = pb.0.get(tick.0);
-                    let hp = hb.0.get(tick.0);
-                    let dir = db.0.get(tick.0);
-                    let events = eb.0.get(tick.0);
-                    if pos.is_none() || hp.is_none() || dir.is_none() || events.is_none() { continue }
-                    let pos = pos.unwrap();
-                    let hp = hp.unwrap();
-                    let dir = dir.unwrap();
-                    let events = events.unwrap();
-                    players.push(PlayerTick {
-                        id: pl.0,
-                        pos,
-                        dir,
-                        hp,
-                        events,
-                        stats: stats.clone(),
-                        powerups: powerups.clone(),
-                    });
-                }
                 let mut enemies: Vec<EnemyTick> = Vec::new();
+                let mut projectiles: Vec<ProjectileTick> = Vec::new();
                 if lp_pos.is_some() {
-                    let lp_pos = lp_pos.unwrap();
-                    for (pb, hp, en, eb) in &enemy_query {
-                        let pos = pb.0.get(tick.0).unwrap();
-                        if pos.distance(lp_pos) < RENDER_DISTANCE {
-                            enemies.push(EnemyTick {
-                                id: en.0,
-                                pos,
-                                hp: hp.current,
-                                events: eb.0.get(tick.0).unwrap_or(0),
-                            });
-                        }
-                    }
-                }
-                let mut powerups: Vec<(PowerUpType, Vec2)> = Vec::new();
-                for (pu, pos) in &powerups_query {
-                    powerups.push((pu.0, pos.translation.xy()));
-                }
-                let mut camps = Vec::new();
-                for (camp, status, enemies) in &camp_query {
-                    if status.0 {
-                        camps.push((camp.0, enemies.current_enemies));
-                    }
-                }
-                let mut chests: Vec<(u8, u8)> = Vec::new();
-                for (id, hp) in &chests_query {
-                    chests.push((id.id, hp.current));
+                    enemies = enemy_ticks(tick.0, *lp_pos, &enemy_query);
+                    projectiles = projectile_ticks(*lp_pos, &projectile_query);
                 }
-                let packet = HostTick {
+                let view = views.clients.entry(conn.player_id).or_default();
+                let (powerup_spawns, powerup_despawns) = view.powerups.update(tick.0, conn.host_ack, &powerups, &grabs, *lp_pos);
+                let mut packet = HostTick {
                     seq_num: tick.0,
                     rmt_num: conn.rmt_num,
                     ack: conn.ack,
+                    echo_time: conn.client_time,
+                    recv_time: conn.recv_time,
+                    host_time: clock::now(&tick, &fixed_time),
                     enemies,
-                    players,
-                    powerups,
-                    camps,
-                    chests
+                    players: players.clone(),
+                    powerup_spawns,
+                    powerup_despawns,
+                    events: log.since(conn.event_ack),
+                    camps: camps.clone(),
+                    chests: view.chests.update(tick.0, conn.host_ack, &chests),
+                    projectiles,
+                    hill: *hill,
                 };
                 let peer = conn.addr;
-                let mut bytes: Vec<u8> = Vec::new();
-                packet.to_buf(&mut bytes);
-                send_buf(bytes.as_slice(), &sock, &peer).expect(&*format!("failed to send HostTick to {:?}", peer));
+                let budget = bandwidth.budget;
+                let link = bandwidth.links.entry(conn.player_id).or_insert_with(|| LinkStats::new(budget));
+                link.update(tick.0, budget, conn.host_ack, conn.host_ack_bits);
+                let mut bytes = fit_datagram(&mut packet, tick.0, conn.player_id, *lp_pos, view);
+                let mut throttled = false;
+                if !link.fits(bytes.len()) {
+                    // far away enemies and projectiles get sent again next tick anyway, and events keep going out until they're acked
+                    throttled = true;
+                    if let Some(lp_pos) = lp_pos {
+                        packet.enemies.retain(|en| en.pos.distance(*lp_pos) < THROTTLED_DISTANCE);
+                        packet.projectiles.retain(|proj| proj.pos.distance(*lp_pos) < THROTTLED_DISTANCE);
+                    }
+                    packet.events.clear();
+                    bytes = fit_datagram(&mut packet, tick.0, conn.player_id, *lp_pos, view);
+                }
+                if bytes.len() > MAX_DATAGRAM_SIZE {
+                    println!("HostTick for player {} is {} bytes even after trimming, skipping it", conn.player_id, bytes.len());
+                }
+                else if link.fits(bytes.len()) {
+                    if let Err(e) = send_buf(bytes.as_slice(), &sock, &peer) {
+                        println!("failed to send HostTick to {:?}: {}", peer, e);
+                    }
+                    for pl in &packet.players {
+                        view.players.insert(pl.id, tick.0);
+                    }
+                    link.spend(tick.0, bytes.len());
+                }
+                else {
+                    link.skipped += 1;
+                }
+                if link.set_throttled(throttled) {
+                    if throttled {
+                        println!("player {} throttled: loss {:.0}%, limit {:.0} B/s", conn.player_id, link.loss * 100., link.rate);
+                    }
+                    else {
+                        println!("player {} no longer throttled, {} ticks skipped", conn.player_id, link.skipped);
+                        link.skipped = 0;
+                    }
+                }
             }
         }
     }
@@ -151,19 +504,14 @@ fn get_id_of_origin(conns: &Connections, origin: &SocketAddr) -> Option<u8> {
 
 /// tries to add a connection using the given origin
 /// returns Some(player id) if successful, otherwise None
-fn add_connection(conns: &mut Connections, origin: &SocketAddr) -> Option<u8> {
-    let mut unused = [false; player::MAX_PLAYERS - 1];
-    for conn in &conns.0 {
-        if let Some(conn) = conn {
-            unused[conn.player_id as usize] = true;
-        }
-    }
-    let mut fresh_id: u8 = 1;
-    for (i, b) in unused.into_iter().enumerate() {
-        if i == fresh_id as usize && b {
-            fresh_id += 1;
-        }
+fn add_connection(conns: &mut Connections, cap: usize, origin: &SocketAddr, tick: u16, event_ack: u16) -> Option<u8> {
+    let mut used = [false; player::MAX_PLAYERS];
+    used[0] = true;  // the host
+    for conn in conns.0.iter().flatten() {
+        used[conn.player_id as usize] = true;
     }
+    // lowest free id, so players who leave make room under the cap
+    let fresh_id = used.iter().take(cap).position(|b| !b)? as u8;
     for conn in &mut conns.0 {
         if conn.is_none() {
             let _ = conn.insert(Connection {
@@ -171,6 +519,12 @@ fn add_connection(conns: &mut Connections, origin: &SocketAddr) -> Option<u8> {
                 player_id: fresh_id,
                 rmt_num: 0,
                 ack: 0,
+                host_ack: 0,
+                host_ack_bits: 0,
+                event_ack,
+                client_time: -1.,
+                recv_time: 0.,
+                last_heard: tick,
             });
             return Some(fresh_id);
         }
@@ -182,40 +536,60 @@ pub fn update(
     mut sock: ResMut<net::Socket>,
     mut conns: ResMut<Connections>,
     tick_num: Res<net::TickNum>,
+    fixed_time: Res<FixedTime>,
     mut usercmd_writer: EventWriter<UserCmdEvent>,
-    seed: Res<MapSeed>
+    mut views: ResMut<ClientViews>,
+    mut bandwidth: ResMut<Bandwidth>,
+    mut gameplay_writer: EventWriter<GameplayEvent>,
+    cap: Res<PlayerCap>,
+    log: Res<GameEventLog>,
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>
 ) {
     if sock.0.is_none() { return }
     let sock = sock.0.as_mut().unwrap();
     loop {
         let mut buf = [0; MAX_DATAGRAM_SIZE];
         if sock.peek(&mut buf).is_err() { break }
-        let (_, origin) = sock.recv_from(&mut buf).unwrap();
+        let (len, origin) = sock.recv_from(&mut buf).unwrap();
         let magic = u16::from_be_bytes(buf[0..2].try_into().unwrap());
         if magic != MAGIC_NUMBER { break; }
         let pt = u8::from_be_bytes(buf[2..3].try_into().unwrap());
         match pt {
             pt if pt == PacketType::ConnectionRequest as u8 => {
                 println!("ConnectionRequest received");
+                let request = ConnectionRequest::from_buf(&buf[3..len]);
+                if request.is_err() {
+                    println!("Malformed ConnectionRequest Received!");
+                    continue;
+                }
+                let request = request.unwrap();
                 let mut maybe_id = get_id_of_origin(&conns, &origin);
                 if maybe_id.is_some() {
                     continue;  // this user is already in the server
                 }
-                maybe_id = add_connection(&mut conns, &origin);
+                maybe_id = add_connection(&mut conns, cap.0, &origin, tick_num.0, log.newest());  // nothing from before they joined
                 if maybe_id.is_none() {
                     send_empty_packet(PacketType::ServerFull, sock, &origin).expect("cant send server full");
+                    continue;
                 }
                 let player_id = maybe_id.unwrap();
+                views.clients.remove(&player_id);  // whoever had this id before saw a different set of powerups and chests
+                bandwidth.links.remove(&player_id);  // and had a different link
+                gameplay_writer.send(GameplayEvent(GameEvent::PlayerJoined { player: player_id, class: request.class }));
                 let packet = ConnectionResponse {
                     player_id,
-                    seed: seed.0
+                    seed: seed.0,
+                    num_camps: num_camps.0
                 };
                 let mut bytes: Vec<u8> = Vec::new();
                 packet.to_buf(&mut bytes);
-                send_buf(bytes.as_slice(), sock, &origin).expect("Can't send connection response");
+                if let Err(e) = send_buf(bytes.as_slice(), sock, &origin) {
+                    println!("failed to send connection response to {:?}: {}", origin, e);
+                }
             },
             pt if pt == PacketType::ClientTick as u8 => {
-                let packet = ClientTick::from_buf(&buf[3..]);
+                let packet = ClientTick::from_buf(&buf[3..len]);
                 if packet.is_err() {
                     println!("Malformed ClientTick Received!");
                     continue;
@@ -226,6 +600,23 @@ pub fn update(
                     continue;  // ignore packets from non connected clients
                 }
                 let id = maybe_id.unwrap();
+                // remember the client's clock so the next HostTick can echo it back for clock sync.
+                // Every reading pairs up with when it got here, so the latest arrival is always usable,
+                // even when the client has just jumped its clock back and the readings go down
+                for conn in conns.0.iter_mut().flatten() {
+                    if conn.addr == origin {
+                        conn.last_heard = tick_num.0;
+                        conn.client_time = packet.client_time;
+                        conn.recv_time = clock::now(&tick_num, &fixed_time);
+                    }
+                    if conn.addr == origin && !net::newer(conn.host_ack, packet.rmt_num) {
+                        conn.host_ack = packet.rmt_num;
+                        conn.host_ack_bits = packet.ack;
+                    }
+                    if conn.addr == origin && packet.event_ack != 0 && net::event_newer(packet.event_ack, conn.event_ack) {
+                        conn.event_ack = packet.event_ack;
+                    }
+                }
                 if packet.seq_num < tick_num.0 - net::DELAY {
                     // TODO deal with packet misses
                     println!("packet late, local is {} remote is {}", tick_num.0, packet.seq_num);
@@ -239,13 +630,13 @@ pub fn update(
                 });
             },
             pt if pt == PacketType::Disconnect as u8 => {
-                // TODO make player dead
                 println!("disconnect received");
                 for conn in &mut conns.0 {
                     if conn.is_some() {
                         let s = conn.unwrap().addr;
                         if s == origin {
-                            conn.take();
+                            let player = conn.take().unwrap().player_id;
+                            gameplay_writer.send(GameplayEvent(GameEvent::PlayerLeft { player }));
                         }
                     }
                 }
@@ -253,4 +644,76 @@ pub fn update(
             _ => panic!("Bad packet sent to host")
         }
     }
+    // anyone who quit without saying so
+    for conn in &mut conns.0 {
+        if conn.is_some_and(|conn| tick_num.0.wrapping_sub(conn.last_heard) > TIMEOUT) {
+            let player = conn.take().unwrap().player_id;
+            println!("player {} timed out", player);
+            gameplay_writer.send(GameplayEvent(GameEvent::PlayerLeft { player }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log_keeps_going_past_the_wrap() {
+        let mut log = GameEventLog::new();
+        log.next_id = u16::MAX - 1;
+        for player in 0..4 {
+            log.push(GameEvent::PlayerLeft { player });
+        }
+        assert_eq!(log.newest(), 2);
+        let ids: Vec<u16> = log.since(u16::MAX - 1).iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![u16::MAX, 1, 2]);
+        assert!(log.since(2).is_empty());
+    }
+
+    #[test]
+    fn chests_only_go_out_until_acked() {
+        let mut view = ChestView::default();
+        let live = vec![(0, 20), (1, 20)];
+        assert_eq!(view.update(10, 9, &live), live);
+        assert_eq!(view.update(11, 9, &live), live);  // that one could have been lost
+        assert!(view.update(12, 10, &live).is_empty());
+        assert_eq!(view.update(13, 12, &[(0, 20), (1, 0)]), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn host_ticks_fit_in_a_datagram() {
+        let mut packet = crate::net::packets::tests::host_tick();
+        // everyone burning, poisoned and the rest is what makes a full server too big
+        let mut template = packet.players[0].clone();
+        template.effects = template.effects.repeat(4);
+        packet.players = (0..player::MAX_PLAYERS as u8).map(|id| PlayerTick { id, ..template.clone() }).collect();
+        let mut view = ClientView::default();
+        let bytes = fit_datagram(&mut packet, 700, 5, Some(Vec2::ZERO), &view);
+        assert!(bytes.len() <= MAX_DATAGRAM_SIZE, "{} bytes", bytes.len());
+        assert!(packet.players.len() < player::MAX_PLAYERS, "every player fit, this doesn't test anything");
+        assert_eq!(packet.players[0].id, 5);
+        // whoever got left out goes first next tick
+        let sent: Vec<u8> = packet.players.iter().map(|pl| pl.id).collect();
+        for id in &sent {
+            view.players.insert(*id, 700);
+        }
+        let mut packet = crate::net::packets::tests::host_tick();
+        packet.players = (0..player::MAX_PLAYERS as u8).map(|id| PlayerTick { id, ..template.clone() }).collect();
+        fit_datagram(&mut packet, 701, 5, Some(Vec2::ZERO), &view);
+        let left_out = player::MAX_PLAYERS - sent.len();
+        assert_eq!(packet.players[0].id, 5);
+        assert!(packet.players[1..=left_out].iter().all(|pl| !sent.contains(&pl.id)));
+    }
+
+    #[test]
+    fn event_log_knows_when_someone_fell_behind() {
+        let mut log = GameEventLog::new();
+        for _ in 0..EVENT_LOG_LEN + 10 {
+            log.push(GameEvent::PlayerLeft { player: 1 });
+        }
+        assert_eq!(log.missed(0), Some(10));
+        assert_eq!(log.missed(10), None);
+        assert_eq!(log.missed(log.newest()), None);
+    }
 }
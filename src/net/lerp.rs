@@ -2,28 +2,163 @@ use std::ops::Sub;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 use bevy::sprite::collide_aabb::collide;
-use crate::game::buffers::PosBuffer;
+use crate::game::buffers::{BUFFER_LEN, PosBuffer};
 use crate::game::components::{Collider, Health};
 use crate::game::map::WorldMap;
 use crate::game::movement;
 use crate::game::player::LocalPlayer;
 use crate::net;
+use crate::net::TICKLEN_S;
 
 const COLLISION_SHOVE_DIST: f32 = 4.0;
+pub const MIN_INTERP_DELAY: f32 = 1.5;  // in ticks, we always need at least one snapshot ahead of the playback point
+pub const MAX_INTERP_DELAY: f32 = 6.;
+const JITTER_DELAY_MULT: f32 = 2.;  // how many jitters of headroom to leave before a late packet becomes a stall
+const DELAY_ADJUST_RATE: f32 = 0.5;  // ticks per second the delay is allowed to drift toward its target
+const MAX_EXTRAPOLATION: f32 = 2.;  // ticks we're willing to guess past the newest snapshot
+const CLOCK_SNAP: f32 = 8.;  // ticks of error before the render clock gives up easing and just jumps
+const CLOCK_CORRECTION_RATE: f32 = 4.;  // fraction of the render clock error corrected per second
 
-pub fn lerp_pos(
+/// Smoothed playback clock in fractional ticks. It follows TickNum, but eases over re-syncs instead of jumping
+#[derive(Resource)]
+pub struct RenderClock(pub f32);
+
+/// Adaptive interpolation state for an entity whose position comes from the network.
+/// The delay grows with the jitter observed on that entity's snapshots and shrinks again when the link settles.
+#[derive(Component)]
+pub struct JitterBuffer {
+    pub jitter: f32,  // smoothed variation in transit time, seconds
+    pub delay: f32,  // current interpolation delay, ticks
+    last_transit: Option<f32>,
+}
+
+impl JitterBuffer {
+    pub fn new() -> JitterBuffer {
+        return JitterBuffer { jitter: 0., delay: MIN_INTERP_DELAY, last_transit: None };
+    }
+
+    /// record that a snapshot sampled on seq_num arrived at arrival_s (seconds since startup)
+    pub fn observe(&mut self, seq_num: u16, arrival_s: f32) {
+        let transit = arrival_s - seq_num as f32 * TICKLEN_S;
+        if let Some(last) = self.last_transit {
+            // RFC 3550 style running estimate
+            let d = (transit - last).abs();
+            self.jitter += (d - self.jitter) / 16.;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    pub fn target_delay(&self) -> f32 {
+        return (MIN_INTERP_DELAY + JITTER_DELAY_MULT * self.jitter / TICKLEN_S).clamp(MIN_INTERP_DELAY, MAX_INTERP_DELAY);
+    }
+
+    /// true once this entity has been fed by the network at least once
+    pub fn is_networked(&self) -> bool {
+        return self.last_transit.is_some();
+    }
+}
+
+pub fn update_render_clock(
+    time: Res<Time>,
     tick_time: Res<FixedTime>,
     tick: Res<net::TickNum>,
-    mut query: Query<(&mut Transform, &PosBuffer), Without<LocalPlayer>>,
+    mut clock: ResMut<RenderClock>,
+) {
+    let period = tick_time.period.as_secs_f32();
+    let target = tick.0 as f32 + tick_time.accumulated().as_secs_f32() / period;
+    clock.0 += time.delta_seconds() / period;
+    let error = target - clock.0;
+    if error.abs() > CLOCK_SNAP {
+        clock.0 = target;
+    }
+    else {
+        clock.0 += error * (CLOCK_CORRECTION_RATE * time.delta_seconds()).min(1.);
+    }
+}
+
+/// returns the position stored for tick only if it actually arrived on that tick,
+/// as opposed to being carried forward by increment_tick
+fn received(pb: &PosBuffer, tick: u16) -> Option<Vec2> {
+    let (pos, date) = pb.0.get_both(tick);
+    if date != tick { return None }
+    return *pos;
+}
+
+/// Samples an entity's position at a fractional tick. Interpolates between the nearest snapshots that
+/// really arrived, and extrapolates a little past the newest one when the next is late.
+fn sample(pb: &PosBuffer, render_tick: f32, newest: u16, networked: bool) -> Option<Vec2> {
+    if render_tick < 0. { return None }
+    let base = render_tick.floor() as u16;
+    let mut prev = None;
+    for i in 0..(BUFFER_LEN / 2) as u16 {
+        let t = base.wrapping_sub(i);
+        if let Some(p) = received(pb, t) {
+            prev = Some((t, p));
+            break;
+        }
+    }
+    let mut next = None;
+    for i in 1..(BUFFER_LEN / 2) as u16 {
+        let t = base.wrapping_add(i);
+        if t > newest { break }
+        if let Some(p) = received(pb, t) {
+            next = Some((t, p));
+            break;
+        }
+    }
+    match (prev, next) {
+        (Some((pt, pp)), Some((nt, np))) => {
+            let percent = (render_tick - pt as f32) / nt.wrapping_sub(pt) as f32;
+            return Some(pp.lerp(np, percent.clamp(0., 1.)));
+        }
+        (Some((pt, pp)), None) => {
+            // keep going along the last known velocity for a little while
+            let ahead = (render_tick - pt as f32).min(MAX_EXTRAPOLATION);
+            for i in 1..(BUFFER_LEN / 2) as u16 {
+                let t = pt.wrapping_sub(i);
+                if let Some(qp) = received(pb, t) {
+                    let vel = (pp - qp) / i as f32;
+                    return Some(pp + vel * ahead);
+                }
+            }
+            return Some(pp);
+        }
+        (None, Some((_, np))) => return Some(np),
+        (None, None) => {
+            // nothing from the network in the window. If this entity gets its state from the network we hold
+            // it where it is, otherwise it's simulated locally and the buffer is complete so we can lerp directly
+            if networked { return None }
+            let prev_state = pb.0.get(base);
+            let next_state = pb.0.get(base.wrapping_add(1));
+            if prev_state.is_none() { return None }
+            if next_state.is_none() { return *prev_state }
+            return Some(prev_state.unwrap().lerp(next_state.unwrap(), render_tick.fract()));
+        }
+    }
+}
+
+pub fn lerp_pos(
+    time: Res<Time>,
+    clock: Res<RenderClock>,
+    tick: Res<net::TickNum>,
+    mut query: Query<(&mut Transform, &PosBuffer, Option<&mut JitterBuffer>), Without<LocalPlayer>>,
 ) {
-    for (mut tf, bp) in &mut query {
-        let next_state = bp.0.get(tick.0.saturating_sub(net::DELAY));
-        let prev_state = bp.0.get(tick.0.saturating_sub(net::DELAY + 1));
-        if next_state.is_none() || prev_state.is_none() { return }
-        let next_state = next_state.unwrap();
-        let prev_state = prev_state.unwrap();
-        let percent: f32 = tick_time.accumulated().as_secs_f32() / tick_time.period.as_secs_f32();
-        let new_state = prev_state.lerp(next_state, percent);
+    for (mut tf, pb, jb) in &mut query {
+        let mut networked = false;
+        let delay = match jb {
+            Some(mut jb) => {
+                // ease toward the target so the playback point doesn't lurch with every packet
+                let step = DELAY_ADJUST_RATE * time.delta_seconds();
+                let target = jb.target_delay();
+                jb.delay += (target - jb.delay).clamp(-step, step);
+                networked = jb.is_networked();
+                jb.delay
+            }
+            None => net::DELAY as f32,
+        };
+        let new_state = sample(pb, clock.0 - delay, tick.0, networked);
+        if new_state.is_none() { continue }
+        let new_state = new_state.unwrap();
         tf.translation.x = new_state.x;
         tf.translation.y = new_state.y;
     }
@@ -1,7 +1,14 @@
+pub mod bandwidth;
+pub mod bot;
 pub mod host;
 pub mod client;
+pub mod clock;
 pub mod lerp;
 pub mod packets;
+pub mod replay;
+pub mod wire;
+#[cfg(test)]
+mod harness;
 
 use std::net::UdpSocket;
 use bevy::prelude::*;
@@ -17,7 +24,7 @@ pub const TICKRATE: u8 = 10;
 pub const TICKLEN_S: f32 = 1. / TICKRATE as f32;
 pub const DELAY: u16 = 2;
 pub const MAGIC_NUMBER: u16 = 24835; // 8008135 % 69420
-pub const MAX_DATAGRAM_SIZE: usize = 1024;
+pub const MAX_DATAGRAM_SIZE: usize = 1472;  // biggest UDP payload that fits in a 1500 byte ethernet frame
 
 #[derive(Resource)]
 pub struct TickNum(pub u16);  // this is the tick we're writing to, NOT playing back
@@ -30,8 +37,40 @@ pub struct IsHost(pub bool);
 
 #[derive(Resource)]
 pub struct Ack {
-    pub rmt_num: u16,
-    pub bitfield: u32
+    pub rmt_num: u16,  // newest remote packet we've received
+    pub bitfield: u32  // bit i is set if we received rmt_num - 1 - i
+}
+
+impl Ack {
+    pub fn receive(&mut self, seq_num: u16) {
+        if seq_num > self.rmt_num {
+            let shift = (seq_num - self.rmt_num) as u32;
+            self.bitfield = self.bitfield.checked_shl(shift).unwrap_or(0) | 1u32.checked_shl(shift - 1).unwrap_or(0);
+            self.rmt_num = seq_num;
+        }
+        else if seq_num < self.rmt_num {
+            self.bitfield |= 1u32.checked_shl((self.rmt_num - seq_num - 1) as u32).unwrap_or(0);
+        }
+    }
+}
+
+/// whether sequence number a comes after b, allowing for them wrapping around
+pub fn newer(a: u16, b: u16) -> bool {
+    return a != b && a.wrapping_sub(b) < u16::MAX / 2;
+}
+
+/// newest gameplay event id a client has handled. Ids start at 1 so 0 means none yet
+#[derive(Resource)]
+pub struct EventAck(pub u16);
+
+/// the id after this one, event ids skip 0 when they wrap
+pub fn next_event(id: u16) -> u16 {
+    return id.wrapping_add(1).max(1);
+}
+
+/// whether event id comes after ack, where an ack of 0 hasn't seen anything
+pub fn event_newer(id: u16, ack: u16) -> bool {
+    return ack == 0 || newer(id, ack);
 }
 
 pub struct NetPlugin;
@@ -39,20 +78,34 @@ pub struct NetPlugin;
 impl Plugin for NetPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Startup, (startup, host::startup))  // you cant conditionally run this unless you do a bunch of bullshit
+            .add_systems(Startup, (startup, host::startup, replay::startup))  // you cant conditionally run this unless you do a bunch of bullshit
             .add_systems(FixedUpdate,
                          (increment_tick.after(client::fixed).after(host::fixed).run_if(in_state(AppState::Game)),
                          client::fixed.run_if(is_client).after(movement::update_buffer),
                          host::fixed.run_if(is_host).after(enemy::fixed_move).after(movement::update_buffer),
-                         lerp::resolve_collisions.run_if(is_host).run_if(in_state(AppState::Game)).after(enemy::fixed_resolve).before(increment_tick)))
+                         lerp::resolve_collisions.run_if(is_host).run_if(in_state(AppState::Game)).after(enemy::fixed_resolve).before(increment_tick),
+                         replay::play.run_if(resource_exists::<replay::Replay>()).run_if(in_state(AppState::Game)).before(increment_tick)))
             .add_systems(Update,
-                         (lerp::lerp_pos.after(host::update).after(player::handle_usercmd_events).after(increment_tick),
+                         (lerp::update_render_clock.before(lerp::lerp_pos),
+                         lerp::lerp_pos.after(host::update).after(player::handle_usercmd_events).after(increment_tick),
                          client::update.run_if(is_client),
-                         host::update.run_if(is_host)))
-            .add_systems(OnEnter(AppState::Game), host::connect.run_if(is_host))
+                         clock::adjust_clock.run_if(is_client).run_if(in_state(AppState::Game)).after(client::update),
+                         host::update.run_if(is_host),
+                         host::log_events.run_if(is_host).run_if(in_state(AppState::Game)),
+                         (bandwidth::toggle_net_stats, bandwidth::update_net_stats.after(bandwidth::toggle_net_stats)).run_if(is_host).run_if(in_state(AppState::Game)),
+                         replay::toggle_recording.run_if(not(resource_exists::<replay::Replay>())).run_if(in_state(AppState::Game)),
+                         (replay::playback_controls, replay::free_camera, replay::update_replay_hud).run_if(resource_exists::<replay::Replay>()).run_if(in_state(AppState::Game))))
+            .add_systems(OnEnter(AppState::Game),
+                     (host::connect.run_if(is_host),
+                      replay::spawn_replay_hud.run_if(resource_exists::<replay::Replay>())))
+            .add_systems(OnEnter(AppState::Replay), replay::load_replay)
             .add_systems(OnExit(AppState::Game),
                      (client::disconnect.run_if(is_client),
-                      host::disconnect.run_if(is_host)))
+                      clock::reset_clock.run_if(is_client),
+                      host::disconnect.run_if(is_host),
+                      bandwidth::despawn_net_stats,
+                      replay::stop_recording,
+                      (replay::despawn_replay_hud, replay::unload_replay).run_if(resource_exists::<replay::Replay>()).after(clock::reset_clock)))
             .add_systems(OnEnter(AppState::Connecting), client::connect.run_if(is_client))
             .add_event::<EnemyTickEvent>()
             .add_event::<PlayerTickEvent>()
@@ -66,6 +119,9 @@ pub fn startup(mut commands: Commands) {
     commands.insert_resource(Socket(None));
     commands.insert_resource(IsHost(true));  // gets changed when you start the game
     commands.insert_resource(Ack { rmt_num: 0, bitfield: 0 });
+    commands.insert_resource(EventAck(0));
+    commands.insert_resource(lerp::RenderClock(0.));
+    commands.insert_resource(clock::ClockSync::new());
 }
 
 pub fn increment_tick(
@@ -1,9 +1,15 @@
 use std::io::Result;
 use std::net::{SocketAddr, UdpSocket};
 use bevy::prelude::*;
-use crate::game::components::{PowerUpType, Stats, StoredPowerUps};
-use crate::game::map::MAXCHESTS;
+use crate::game::components::{PowerUpType, Stagger, Stats, StoredPowerUps};
+use crate::game::events::{Attacker, GameEvent};
+use crate::game::hill::Hill;
+use crate::game::item::{Hotbar, ItemSlot, ItemType};
+use crate::game::player::Respawn;
+use crate::game::projectile::ProjectileKind;
+use crate::game::status::{StatusEffect, StatusType};
 use crate::net::MAGIC_NUMBER;
+use crate::net::wire::{Reader, Wire, wire_enum, wire_struct};
 
 
 pub enum PacketType {
@@ -16,11 +22,13 @@ pub enum PacketType {
 }
 
 /// sent over the network to describe an enemy
+#[derive(Clone)]
 pub struct EnemyTick {
-    pub id: u8,
+    pub id: u16,
     pub pos: Vec2,
     pub hp: u8,
-    pub events: u8
+    pub events: u8,
+    pub effects: Vec<StatusEffect>,
 }
 
 /// sent by network module to disperse enemy information from the host
@@ -30,7 +38,32 @@ pub struct EnemyTickEvent {
     pub tick: EnemyTick
 }
 
+/// a powerup appearing on the host, resent every tick until the client acks it
+#[derive(Clone)]
+pub struct PowerupSpawn {
+    pub id: u16,
+    pub ptype: PowerUpType,
+    pub pos: Vec2
+}
+
+/// a powerup going away, resent every tick until the client acks it
+#[derive(Clone)]
+pub struct PowerupDespawn {
+    pub id: u16,
+    pub grabbed_by: u8  // player id, or NO_GRABBER
+}
+
+/// sent over the network to describe something in flight
+#[derive(Clone)]
+pub struct ProjectileTick {
+    pub id: u16,
+    pub kind: ProjectileKind,
+    pub pos: Vec2,
+    pub dir: f32
+}
+
 /// sent over the network to describe a player
+#[derive(Clone)]
 pub struct PlayerTick {
     pub id: u8,
     pub pos: Vec2,
@@ -38,7 +71,15 @@ pub struct PlayerTick {
     pub dir: f32,
     pub events: u8,
     pub stats: Stats,
-    pub powerups: StoredPowerUps
+    pub powerups: StoredPowerUps,
+    pub weapon: u8,
+    pub class: u8,
+    pub team: u8,  // NO_TEAM in free-for-all
+    pub shield: u8,  // durability
+    pub stagger: Stagger,
+    pub effects: Vec<StatusEffect>,
+    pub hotbar: Hotbar,
+    pub respawn: Respawn,
 }
 
 /// sent by network module to disperse player information from the host
@@ -71,264 +112,167 @@ pub fn send_buf(buf: &[u8], local: &UdpSocket, peer: &SocketAddr) -> Result<usiz
     return local.send_to(buf, peer);
 }
 
-pub trait Packet {
-    fn from_buf(buf: &[u8]) -> Result<Self> where Self: Sized;
-    fn to_buf(&self, bytes: &mut Vec<u8>);
+/// Everything that goes over the socket with a header in front.
+/// from_buf takes the bytes after the magic number and packet type
+pub trait Packet: Wire {
+    const TYPE: PacketType;
+
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        return Self::read(&mut Reader::new(buf));
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.push(Self::TYPE as u8);
+        self.write(bytes);
+    }
 }
 
 pub struct HostTick {
     pub seq_num: u16,
     pub rmt_num: u16,
     pub ack: u32,
+    pub echo_time: f32,  // client_time of the latest ClientTick from this client
+    pub recv_time: f32,  // when the host received that ClientTick
+    pub host_time: f32,  // when this packet was sent
     pub enemies: Vec<EnemyTick>,
     pub players: Vec<PlayerTick>,
-    pub powerups: Vec<(PowerUpType, Vec2)>,
-    pub camps: Vec<(u8, u8)>,
-    pub chests: Vec<(u8, u8)>,
-}
-
-impl Packet for HostTick {
-    fn from_buf(buf: &[u8]) -> Result<Self> {
-        let mut i: usize = 0;
-        let seq_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
-        i += 2;
-        let rmt_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
-        i += 2;
-        let ack = u32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-        i += 4;
-        let enemy_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
-        i += 1;
-        let player_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
-        i += 1;
-        let powerup_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
-        i += 1;
-        let mut enemies: Vec<EnemyTick> = Vec::new();
-        for _ in 0..enemy_count {
-            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let pos = Vec2 {
-                x: f32::from_be_bytes(buf[i..i+4].try_into().unwrap()),
-                y: f32::from_be_bytes(buf[i+4..i+8].try_into().unwrap())
-            };
-            i += 8;
-            let hp = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let events = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            enemies.push(EnemyTick { id, pos, hp, events });
-        }
-        let mut players: Vec<PlayerTick> = Vec::new();
-        for _ in 0..player_count {
-            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let pos = Vec2 {
-                x: f32::from_be_bytes(buf[i..i+4].try_into().unwrap()),
-                y: f32::from_be_bytes(buf[i+4..i+8].try_into().unwrap())
-            };
-            i += 8;
-            let hp = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let dir = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-            i += 4;
-            let events = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let score = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let enemies_killed = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let players_killed = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let camps_captured = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let deaths = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let kd_ratio = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-            i += 4;
-            let meat = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let damage_dealt_up = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let damage_reduction_up = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let attack_speed_up = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let move_speed_up = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            players.push(PlayerTick { id, pos, hp, dir, events, stats: Stats {
-                score,
-                enemies_killed,
-                players_killed,
-                camps_captured,
-                deaths,
-                kd_ratio,
-            }, powerups: StoredPowerUps { power_ups: [meat, damage_dealt_up, damage_reduction_up, attack_speed_up, move_speed_up] } });
-        }
-        let mut powerups: Vec<(PowerUpType, Vec2)> = Vec::new();
-        for _ in 0..powerup_count {
-            let ptype = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let ptype = match ptype {
-                0 => PowerUpType::Meat,
-                1 => PowerUpType::DamageDealtUp,
-                2 => PowerUpType::DamageReductionUp,
-                3 => PowerUpType::AttackSpeedUp,
-                4 => PowerUpType::MovementSpeedUp,
-                _ => panic!()
-            };
-            let x = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-            i += 4;
-            let y = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-            i += 4;
-            powerups.push((ptype, Vec2 {x, y}));
-        }
-        let mut camps: Vec<(u8, u8)> = Vec::new();
-        let num_camps = u8::from_be_bytes([buf[i]].try_into().unwrap());
-        i += 1;
-        for _ in 0..num_camps {
-            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let count = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            camps.push((id, count));
-        }
-        let mut chests: Vec<(u8, u8)> = Vec::new();
-        for _ in 0..MAXCHESTS {
-            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            let hp = u8::from_be_bytes([buf[i]].try_into().unwrap());
-            i += 1;
-            chests.push((id, hp));
-        }
-        return Ok(HostTick {
-            seq_num,
-            rmt_num,
-            ack,
-            enemies,
-            players,
-            powerups,
-            camps,
-            chests
-        })
-    }
-
-    fn to_buf(&self, bytes: &mut Vec<u8>) {
-        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
-        bytes.extend_from_slice(&(PacketType::HostTick as u8).to_be_bytes());
-        bytes.extend_from_slice(&self.seq_num.to_be_bytes());
-        bytes.extend_from_slice(&self.rmt_num.to_be_bytes());
-        bytes.extend_from_slice(&self.ack.to_be_bytes());
-        bytes.extend_from_slice(&(self.enemies.len() as u8).to_be_bytes());
-        bytes.extend_from_slice(&(self.players.len() as u8).to_be_bytes());
-        bytes.extend_from_slice(&(self.powerups.len() as u8).to_be_bytes());
-        for enemy in &self.enemies {
-            bytes.extend_from_slice(&enemy.id.to_be_bytes());
-            bytes.extend_from_slice(&enemy.pos.x.to_be_bytes());
-            bytes.extend_from_slice(&enemy.pos.y.to_be_bytes());
-            bytes.extend_from_slice(&enemy.hp.to_be_bytes());
-            bytes.extend_from_slice(&enemy.events.to_be_bytes());
-        }
-        for player in &self.players {
-            bytes.extend_from_slice(&player.id.to_be_bytes());
-            bytes.extend_from_slice(&player.pos.x.to_be_bytes());
-            bytes.extend_from_slice(&player.pos.y.to_be_bytes());
-            bytes.extend_from_slice(&player.hp.to_be_bytes());
-            bytes.extend_from_slice(&player.dir.to_be_bytes());
-            bytes.extend_from_slice(&player.events.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.score.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.enemies_killed.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.players_killed.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.camps_captured.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.deaths.to_be_bytes());
-            bytes.extend_from_slice(&player.stats.kd_ratio.to_be_bytes());
-            for b in &player.powerups.power_ups {
-                bytes.extend_from_slice(&b.to_be_bytes());
-            }
-        }
-
-        for powerup in &self.powerups {
-            bytes.extend_from_slice(&(powerup.0 as u8).to_be_bytes());
-            bytes.extend_from_slice(&powerup.1.x.to_be_bytes());
-            bytes.extend_from_slice(&powerup.1.y.to_be_bytes());
-        }
-        bytes.extend_from_slice(&(self.camps.len() as u8).to_be_bytes());
-        for camp in &self.camps {
-            bytes.extend_from_slice(&camp.0.to_be_bytes());
-            bytes.extend_from_slice(&camp.1.to_be_bytes());
-        }
-        for chest in &self.chests {
-            bytes.extend_from_slice(&chest.0.to_be_bytes());
-            bytes.extend_from_slice(&chest.1.to_be_bytes());
-        }
-    }
+    pub powerup_spawns: Vec<PowerupSpawn>,
+    pub powerup_despawns: Vec<PowerupDespawn>,
+    pub events: Vec<(u16, GameEvent)>,  // (event id, event), oldest first
+    pub camps: Vec<(u16, u8)>,  // (camp id, enemies left)
+    pub chests: Vec<(u16, u8)>,  // (chest id, hp)
+    pub projectiles: Vec<ProjectileTick>,
+    pub hill: Hill,
 }
 
 pub struct ClientTick {
     pub seq_num: u16,
     pub rmt_num: u16,
     pub ack: u32,
+    pub client_time: f32,  // fractional tick on the client when this was sent, echoed back for clock sync
+    pub event_ack: u16,  // newest gameplay event id the client has handled
     pub tick: UserCmd
 }
 
-impl Packet for ClientTick {
-    fn from_buf(buf: &[u8]) -> Result<Self> {
-        let mut i: usize = 0;
-        let seq_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
-        i += 2;
-        let rmt_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
-        i += 2;
-        let ack = u32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-        i += 4;
-        let pos = Vec2{
-            x: f32::from_be_bytes(buf[i..i+4].try_into().unwrap()),
-            y: f32::from_be_bytes(buf[i+4..i+8].try_into().unwrap())
-        };
-        i += 8;
-        let dir = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
-        i += 4;
-        let events = u8::from_be_bytes([buf[i]].try_into().unwrap());
-
-        return Ok(ClientTick {
-            seq_num,
-            rmt_num,
-            ack,
-            tick: UserCmd {
-                pos,
-                dir,
-                events
-            }
-        })
-    }
-
-    fn to_buf(&self, bytes: &mut Vec<u8>) {
-        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
-        bytes.extend_from_slice(&(PacketType::ClientTick as u8).to_be_bytes());
-        bytes.extend_from_slice(&self.seq_num.to_be_bytes());
-        bytes.extend_from_slice(&self.rmt_num.to_be_bytes());
-        bytes.extend_from_slice(&self.ack.to_be_bytes());
-        bytes.extend_from_slice(&self.tick.pos.x.to_be_bytes());
-        bytes.extend_from_slice(&self.tick.pos.y.to_be_bytes());
-        bytes.extend_from_slice(&self.tick.dir.to_be_bytes());
-        bytes.extend_from_slice(&self.tick.events.to_be_bytes());
-    }
+/// the class they picked comes along with asking to join
+pub struct ConnectionRequest {
+    pub class: u8
 }
 
 pub struct ConnectionResponse {
     pub player_id: u8,
-    pub seed: u64
+    pub seed: u64,
+    pub num_camps: u16
 }
 
-impl Packet for ConnectionResponse {
-    fn from_buf(buf: &[u8]) -> Result<Self> {
-        let player_id = u8::from_be_bytes([buf[0]].try_into().unwrap());
-        let seed = u64::from_be_bytes(buf[1..9].try_into().unwrap());
-        return Ok(ConnectionResponse { player_id, seed });
-    }
+// WIRE LAYOUTS
+// fields go out in the order they're listed here
 
-    fn to_buf(&self, bytes: &mut Vec<u8>) {
-        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
-        bytes.extend_from_slice(&(PacketType::ConnectionResponse as u8).to_be_bytes());
-        bytes.extend_from_slice(&self.player_id.to_be_bytes());
-        bytes.extend_from_slice(&self.seed.to_be_bytes());
-    }
+wire_struct!(EnemyTick { id, pos, hp, events, effects });
+wire_struct!(PlayerTick { id, pos, hp, dir, events, stats, powerups, weapon, class, team, shield, stagger, effects, hotbar, respawn });
+wire_struct!(Respawn { died, delay, spawned, protection });
+wire_struct!(Hill { camp, rotates_in, holder });
+wire_struct!(Stagger { since, ticks, push });
+wire_struct!(Stats { score, enemies_killed, players_killed, assists, camps_captured, deaths, kd_ratio });
+wire_struct!(StoredPowerUps { power_ups });
+wire_struct!(PowerupSpawn { id, ptype, pos });
+wire_struct!(PowerupDespawn { id, grabbed_by });
+wire_struct!(UserCmd { pos, dir, events });
+wire_struct!(ProjectileTick { id, kind, pos, dir });
+wire_struct!(StatusEffect { kind, magnitude, expires, source });
+wire_struct!(ItemSlot { item, count });
+wire_struct!(Hotbar { slots });
+
+wire_enum!(PowerUpType {
+    0 => Meat,
+    1 => DamageDealtUp,
+    2 => DamageReductionUp,
+    3 => AttackSpeedUp,
+    4 => MovementSpeedUp,
+    5 => MaxHPUp,
+    6 => Lifesteal,
+    7 => CritChance,
+});
+
+wire_enum!(ProjectileKind {
+    0 => Arrow,
+});
+
+wire_enum!(StatusType {
+    0 => Poison,
+    1 => Burn,
+    2 => Slow,
+    3 => Haste,
+    4 => Vulnerability,
+    5 => Regeneration,
+});
+
+wire_enum!(ItemType {
+    0 => Meat,
+    1 => HealthPotion,
+    2 => SpeedTonic,
+    3 => Bomb,
+});
+
+wire_enum!(Attacker {
+    0 => Player(id),
+    1 => Enemy(id),
+});
+
+wire_enum!(GameEvent {
+    0 => EnemyKilled { enemy, killer, pos },
+    1 => ChestOpened { chest, opener },
+    2 => CampCleared { camp, player },
+    3 => CampRespawned { camp },
+    4 => PlayerKilled { victim, attacker, assists },
+    5 => PlayerJoined { player, class },
+    6 => PlayerLeft { player },
+    7 => WeaponTaken { chest, player },
+    8 => ItemTaken { chest, player },
+    9 => Exploded { pos, player },
+    10 => DashRejected { player, pos },
+    11 => SpawnRejected { player },
+});
+
+wire_struct!(HostTick {
+    seq_num,  // has to stay first, replays read it straight out of the bytes
+    rmt_num,
+    ack,
+    echo_time,
+    recv_time,
+    host_time,
+    enemies,
+    players,
+    powerup_spawns,
+    powerup_despawns,
+    events,
+    camps,
+    chests,
+    projectiles,
+    hill,
+});
+
+wire_struct!(ClientTick { seq_num, rmt_num, ack, client_time, event_ack, tick });
+
+wire_struct!(ConnectionRequest { class });
+
+wire_struct!(ConnectionResponse { player_id, seed, num_camps });
+
+impl Packet for HostTick {
+    const TYPE: PacketType = PacketType::HostTick;
+}
+
+impl Packet for ClientTick {
+    const TYPE: PacketType = PacketType::ClientTick;
+}
+
+impl Packet for ConnectionRequest {
+    const TYPE: PacketType = PacketType::ConnectionRequest;
+}
+
+impl Packet for ConnectionResponse {
+    const TYPE: PacketType = PacketType::ConnectionResponse;
 }
 
 pub fn send_empty_packet(pt: PacketType, local: &UdpSocket, peer: &SocketAddr) -> Result<usize> {
@@ -339,4 +283,150 @@ pub fn send_empty_packet(pt: PacketType, local: &UdpSocket, peer: &SocketAddr) -
         return local.send(bytes.as_slice());
     }
     return local.send_to(bytes.as_slice(), peer);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// encoding, decoding and encoding again has to give the same bytes, and any truncated copy has to fail cleanly
+    fn assert_round_trip<P: Packet>(packet: &P) {
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        assert_eq!(u16::from_be_bytes(bytes[0..2].try_into().unwrap()), MAGIC_NUMBER);
+        assert_eq!(bytes[2], P::TYPE as u8);
+        let decoded = P::from_buf(&bytes[3..]).expect("couldn't decode what we just encoded");
+        let mut again: Vec<u8> = Vec::new();
+        decoded.to_buf(&mut again);
+        assert_eq!(bytes, again);
+        for len in 3..bytes.len() {
+            assert!(P::from_buf(&bytes[3..len]).is_err(), "decoded a packet cut off at {} of {} bytes", len, bytes.len());
+        }
+    }
+
+    /// one test per packet type, each checked with assert_round_trip
+    macro_rules! round_trip_tests {
+        ($($test:ident => $packet:expr),* $(,)?) => {
+            $(
+                #[test]
+                fn $test() {
+                    assert_round_trip(&$packet);
+                }
+            )*
+        };
+    }
+
+    fn stats() -> Stats {
+        return Stats { score: 300, enemies_killed: 3, players_killed: 1, assists: 6, camps_captured: 2, deaths: 4, kd_ratio: 0.25 };
+    }
+
+    pub(crate) fn host_tick() -> HostTick {
+        return HostTick {
+            seq_num: 700,
+            rmt_num: 698,
+            ack: 0xDEADBEEF,
+            echo_time: 697.5,
+            recv_time: 699.25,
+            host_time: 700.1,
+            enemies: (0..300).map(|id| EnemyTick { id, pos: Vec2::new(id as f32, -1.), hp: 80, events: 2, effects: Vec::new() }).collect(),
+            players: vec![PlayerTick {
+                id: 3,
+                pos: Vec2::new(16., 32.),
+                hp: 55,
+                dir: 1.5,
+                events: 5,
+                stats: stats(),
+                powerups: StoredPowerUps { power_ups: [1, 2, 3, 4, 5, 6, 7, 8] },
+                weapon: 2,
+                class: 1,
+                team: 3,
+                shield: 60,
+                stagger: Stagger { since: 1000, ticks: 3, push: Vec2::new(-20., 5.) },
+                effects: vec![
+                    StatusEffect { kind: StatusType::Burn, magnitude: 6, expires: 1030, source: Attacker::Enemy(513) },
+                    StatusEffect { kind: StatusType::Regeneration, magnitude: 5, expires: 65535, source: Attacker::Player(3) },
+                ],
+                hotbar: Hotbar { slots: [
+                    ItemSlot { item: ItemType::Bomb, count: 2 },
+                    ItemSlot::default(),
+                    ItemSlot { item: ItemType::Meat, count: 5 },
+                ] },
+                respawn: Respawn { died: 650, delay: 50, spawned: 690, protection: 30 },
+            }],
+            powerup_spawns: vec![PowerupSpawn { id: 9000, ptype: PowerUpType::CritChance, pos: Vec2::new(-8., 8.) }],
+            powerup_despawns: vec![PowerupDespawn { id: 8999, grabbed_by: 2 }],
+            events: vec![
+                (1, GameEvent::EnemyKilled { enemy: 299, killer: 1, pos: Vec2::new(3., 4.) }),
+                (2, GameEvent::ChestOpened { chest: 11, opener: 0 }),
+                (3, GameEvent::CampCleared { camp: 260, player: 2 }),
+                (4, GameEvent::CampRespawned { camp: 7 }),
+                (5, GameEvent::PlayerKilled { victim: 1, attacker: Attacker::Player(2), assists: 0b1001 }),
+                (6, GameEvent::PlayerKilled { victim: 2, attacker: Attacker::Enemy(513), assists: 1 << 15 }),
+                (7, GameEvent::PlayerJoined { player: 11, class: 2 }),
+                (8, GameEvent::PlayerLeft { player: 4 }),
+                (9, GameEvent::WeaponTaken { chest: 300, player: 5 }),
+                (10, GameEvent::ItemTaken { chest: 12, player: 1 }),
+                (11, GameEvent::Exploded { pos: Vec2::new(-64., 96.), player: 3 }),
+                (12, GameEvent::DashRejected { player: 2, pos: Vec2::new(30., -45.) }),
+                (13, GameEvent::SpawnRejected { player: 1 }),
+            ],
+            camps: vec![(0, 5), (300, 1)],
+            // more chests than the map makes by default, these used to always be read as exactly MAXCHESTS
+            chests: (0..12).map(|id| (id, (id % 2) as u8)).collect(),
+            projectiles: vec![ProjectileTick { id: 65535, kind: ProjectileKind::Arrow, pos: Vec2::new(40., -20.), dir: 0.75 }],
+            hill: Hill { camp: 12, rotates_in: 299, holder: 3 },
+        };
+    }
+
+    round_trip_tests! {
+        host_tick_round_trip => host_tick(),
+        empty_host_tick_round_trip => HostTick {
+            enemies: Vec::new(),
+            players: Vec::new(),
+            powerup_spawns: Vec::new(),
+            powerup_despawns: Vec::new(),
+            events: Vec::new(),
+            camps: Vec::new(),
+            chests: Vec::new(),
+            projectiles: Vec::new(),
+            ..host_tick()
+        },
+        client_tick_round_trip => ClientTick {
+            seq_num: 41,
+            rmt_num: 40,
+            ack: 7,
+            client_time: 41.3,
+            event_ack: 65535,
+            tick: UserCmd { pos: Vec2::new(-100., 250.), dir: -3.1, events: 3 },
+        },
+        connection_request_round_trip => ConnectionRequest { class: 2 },
+        connection_response_round_trip => ConnectionResponse { player_id: 11, seed: u64::MAX, num_camps: 400 },
+    }
+
+    #[test]
+    fn host_tick_fields_survive() {
+        let mut bytes: Vec<u8> = Vec::new();
+        host_tick().to_buf(&mut bytes);
+        let decoded = HostTick::from_buf(&bytes[3..]).unwrap();
+        assert_eq!(decoded.seq_num, 700);
+        assert_eq!(decoded.enemies.len(), 300);
+        assert_eq!(decoded.enemies[299].id, 299);
+        assert!(decoded.players[0].powerups == StoredPowerUps { power_ups: [1, 2, 3, 4, 5, 6, 7, 8] });
+        assert_eq!(decoded.players[0].weapon, 2);
+        assert_eq!(decoded.players[0].class, 1);
+        assert_eq!(decoded.players[0].team, 3);
+        assert_eq!(decoded.players[0].shield, 60);
+        assert_eq!(decoded.players[0].stagger, Stagger { since: 1000, ticks: 3, push: Vec2::new(-20., 5.) });
+        assert_eq!(decoded.players[0].effects[1].kind, StatusType::Regeneration);
+        assert_eq!(decoded.players[0].effects[1].source, Attacker::Player(3));
+        assert_eq!(decoded.players[0].hotbar.peek(0), Some(ItemType::Bomb));
+        assert_eq!(decoded.players[0].respawn, Respawn { died: 650, delay: 50, spawned: 690, protection: 30 });
+        assert_eq!(decoded.players[0].hotbar.slots[2], ItemSlot { item: ItemType::Meat, count: 5 });
+        assert_eq!(decoded.powerup_spawns[0].ptype, PowerUpType::CritChance);
+        assert_eq!(decoded.events[5], (6, GameEvent::PlayerKilled { victim: 2, attacker: Attacker::Enemy(513), assists: 1 << 15 }));
+        assert_eq!(decoded.camps, vec![(0, 5), (300, 1)]);
+        assert_eq!(decoded.chests.len(), 12);
+        assert_eq!(decoded.projectiles[0].id, 65535);
+        assert_eq!(decoded.projectiles[0].kind, ProjectileKind::Arrow);
+        assert_eq!(decoded.hill, Hill { camp: 12, rotates_in: 299, holder: 3 });
+    }
+}
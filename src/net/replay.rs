@@ -0,0 +1,349 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::buffers::{CircularBuffer, PosBuffer};
+use crate::game::camera::SpatialCameraBundle;
+use crate::game::map::{MapSeed, NumCamps};
+use crate::game::PlayerId;
+use crate::net::{IsHost, MAGIC_NUMBER, TICKLEN_S, TickNum, TICKRATE};
+use crate::net::client::HostTickApplier;
+use crate::net::lerp::JitterBuffer;
+use crate::net::packets::{HostTick, Packet};
+
+pub const REPLAY_DIR: &str = "replays";
+pub const REPLAY_VERSION: u8 = 18;
+pub const RECORD_KEY: KeyCode = KeyCode::F9;
+const SEEK_TICKS: u16 = 10 * TICKRATE as u16;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.;
+const FREE_CAMERA_SPEED: f32 = 600.;
+
+/// Writes every HostTick we see to a replay file while it's open.
+/// File layout: magic (u16), version (u8), map seed (u64), camp count (u16), recording player id (u8),
+/// then one (length u16, HostTick bytes) frame per tick.
+#[derive(Resource)]
+pub struct Recorder {
+    file: Option<BufWriter<File>>,
+    frames: u32,  // written since it started
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        return self.file.is_some();
+    }
+
+    /// the first frame is all a replay has to go on for anything that's only sent when it changes
+    pub fn needs_snapshot(&self) -> bool {
+        return self.file.is_some() && self.frames == 0;
+    }
+
+    pub fn start(&mut self, seed: u64, num_camps: u16, player_id: u8) -> std::io::Result<PathBuf> {
+        fs::create_dir_all(REPLAY_DIR)?;
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = PathBuf::from(REPLAY_DIR).join(format!("{}.jqr", stamp));
+        let mut file = BufWriter::new(File::create(&path)?);
+        file.write_all(&MAGIC_NUMBER.to_be_bytes())?;
+        file.write_all(&REPLAY_VERSION.to_be_bytes())?;
+        file.write_all(&seed.to_be_bytes())?;
+        file.write_all(&num_camps.to_be_bytes())?;
+        file.write_all(&player_id.to_be_bytes())?;
+        self.file = Some(file);
+        self.frames = 0;
+        return Ok(path);
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut file) = self.file.take() {
+            let _ = file.flush();
+        }
+    }
+
+    pub fn record(&mut self, packet: &HostTick) {
+        if self.file.is_none() { return }
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        self.record_raw(&bytes[3..]);  // skip magic and packet type
+    }
+
+    /// bytes is a HostTick without the magic number and packet type in front
+    pub fn record_raw(&mut self, bytes: &[u8]) {
+        if self.file.is_none() { return }
+        let file = self.file.as_mut().unwrap();
+        let res = file.write_all(&(bytes.len() as u16).to_be_bytes())
+            .and_then(|_| file.write_all(bytes));
+        self.frames += 1;
+        if res.is_err() {
+            println!("replay write failed, stopping recording");
+            self.stop();
+        }
+    }
+}
+
+/// a loaded replay being played back. Only exists while watching one
+#[derive(Resource)]
+pub struct Replay {
+    pub seed: u64,
+    pub num_camps: u16,
+    pub frames: Vec<(u16, Vec<u8>)>,  // (seq_num, HostTick bytes)
+    pub cursor: usize,  // next frame to play
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Replay {
+    pub fn from_bytes(buf: &[u8]) -> Option<Replay> {
+        if buf.len() < 14 { return None }
+        if u16::from_be_bytes(buf[0..2].try_into().unwrap()) != MAGIC_NUMBER { return None }
+        if buf[2] != REPLAY_VERSION { return None }
+        let seed = u64::from_be_bytes(buf[3..11].try_into().unwrap());
+        let num_camps = u16::from_be_bytes(buf[11..13].try_into().unwrap());
+        let mut frames = Vec::new();
+        let mut i = 14;
+        while i + 2 <= buf.len() {
+            let len = u16::from_be_bytes(buf[i..i+2].try_into().unwrap()) as usize;
+            i += 2;
+            if i + len > buf.len() || len < 2 { break }  // truncated, probably the game closed mid-write
+            let seq_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
+            frames.push((seq_num, buf[i..i+len].to_vec()));
+            i += len;
+        }
+        if frames.is_empty() { return None }
+        return Some(Replay { seed, num_camps, frames, cursor: 0, speed: 1., paused: false });
+    }
+
+    pub fn first_tick(&self) -> u16 {
+        return self.frames[0].0;
+    }
+
+    pub fn last_tick(&self) -> u16 {
+        return self.frames[self.frames.len() - 1].0;
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayHud;
+
+pub fn startup(mut commands: Commands) {
+    commands.insert_resource(Recorder { file: None, frames: 0 });
+}
+
+fn newest_replay() -> Option<PathBuf> {
+    let entries = fs::read_dir(REPLAY_DIR).ok()?;
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "jqr") {
+            let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(UNIX_EPOCH);
+            if newest.is_none() || modified > newest.as_ref().unwrap().0 {
+                newest = Some((modified, path));
+            }
+        }
+    }
+    return newest.map(|(_, path)| path);
+}
+
+/// OnEnter(AppState::Replay): load the newest replay and set up the game to watch it like a client without a socket
+pub fn load_replay(
+    mut commands: Commands,
+    mut is_host: ResMut<IsHost>,
+    mut res_id: ResMut<PlayerId>,
+    mut seed: ResMut<MapSeed>,
+    mut num_camps: ResMut<NumCamps>,
+    mut tick: ResMut<TickNum>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let replay = newest_replay()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|buf| Replay::from_bytes(&buf));
+    if replay.is_none() {
+        println!("no readable replays in {}/", REPLAY_DIR);
+        app_state_next_state.set(AppState::MainMenu);
+        return;
+    }
+    let replay = replay.unwrap();
+    is_host.0 = false;
+    res_id.0 = 0xFF;  // nobody is the local player, the camera is free
+    seed.0 = replay.seed;
+    num_camps.0 = replay.num_camps;
+    tick.0 = replay.first_tick();
+    commands.insert_resource(replay);
+    app_state_next_state.set(AppState::Game);
+}
+
+pub fn unload_replay(mut commands: Commands, mut fixed_time: ResMut<FixedTime>) {
+    commands.remove_resource::<Replay>();
+    *fixed_time = FixedTime::new_from_secs(TICKLEN_S);  // drop anything accumulated while paused
+}
+
+/// F9 starts and stops recording during a match
+pub fn toggle_recording(
+    input: Res<Input<KeyCode>>,
+    mut recorder: ResMut<Recorder>,
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>,
+    res_id: Res<PlayerId>,
+) {
+    if !input.just_pressed(RECORD_KEY) { return }
+    if recorder.is_recording() {
+        recorder.stop();
+        println!("replay recording stopped");
+        return;
+    }
+    match recorder.start(seed.0, num_camps.0, res_id.0) {
+        Ok(path) => println!("recording replay to {:?}", path),
+        Err(e) => println!("couldn't start replay recording: {}", e),
+    }
+}
+
+pub fn stop_recording(mut recorder: ResMut<Recorder>) {
+    recorder.stop();
+}
+
+/// Runs on FixedUpdate before increment_tick, feeding every frame up to the current tick into the client pipeline
+pub fn play(
+    mut replay: ResMut<Replay>,
+    tick: Res<TickNum>,
+    mut applier: HostTickApplier,
+) {
+    while replay.cursor < replay.frames.len() && replay.frames[replay.cursor].0 <= tick.0 {
+        let packet = HostTick::from_buf(&replay.frames[replay.cursor].1);
+        replay.cursor += 1;
+        if packet.is_err() { continue }
+        applier.apply(packet.unwrap());
+    }
+}
+
+fn set_speed(fixed_time: &mut FixedTime, replay: &Replay) {
+    if replay.paused {
+        // no ticks at all until we unpause
+        *fixed_time = FixedTime::new(Duration::from_secs(3600));
+    }
+    else {
+        *fixed_time = FixedTime::new_from_secs(TICKLEN_S / replay.speed);
+    }
+}
+
+/// Space pauses, up/down change speed, left/right seek
+pub fn playback_controls(
+    input: Res<Input<KeyCode>>,
+    mut replay: ResMut<Replay>,
+    mut tick: ResMut<TickNum>,
+    mut fixed_time: ResMut<FixedTime>,
+    mut applier: HostTickApplier,
+    mut buffers: Query<(&Transform, &mut PosBuffer, Option<&mut JitterBuffer>)>,
+) {
+    if input.just_pressed(KeyCode::Space) {
+        replay.paused = !replay.paused;
+        set_speed(&mut fixed_time, &replay);
+    }
+    if input.just_pressed(KeyCode::Up) {
+        replay.speed = (replay.speed * 2.).min(MAX_SPEED);
+        set_speed(&mut fixed_time, &replay);
+    }
+    if input.just_pressed(KeyCode::Down) {
+        replay.speed = (replay.speed / 2.).max(MIN_SPEED);
+        set_speed(&mut fixed_time, &replay);
+    }
+    let mut target = None;
+    if input.just_pressed(KeyCode::Left) {
+        target = Some(tick.0.saturating_sub(SEEK_TICKS).max(replay.first_tick()));
+    }
+    if input.just_pressed(KeyCode::Right) {
+        target = Some(tick.0.saturating_add(SEEK_TICKS).min(replay.last_tick()));
+    }
+    if target.is_none() { return }
+    let target = target.unwrap();
+    tick.0 = target;
+    // play from the last frame at or before the target so the world is complete straight away
+    let after = replay.frames.partition_point(|(seq, _)| *seq <= target);
+    replay.cursor = after.saturating_sub(1);
+    // powerups are only sent when they appear and disappear, and chests when they change, so rebuild them from the start of the recording
+    applier.clear_powerups();
+    applier.clear_projectiles();
+    applier.reset_events();
+    for (_, bytes) in &replay.frames[..replay.cursor] {
+        if let Ok(packet) = HostTick::from_buf(bytes) {
+            applier.apply_powerups(&packet.powerup_spawns, &packet.powerup_despawns, false);
+            applier.apply_chests(&packet.chests);
+        }
+    }
+    // the position buffers are full of snapshots from the wrong time, start them over from where things are now
+    for (tf, mut pb, jb) in &mut buffers {
+        pb.0 = CircularBuffer::new_from(Some(tf.translation.xy()));
+        if let Some(mut jb) = jb {
+            *jb = JitterBuffer::new();
+        }
+    }
+}
+
+/// with no local player the camera is moved with WASD or the arrow keys
+pub fn free_camera(
+    input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, With<SpatialCameraBundle>>,
+) {
+    let mut dir = Vec2::ZERO;
+    if input.pressed(KeyCode::W) { dir.y += 1. }
+    if input.pressed(KeyCode::S) { dir.y -= 1. }
+    if input.pressed(KeyCode::A) { dir.x -= 1. }
+    if input.pressed(KeyCode::D) { dir.x += 1. }
+    if dir == Vec2::ZERO { return }
+    let movement = dir.normalize() * FREE_CAMERA_SPEED * time.delta_seconds();
+    for mut tf in &mut cameras {
+        tf.translation.x += movement.x;
+        tf.translation.y += movement.y;
+    }
+}
+
+pub fn spawn_replay_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(20.),
+            top: Val::Px(20.),
+            ..Default::default()
+        },
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: Color::WHITE,
+            }
+        ),
+        ..Default::default()},
+        ReplayHud));
+}
+
+pub fn update_replay_hud(
+    replay: Res<Replay>,
+    tick: Res<TickNum>,
+    mut huds: Query<&mut Text, With<ReplayHud>>,
+) {
+    let elapsed = tick.0.saturating_sub(replay.first_tick()) as f32 * TICKLEN_S;
+    let length = replay.last_tick().saturating_sub(replay.first_tick()) as f32 * TICKLEN_S;
+    for mut text in &mut huds {
+        text.sections[0].value = format!("REPLAY {} {:.2}x  {:02}:{:02} / {:02}:{:02}\nSpace pause, Up/Down speed, Left/Right seek, WASD camera",
+            if replay.paused { "paused" } else { "playing" },
+            replay.speed,
+            (elapsed / 60.) as i32, (elapsed % 60.) as i32,
+            (length / 60.) as i32, (length % 60.) as i32);
+    }
+}
+
+pub fn despawn_replay_hud(
+    mut commands: Commands,
+    huds: Query<Entity, With<ReplayHud>>,
+) {
+    for e in &huds {
+        commands.entity(e).despawn_recursive();
+    }
+}
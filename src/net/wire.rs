@@ -0,0 +1,245 @@
+//! The wire format every packet is built from.
+//! A type describes its layout once with wire_struct! or wire_enum!, and both encoding and decoding come from that.
+//! Everything is big endian, lists are prefixed with a u16 length, and enum variants with a u8 tag.
+use std::io::{Error, ErrorKind, Result};
+use bevy::prelude::Vec2;
+
+/// something that can be written to and read back from a packet
+pub trait Wire: Sized {
+    fn write(&self, bytes: &mut Vec<u8>);
+    fn read(r: &mut Reader) -> Result<Self>;
+}
+
+/// walks through a received buffer, failing instead of panicking if the packet is too short
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        return Reader { buf, i: 0 };
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.i + n > self.buf.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "packet too short"));
+        }
+        let bytes = &self.buf[self.i..self.i+n];
+        self.i += n;
+        return Ok(bytes);
+    }
+}
+
+pub fn bad_data(what: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, what.to_string());
+}
+
+macro_rules! wire_number {
+    ($($t:ty),*) => {
+        $(
+            impl Wire for $t {
+                fn write(&self, bytes: &mut Vec<u8>) {
+                    bytes.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn read(r: &mut Reader) -> Result<Self> {
+                    return Ok(<$t>::from_be_bytes(r.take(std::mem::size_of::<$t>())?.try_into().unwrap()));
+                }
+            }
+        )*
+    };
+}
+
+wire_number!(u8, u16, u32, u64, f32);
+
+impl Wire for bool {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.push(*self as u8);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self> {
+        return Ok(u8::read(r)? != 0);
+    }
+}
+
+/// lists get a u16 length in front
+impl<T: Wire> Wire for Vec<T> {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        (self.len() as u16).write(bytes);
+        for item in self {
+            item.write(bytes);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self> {
+        let len = u16::read(r)?;
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(T::read(r)?);
+        }
+        return Ok(items);
+    }
+}
+
+/// arrays are always the same length so they don't need one
+impl<T: Wire, const N: usize> Wire for [T; N] {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for item in self {
+            item.write(bytes);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self> {
+        let mut items = Vec::with_capacity(N);
+        for _ in 0..N {
+            items.push(T::read(r)?);
+        }
+        return items.try_into().map_err(|_| bad_data("array length"));
+    }
+}
+
+impl<A: Wire, B: Wire> Wire for (A, B) {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.0.write(bytes);
+        self.1.write(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self> {
+        let a = A::read(r)?;
+        let b = B::read(r)?;
+        return Ok((a, b));
+    }
+}
+
+/// Describes a struct's layout as its fields in wire order.
+/// Every field has to be listed since read builds the struct out of them
+macro_rules! wire_struct {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::net::wire::Wire for $name {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                $($crate::net::wire::Wire::write(&self.$field, bytes);)*
+            }
+
+            fn read(r: &mut $crate::net::wire::Reader) -> std::io::Result<Self> {
+                $(let $field = $crate::net::wire::Wire::read(r)?;)*
+                return Ok($name { $($field),* });
+            }
+        }
+    };
+}
+
+/// Describes an enum as a u8 tag per variant followed by that variant's fields.
+/// Variants can be unit, struct-like `Name { a, b }`, or tuple-like `Name(a, b)`
+macro_rules! wire_enum {
+    ($name:ident { $($tag:literal => $variant:ident $({ $($field:ident),* })? $(( $($pos:ident),* ))?),* $(,)? }) => {
+        impl $crate::net::wire::Wire for $name {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                match self {
+                    $(
+                        $name::$variant $({ $($field),* })? $(( $($pos),* ))? => {
+                            bytes.push($tag);
+                            $($($crate::net::wire::Wire::write($field, bytes);)*)?
+                            $($($crate::net::wire::Wire::write($pos, bytes);)*)?
+                        },
+                    )*
+                }
+            }
+
+            fn read(r: &mut $crate::net::wire::Reader) -> std::io::Result<Self> {
+                let tag = <u8 as $crate::net::wire::Wire>::read(r)?;
+                match tag {
+                    $(
+                        $tag => {
+                            $($(let $field = $crate::net::wire::Wire::read(r)?;)*)?
+                            $($(let $pos = $crate::net::wire::Wire::read(r)?;)*)?
+                            return Ok($name::$variant $({ $($field),* })? $(( $($pos),* ))?);
+                        },
+                    )*
+                    _ => return Err($crate::net::wire::bad_data(concat!("unknown ", stringify!($name)))),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use wire_struct;
+pub(crate) use wire_enum;
+
+wire_struct!(Vec2 { x, y });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner {
+        a: u8,
+        b: f32,
+    }
+
+    wire_struct!(Inner { a, b });
+
+    struct Outer {
+        id: u16,
+        inner: Inner,
+        list: Vec<(u16, u8)>,
+        fixed: [u8; 3],
+    }
+
+    wire_struct!(Outer { id, inner, list, fixed });
+
+    enum Shape {
+        Empty,
+        Circle { r: f32 },
+        Pair(u8, u16),
+    }
+
+    wire_enum!(Shape {
+        0 => Empty,
+        1 => Circle { r },
+        2 => Pair(a, b),
+    });
+
+    fn encode<T: Wire>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value.write(&mut bytes);
+        return bytes;
+    }
+
+    #[test]
+    fn nested_struct_round_trip() {
+        let outer = Outer {
+            id: 513,
+            inner: Inner { a: 7, b: -1.5 },
+            list: vec![(1, 2), (300, 4)],
+            fixed: [9, 8, 7],
+        };
+        let bytes = encode(&outer);
+        // u16 + (u8 + f32) + (u16 len + 2 * (u16 + u8)) + 3 * u8
+        assert_eq!(bytes.len(), 2 + 5 + 2 + 6 + 3);
+        let back = Outer::read(&mut Reader::new(&bytes)).unwrap();
+        assert_eq!(back.id, 513);
+        assert_eq!(back.inner.a, 7);
+        assert_eq!(back.inner.b, -1.5);
+        assert_eq!(back.list, vec![(1, 2), (300, 4)]);
+        assert_eq!(back.fixed, [9, 8, 7]);
+    }
+
+    #[test]
+    fn enum_round_trip() {
+        for shape in [Shape::Empty, Shape::Circle { r: 2.5 }, Shape::Pair(3, 1000)] {
+            let bytes = encode(&shape);
+            let back = Shape::read(&mut Reader::new(&bytes)).unwrap();
+            assert_eq!(encode(&back), bytes);
+        }
+        assert!(Shape::read(&mut Reader::new(&[9])).is_err());
+    }
+
+    #[test]
+    fn short_buffer_is_an_error() {
+        let bytes = encode(&vec![1u16, 2, 3]);
+        for len in 0..bytes.len() {
+            assert!(Vec::<u16>::read(&mut Reader::new(&bytes[..len])).is_err());
+        }
+    }
+}